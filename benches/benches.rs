@@ -1,18 +1,13 @@
 use criterion::{criterion_main, criterion_group, Criterion};
 use std::hint::black_box;
 
-use easypls::expr::Expr;
+use easypls::cnf::CNF;
+use easypls::expr::{Backend, CnfEncoding, Expr};
+use easypls::runtime::{batch::BatchVM, env::Env, vm::VM};
 
-pub fn small_sat() {
-    let prop = "not (((a or (b and c) <-> d) xor a or (b and c) <-> d) nand e) or not (not f)";
+const SMALL: &str = "not (((a or (b and c) <-> d) xor a or (b and c) <-> d) nand e) or not (not f)";
 
-    let expr = Expr::parse(prop.as_bytes()).unwrap();
-    let mut cnf = expr.tseitin(false);
-    let _ = cnf.find_evidence();
-}
-
-pub fn large_sat() {
-    let prop = "not (
+const LARGE: &str = "not (
   (
     (
       (a or (b and c) <-> d)
@@ -86,18 +81,89 @@ not (not f)
  
 ";
 
-    let mut cnf = Expr::parse(prop.as_bytes()).unwrap().tseitin(false);
-    let _ = cnf.find_evidence();
+pub fn solve(prop: &str, encoding: CnfEncoding) -> Option<Vec<bool>> {
+    let expr = Expr::parse(prop.as_bytes()).unwrap();
+    let cnf = expr.encode(encoding);
+    cnf.find_evidence()
 }
 
 pub fn small_sat_bench(c: &mut Criterion) {
-    c.bench_function("small_sat", |b| b.iter(|| black_box(small_sat())));
+    c.bench_function("small_sat", |b| b.iter(|| black_box(solve(SMALL, CnfEncoding::Tseitin))));
+    c.bench_function("small_sat_pg", |b| b.iter(|| black_box(solve(SMALL, CnfEncoding::PlaistedGreenbaum))));
 }
 
 pub fn large_sat_bench(c: &mut Criterion) {
-    c.bench_function("large_sat", |b| b.iter(|| black_box(large_sat())));
+    c.bench_function("large_sat", |b| b.iter(|| black_box(solve(LARGE, CnfEncoding::Tseitin))));
+    c.bench_function("large_sat_pg", |b| b.iter(|| black_box(solve(LARGE, CnfEncoding::PlaistedGreenbaum))));
+}
+
+// Deterministic random 3-SAT instance
+fn random_3sat(num_vars: usize, num_clauses: usize, seed: u64) -> CNF {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 33) as usize
+    };
+    let clauses = (0..num_clauses)
+        .map(|_| (0..3).map(|_| {
+            let id = (next() % num_vars + 1) as isize;
+            if next() % 2 == 0 { id } else { -id }
+        }).collect())
+        .collect();
+    CNF::new((0..num_vars).map(|idx| format!("x{idx}")).collect(), clauses)
 }
 
+// Four random 3-SAT instances each, 200 variables at the 4.26 clauses per variable where they are hardest,
+// and 300 variables at 4 clauses per variable
+pub fn random_3sat_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("random_3sat");
+    group.sample_size(10);
+    for (num_vars, num_clauses) in [(200, 852), (300, 1200)] {
+        let instances = (0..4).map(|seed| random_3sat(num_vars, num_clauses, seed)).collect::<Vec<_>>();
+        group.bench_function(format!("{num_vars}_vars"), |b| b.iter(|| {
+            instances.iter().map(|cnf| black_box(cnf.find_evidence())).filter(Option::is_some).count()
+        }));
+    }
+    group.finish();
+}
+
+// De Morgan, distributivity and absorption checked one after another
+const EQUIVALENCES: [(&str, &str); 3] = [
+    ("not (a or b)", "not a and not b"),
+    ("a and (b or c)", "a and b or a and c"),
+    ("a or a and b", "a"),
+];
+
+pub fn equivalence_bench(c: &mut Criterion) {
+    let pairs = EQUIVALENCES.map(|(l, r)| (Expr::parse(l.as_bytes()).unwrap(), Expr::parse(r.as_bytes()).unwrap()));
+    for (name, backend) in [("equivalence_sat", Backend::Sat), ("equivalence_bdd", Backend::Bdd)] {
+        c.bench_function(name, |b| b.iter(|| {
+            pairs.iter().all(|(l, r)| black_box(l.is_equivalent(r, backend)))
+        }));
+    }
+}
+
+// Every assignment of 16 variables, one at a time against 64 at a time
+pub fn truth_table_bench(c: &mut Criterion) {
+    let expr = (0..8)
+        .map(|idx| Expr::parse(format!("(x{idx} and not y{idx} -> x{}) xor y{idx}", (idx + 1) % 8).as_bytes()).unwrap())
+        .reduce(Expr::or)
+        .unwrap();
+    let names = expr.get_variables();
+    c.bench_function("truth_table_vm", |b| b.iter(|| {
+        let mut env = Env::new();
+        (0..1usize << names.len()).filter(|idx| {
+            for (var, name) in names.iter().enumerate() {
+                env.define(name.clone(), idx >> var & 1 == 1);
+            }
+            VM::new(&env, expr.compile()).eval().unwrap()
+        }).count()
+    }));
+    c.bench_function("truth_table_batch", |b| b.iter(|| {
+        let words = BatchVM::new(expr.compile()).all_assignments(&names).unwrap();
+        words.iter().map(|word| word.count_ones()).sum::<u32>()
+    }));
+}
 
-criterion_group!(benches, small_sat_bench, large_sat_bench);
+criterion_group!(benches, small_sat_bench, large_sat_bench, random_3sat_bench, equivalence_bench, truth_table_bench);
 criterion_main!(benches);
@@ -0,0 +1,538 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::bignum::BigUint;
+use crate::card::Comparison;
+use crate::dag::Node;
+use crate::expr::Expr;
+use crate::pb::LinearBdd;
+
+// Handle of a BDD node, only meaningful for the Bdd that made it
+// Two functions are equivalent exactly when they have the same handle
+pub type BddRef = usize;
+
+pub const FALSE: BddRef = 0;
+pub const TRUE: BddRef = 1;
+
+// Terminals sit below every variable
+const TERMINAL_LEVEL: usize = usize::MAX;
+
+// Sifting stops moving a variable in a direction once the roots grow past this many times their size
+const SIFT_MAX_GROWTH: usize = 2;
+
+// Binary operators for Bdd::apply
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BddOp {
+    And,
+    Or,
+    Xor,
+    Iff,
+    Implies,
+    Nand,
+    Nor,
+}
+
+impl FromStr for BddOp {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<BddOp, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "and" => Ok(BddOp::And),
+            "or" => Ok(BddOp::Or),
+            "xor" => Ok(BddOp::Xor),
+            "iff" | "<->" => Ok(BddOp::Iff),
+            "implies" | "->" => Ok(BddOp::Implies),
+            "nand" => Ok(BddOp::Nand),
+            "nor" => Ok(BddOp::Nor),
+            _ => Err(format!("Unknown operator '{}', expected 'and', 'or', 'xor', 'iff', 'implies', 'nand' or 'nor'", name)),
+        }
+    }
+}
+
+// Decision node testing the variable at level, with the child for false and for true
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct BddNode {
+    level: usize,
+    low: BddRef,
+    high: BddRef,
+}
+
+// Reduced ordered binary decision diagrams sharing one node table
+// Nodes are only freed by collect, which keeps what the given roots need
+#[derive(Clone, Debug)]
+pub struct Bdd {
+    // Variable tested at each level, from the root down
+    order: Vec<String>,
+    levels: HashMap<String, usize>,
+
+    nodes: Vec<BddNode>,
+    unique: HashMap<BddNode, BddRef>,
+    ite_cache: HashMap<(BddRef, BddRef, BddRef), BddRef>,
+
+    // Table size past which make gives up, and whether it did, see build_within
+    node_limit: usize,
+    gave_up: bool,
+}
+
+impl Bdd {
+    // Manager whose variables are tested in the given order, new variables go below the existing ones
+    pub fn new(order: Vec<String>) -> Bdd {
+        let terminal = BddNode { level: TERMINAL_LEVEL, low: FALSE, high: FALSE };
+        let mut bdd = Bdd {
+            order: Vec::new(),
+            levels: HashMap::new(),
+            nodes: vec![terminal, BddNode { high: TRUE, low: TRUE, ..terminal }],
+            unique: HashMap::new(),
+            ite_cache: HashMap::new(),
+            node_limit: usize::MAX,
+            gave_up: false,
+        };
+        for name in order {
+            bdd.level_of(&name);
+        }
+        bdd
+    }
+
+    pub fn order(&self) -> &[String] {
+        &self.order
+    }
+
+    // Whether f is a handle of this manager
+    pub fn contains(&self, f: BddRef) -> bool {
+        f < self.nodes.len()
+    }
+
+    // Nodes in the table, including the terminals and any no root needs anymore
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn level_of(&mut self, name: &str) -> usize {
+        if let Some(level) = self.levels.get(name) {
+            return *level;
+        }
+        self.order.push(name.to_string());
+        self.levels.insert(name.to_string(), self.order.len() - 1);
+        self.order.len() - 1
+    }
+
+    fn level(&self, f: BddRef) -> usize {
+        self.nodes[f].level
+    }
+
+    // The node for level with the given children, shared with any equal node
+    fn make(&mut self, level: usize, low: BddRef, high: BddRef) -> BddRef {
+        if low == high {
+            return low;
+        }
+        let node = BddNode { level, low, high };
+        if let Some(f) = self.unique.get(&node) {
+            return *f;
+        }
+        // The result is thrown away by build_within, so anything will do
+        if self.nodes.len() >= self.node_limit {
+            self.gave_up = true;
+            return FALSE;
+        }
+        self.nodes.push(node);
+        self.unique.insert(node, self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    // Child of f for the variable at level having value, f itself if it doesn't test that variable
+    fn cofactor(&self, f: BddRef, level: usize, value: bool) -> BddRef {
+        let node = self.nodes[f];
+        match (node.level == level, value) {
+            (false, _) => f,
+            (true, false) => node.low,
+            (true, true) => node.high,
+        }
+    }
+
+    pub fn var(&mut self, name: &str) -> BddRef {
+        let level = self.level_of(name);
+        self.make(level, FALSE, TRUE)
+    }
+
+    // If f then g else h
+    pub fn ite(&mut self, f: BddRef, g: BddRef, h: BddRef) -> BddRef {
+        match (f, g, h) {
+            (TRUE, _, _) => return g,
+            (FALSE, _, _) => return h,
+            _ if g == h => return g,
+            (_, TRUE, FALSE) => return f,
+            _ => {}
+        }
+        if let Some(result) = self.ite_cache.get(&(f, g, h)) {
+            return *result;
+        }
+        if self.gave_up {
+            return FALSE;
+        }
+
+        let level = self.level(f).min(self.level(g)).min(self.level(h));
+        let [low, high] = [false, true].map(|value| {
+            let (f, g, h) = (self.cofactor(f, level, value), self.cofactor(g, level, value), self.cofactor(h, level, value));
+            self.ite(f, g, h)
+        });
+        let result = self.make(level, low, high);
+        self.ite_cache.insert((f, g, h), result);
+        result
+    }
+
+    pub fn not(&mut self, f: BddRef) -> BddRef {
+        self.ite(f, FALSE, TRUE)
+    }
+
+    pub fn apply(&mut self, op: BddOp, f: BddRef, g: BddRef) -> BddRef {
+        match op {
+            BddOp::And => self.ite(f, g, FALSE),
+            BddOp::Or => self.ite(f, TRUE, g),
+            BddOp::Xor => {
+                let not_g = self.not(g);
+                self.ite(f, not_g, g)
+            }
+            BddOp::Iff => {
+                let not_g = self.not(g);
+                self.ite(f, g, not_g)
+            }
+            BddOp::Implies => self.ite(f, g, TRUE),
+            BddOp::Nand => {
+                let and = self.ite(f, g, FALSE);
+                self.not(and)
+            }
+            BddOp::Nor => {
+                let or = self.ite(f, TRUE, g);
+                self.not(or)
+            }
+        }
+    }
+
+    // f with the variable fixed to value
+    pub fn restrict(&mut self, f: BddRef, name: &str, value: bool) -> BddRef {
+        match self.levels.get(name) {
+            Some(level) => self.restrict_aux(f, *level, value, &mut HashMap::new()),
+            None => f,
+        }
+    }
+
+    fn restrict_aux(&mut self, f: BddRef, level: usize, value: bool, cache: &mut HashMap<BddRef, BddRef>) -> BddRef {
+        let node = self.nodes[f];
+        if node.level > level {
+            return f;
+        }
+        if node.level == level {
+            return if value { node.high } else { node.low };
+        }
+        if let Some(result) = cache.get(&f) {
+            return *result;
+        }
+
+        let low = self.restrict_aux(node.low, level, value, cache);
+        let high = self.restrict_aux(node.high, level, value, cache);
+        let result = self.make(node.level, low, high);
+        cache.insert(f, result);
+        result
+    }
+
+    // True where some value of the variables makes f true
+    pub fn exists(&mut self, f: BddRef, names: &[String]) -> BddRef {
+        names.iter().fold(f, |f, name| {
+            let low = self.restrict(f, name, false);
+            let high = self.restrict(f, name, true);
+            self.ite(low, TRUE, high)
+        })
+    }
+
+    // True where every value of the variables makes f true
+    pub fn forall(&mut self, f: BddRef, names: &[String]) -> BddRef {
+        names.iter().fold(f, |f, name| {
+            let low = self.restrict(f, name, false);
+            let high = self.restrict(f, name, true);
+            self.ite(low, high, FALSE)
+        })
+    }
+
+    pub fn build(&mut self, expr: &Expr) -> BddRef {
+        self.build_aux(expr, &mut HashMap::new())
+    }
+
+    // Builds each shared node of the expression once, built holds the ones built so far
+    fn build_aux(&mut self, expr: &Expr, built: &mut HashMap<*const Expr, BddRef>) -> BddRef {
+        if let Some(f) = built.get(&(expr as *const Expr)) {
+            return *f;
+        }
+        let f = self.build_node(expr, built);
+        built.insert(expr, f);
+        f
+    }
+
+    fn build_node(&mut self, expr: &Expr, built: &mut HashMap<*const Expr, BddRef>) -> BddRef {
+        let node = expr.node();
+        let operands = node.operands().into_iter().map(|operand| self.build_aux(operand, built)).collect::<Vec<_>>();
+        match node {
+            Node::Var(name) => self.var(&name),
+            Node::Literal(value) => if value { TRUE } else { FALSE },
+            Node::Not(_) => self.not(operands[0]),
+            Node::And(..) => self.apply(BddOp::And, operands[0], operands[1]),
+            Node::Or(..) => self.apply(BddOp::Or, operands[0], operands[1]),
+            Node::Xor(..) => self.apply(BddOp::Xor, operands[0], operands[1]),
+            Node::Iff(..) => self.apply(BddOp::Iff, operands[0], operands[1]),
+            Node::Implies(..) => self.apply(BddOp::Implies, operands[0], operands[1]),
+            Node::Nand(..) => self.apply(BddOp::Nand, operands[0], operands[1]),
+            Node::Nor(..) => self.apply(BddOp::Nor, operands[0], operands[1]),
+            Node::Card(_, comparison, bound, _) => {
+                let terms = operands.into_iter().map(|f| (1, f)).collect::<Vec<_>>();
+                self.linear(&terms, comparison, bound as i64)
+            }
+            Node::Pb(terms, comparison, bound, _) => {
+                let terms = terms.iter().map(|(weight, _)| *weight).zip(operands).collect::<Vec<_>>();
+                self.linear(&terms, comparison, bound)
+            }
+        }
+    }
+
+    // Like build, but gives up once that would add more than limit nodes to the table
+    // The manager is then left as it was before the call, apart from its cache
+    pub fn build_within(&mut self, expr: &Expr, limit: usize) -> Option<BddRef> {
+        let (nodes, variables) = (self.nodes.len(), self.order.len());
+        self.node_limit = nodes.saturating_add(limit);
+        let f = self.build(expr);
+        self.node_limit = usize::MAX;
+        if !self.gave_up {
+            return Some(f);
+        }
+
+        self.gave_up = false;
+        for node in self.nodes.drain(nodes..) {
+            self.unique.remove(&node);
+        }
+        for name in self.order.drain(variables..) {
+            self.levels.remove(&name);
+        }
+        self.ite_cache.clear();
+        None
+    }
+
+    // True where the weighted sum of the true functions compares with the bound
+    // Uses the same diagram as the PB encoding, which shares the sums that compare the same
+    fn linear(&mut self, terms: &[(i64, BddRef)], comparison: Comparison, mut bound: i64) -> BddRef {
+        // Negative weights are moved onto the negation: w * f = w + -w * not f
+        let mut positive = Vec::new();
+        for (weight, f) in terms {
+            if *weight < 0 {
+                positive.push((-weight, self.not(*f)));
+                bound -= weight;
+            } else if *weight > 0 {
+                positive.push((*weight, *f));
+            }
+        }
+
+        let mut diagram = LinearBdd::new(positive, [FALSE, TRUE]);
+        let mut at_most = |bdd: &mut Bdd, bound| diagram.at_most(bound, &mut |f, taken, skipped| bdd.ite(f, taken, skipped));
+        match comparison {
+            Comparison::AtMost => at_most(self, bound),
+            Comparison::AtLeast => {
+                let below = at_most(self, bound - 1);
+                self.not(below)
+            }
+            Comparison::Exactly => {
+                let at_most_bound = at_most(self, bound);
+                let below = at_most(self, bound - 1);
+                self.ite(below, FALSE, at_most_bound)
+            }
+        }
+    }
+
+    // Number of assignments of all the manager's variables that make f true
+    pub fn count_models(&self, f: BddRef) -> BigUint {
+        let counts = &mut HashMap::new();
+        &self.count_aux(f, counts) * &BigUint::pow2(self.depth(f))
+    }
+
+    // Level of f, with terminals below the last variable
+    fn depth(&self, f: BddRef) -> usize {
+        self.level(f).min(self.order.len())
+    }
+
+    // Number of assignments of the variables from the level of f down that make f true
+    fn count_aux(&self, f: BddRef, counts: &mut HashMap<BddRef, BigUint>) -> BigUint {
+        match f {
+            FALSE => return BigUint::zero(),
+            TRUE => return BigUint::one(),
+            _ => {}
+        }
+        if let Some(count) = counts.get(&f) {
+            return count.clone();
+        }
+
+        let node = self.nodes[f];
+        let count = [node.low, node.high].iter()
+            .map(|child| &self.count_aux(*child, counts) * &BigUint::pow2(self.depth(*child) - node.level - 1))
+            .fold(BigUint::zero(), |total, count| &total + &count);
+        counts.insert(f, count.clone());
+        count
+    }
+
+    // Variables f depends on, in the order they are tested
+    pub fn support(&self, f: BddRef) -> Vec<String> {
+        let mut tested = vec![false; self.order.len()];
+        let mut seen = vec![false; self.nodes.len()];
+        let mut stack = vec![f];
+        while let Some(f) = stack.pop() {
+            if f <= TRUE || seen[f] {
+                continue;
+            }
+            seen[f] = true;
+            tested[self.nodes[f].level] = true;
+            stack.push(self.nodes[f].low);
+            stack.push(self.nodes[f].high);
+        }
+        self.order.iter().zip(tested).filter(|(_, tested)| *tested).map(|(name, _)| name.clone()).collect()
+    }
+
+    // Number of decision nodes reachable from the roots, shared nodes counted once
+    pub fn size(&self, roots: &[BddRef]) -> usize {
+        let mut seen = vec![false; self.nodes.len()];
+        let mut stack = roots.to_vec();
+        let mut size = 0;
+        while let Some(f) = stack.pop() {
+            if f <= TRUE || seen[f] {
+                continue;
+            }
+            seen[f] = true;
+            size += 1;
+            stack.push(self.nodes[f].low);
+            stack.push(self.nodes[f].high);
+        }
+        size
+    }
+
+    // Rebuilds the roots with the variables in a new order, which has to hold every variable
+    // Returns the roots in the new table, handles from before are no longer valid
+    pub fn reorder(&mut self, order: Vec<String>, roots: &[BddRef]) -> Result<Vec<BddRef>, String> {
+        if let Some(name) = self.order.iter().find(|name| !order.contains(name)) {
+            return Err(format!("Variable '{}' is missing from the order", name));
+        }
+
+        let mut bdd = Bdd::new(order);
+        let mut moved = HashMap::from([(FALSE, FALSE), (TRUE, TRUE)]);
+        let roots = roots.iter().map(|f| self.transfer(*f, &mut bdd, &mut moved)).collect();
+        *self = bdd;
+        Ok(roots)
+    }
+
+    fn transfer(&self, f: BddRef, bdd: &mut Bdd, moved: &mut HashMap<BddRef, BddRef>) -> BddRef {
+        if let Some(g) = moved.get(&f) {
+            return *g;
+        }
+        let node = self.nodes[f];
+        let low = self.transfer(node.low, bdd, moved);
+        let high = self.transfer(node.high, bdd, moved);
+        let var = bdd.var(&self.order[node.level]);
+        let g = bdd.ite(var, high, low);
+        moved.insert(f, g);
+        g
+    }
+
+    // Drops every node the roots don't need and clears the cache
+    // Returns the roots in the new table, handles from before are no longer valid
+    pub fn collect(&mut self, roots: &[BddRef]) -> Vec<BddRef> {
+        let mut bdd = Bdd::new(self.order.clone());
+        let mut moved = HashMap::from([(FALSE, FALSE), (TRUE, TRUE)]);
+        let roots = roots.iter().map(|f| self.copy(*f, &mut bdd, &mut moved)).collect();
+        *self = bdd;
+        roots
+    }
+
+    // Copies f into a table with the same order
+    fn copy(&self, f: BddRef, bdd: &mut Bdd, moved: &mut HashMap<BddRef, BddRef>) -> BddRef {
+        if let Some(g) = moved.get(&f) {
+            return *g;
+        }
+        let node = self.nodes[f];
+        let low = self.copy(node.low, bdd, moved);
+        let high = self.copy(node.high, bdd, moved);
+        let g = bdd.make(node.level, low, high);
+        moved.insert(f, g);
+        g
+    }
+
+    // Swaps the variable at level with the one below it in place, every handle keeps its function
+    // Nodes of the upper variable that test the lower one are rewritten to test it first
+    fn swap(&mut self, level: usize) {
+        let below = level + 1;
+        let upper = (0..self.nodes.len()).filter(|f| self.nodes[*f].level == level).collect::<Vec<_>>();
+        let lower = (0..self.nodes.len()).filter(|f| self.nodes[*f].level == below).collect::<Vec<_>>();
+        for f in upper.iter().chain(&lower) {
+            self.unique.remove(&self.nodes[*f]);
+        }
+
+        // Children of each upper node by the lower variable, taken before anything moves
+        let split = upper.iter()
+            .map(|f| [self.nodes[*f].low, self.nodes[*f].high].map(|child| [false, true].map(|value| self.cofactor(child, below, value))))
+            .collect::<Vec<_>>();
+        for f in lower {
+            self.nodes[f].level = level;
+            self.unique.insert(self.nodes[f], f);
+        }
+
+        // Upper nodes that don't test the lower variable just move down a level
+        let mut rewrite = Vec::new();
+        for (f, [[f00, f01], [f10, f11]]) in upper.into_iter().zip(split) {
+            if f00 == f01 && f10 == f11 {
+                self.nodes[f].level = below;
+                self.unique.insert(self.nodes[f], f);
+            } else {
+                rewrite.push((f, [f00, f01, f10, f11]));
+            }
+        }
+        for (f, [f00, f01, f10, f11]) in rewrite {
+            let low = self.make(below, f00, f10);
+            let high = self.make(below, f01, f11);
+            self.nodes[f] = BddNode { level, low, high };
+            self.unique.insert(self.nodes[f], f);
+        }
+
+        self.order.swap(level, below);
+        self.levels.insert(self.order[level].clone(), level);
+        self.levels.insert(self.order[below].clone(), below);
+    }
+
+    // Moves each variable in turn to the position where the roots have the fewest nodes,
+    // by swapping it down to the bottom, up to the top, then back to the best level
+    // Returns the roots in the new table, handles from before are no longer valid
+    pub fn sift(&mut self, roots: &[BddRef]) -> Vec<BddRef> {
+        let mut roots = self.collect(roots);
+        for name in self.order.clone() {
+            let mut level = self.levels[&name];
+            let mut best = (self.size(&roots), level);
+            let limit = best.0.saturating_mul(SIFT_MAX_GROWTH);
+
+            let mut size = best.0;
+            while level + 1 < self.order.len() && size <= limit {
+                self.swap(level);
+                level += 1;
+                size = self.size(&roots);
+                best = best.min((size, level));
+            }
+            size = best.0;
+            while level > 0 && size <= limit {
+                self.swap(level - 1);
+                level -= 1;
+                size = self.size(&roots);
+                best = best.min((size, level));
+            }
+            while level < best.1 {
+                self.swap(level);
+                level += 1;
+            }
+            while level > best.1 {
+                self.swap(level - 1);
+                level -= 1;
+            }
+            roots = self.collect(&roots);
+        }
+        roots
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt;
+use std::ops::{Add, Mul};
+
+// Arbitrary precision unsigned integer, only supporting what model counting needs
+// Stored as base 2^32 digits, least significant first, without trailing zeros
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BigUint {
+    digits: Vec<u32>,
+}
+
+impl BigUint {
+    pub fn zero() -> BigUint {
+        BigUint { digits: Vec::new() }
+    }
+
+    pub fn one() -> BigUint {
+        BigUint::from(1)
+    }
+
+    // Returns 2^exponent
+    pub fn pow2(exponent: usize) -> BigUint {
+        let mut digits = vec![0; exponent / 32];
+        digits.push(1 << (exponent % 32));
+        BigUint { digits }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    fn trim(mut self) -> BigUint {
+        while self.digits.last() == Some(&0) {
+            self.digits.pop();
+        }
+        self
+    }
+
+    // Divides in place by a small divisor, returning the remainder
+    fn div_rem_small(&mut self, divisor: u32) -> u32 {
+        let mut remainder = 0u64;
+        for digit in self.digits.iter_mut().rev() {
+            let current = (remainder << 32) | *digit as u64;
+            *digit = (current / divisor as u64) as u32;
+            remainder = current % divisor as u64;
+        }
+        *self = std::mem::take(self).trim();
+        remainder as u32
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(value: u64) -> BigUint {
+        BigUint { digits: vec![value as u32, (value >> 32) as u32] }.trim()
+    }
+}
+
+impl Add for &BigUint {
+    type Output = BigUint;
+
+    fn add(self, other: &BigUint) -> BigUint {
+        let len = self.digits.len().max(other.digits.len());
+        let mut digits = Vec::with_capacity(len + 1);
+        let mut carry = 0u64;
+
+        for i in 0..len {
+            let sum = *self.digits.get(i).unwrap_or(&0) as u64 + *other.digits.get(i).unwrap_or(&0) as u64 + carry;
+            digits.push(sum as u32);
+            carry = sum >> 32;
+        }
+        digits.push(carry as u32);
+
+        BigUint { digits }.trim()
+    }
+}
+
+impl Mul for &BigUint {
+    type Output = BigUint;
+
+    fn mul(self, other: &BigUint) -> BigUint {
+        if self.is_zero() || other.is_zero() {
+            return BigUint::zero();
+        }
+
+        let mut digits = vec![0u32; self.digits.len() + other.digits.len()];
+        for (i, a) in self.digits.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, b) in other.digits.iter().enumerate() {
+                let product = *a as u64 * *b as u64 + digits[i + j] as u64 + carry;
+                digits[i + j] = product as u32;
+                carry = product >> 32;
+            }
+            digits[i + other.digits.len()] = carry as u32;
+        }
+
+        BigUint { digits }.trim()
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+
+        // Peel off nine decimal digits at a time
+        let mut rest = self.clone();
+        let mut chunks = Vec::new();
+        while !rest.is_zero() {
+            chunks.push(rest.div_rem_small(1_000_000_000));
+        }
+
+        write!(f, "{}", chunks.last().unwrap())?;
+        for chunk in chunks.iter().rev().skip(1) {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
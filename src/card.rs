@@ -0,0 +1,279 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Ways of turning a cardinality constraint into clauses
+// Every encoding builds unary counter outputs where output j is true exactly when at least j + 1
+// of the inputs are true, so constraints can be negated and nested inside other expressions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CardEncoding {
+    // Running counts over each prefix of the inputs, O(n * k) clauses
+    SequentialCounter,
+
+    // Binary tree of unary adders, O(n * k) clauses with fewer auxiliary variables in practice
+    #[default]
+    Totalizer,
+
+    // Odd-even merge sorting network, pruned down to the outputs the bound needs, O(n log^2 n) clauses
+    CardinalityNetwork,
+}
+
+impl FromStr for CardEncoding {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<CardEncoding, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "sequential" => Ok(CardEncoding::SequentialCounter),
+            "totalizer" => Ok(CardEncoding::Totalizer),
+            "network" => Ok(CardEncoding::CardinalityNetwork),
+            _ => Err(format!("Unknown cardinality encoding '{}', expected 'sequential', 'totalizer' or 'network'", name)),
+        }
+    }
+}
+
+// How the number of true inputs is compared with the bound
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Comparison {
+    AtMost,
+    AtLeast,
+    Exactly,
+}
+
+impl Comparison {
+    pub fn holds<T: Ord>(&self, count: T, bound: T) -> bool {
+        match self {
+            Comparison::AtMost => count <= bound,
+            Comparison::AtLeast => count >= bound,
+            Comparison::Exactly => count == bound,
+        }
+    }
+
+    // Operator used for linear constraints
+    pub fn operator(&self) -> &'static str {
+        match self {
+            Comparison::AtMost => "<=",
+            Comparison::AtLeast => ">=",
+            Comparison::Exactly => "=",
+        }
+    }
+}
+
+impl FromStr for Comparison {
+    type Err = String;
+
+    fn from_str(op: &str) -> Result<Comparison, String> {
+        match op {
+            "<=" => Ok(Comparison::AtMost),
+            ">=" => Ok(Comparison::AtLeast),
+            "=" | "==" => Ok(Comparison::Exactly),
+            _ => Err(format!("Unknown comparison '{}', expected '<=', '>=' or '='", op)),
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Comparison::AtMost => write!(f, "at most"),
+            Comparison::AtLeast => write!(f, "at least"),
+            Comparison::Exactly => write!(f, "exactly"),
+        }
+    }
+}
+
+// Clauses making id equivalent to the number of true literals in lits satisfying the comparison with bound
+// fresh returns a new auxiliary variable each time it is called
+pub fn reified(
+    id: isize,
+    lits: &[isize],
+    comparison: Comparison,
+    bound: usize,
+    encoding: CardEncoding,
+    fresh: &mut impl FnMut() -> isize,
+) -> Vec<Vec<isize>> {
+    // The constraint is count >= lower and, if there is an upper bound, count <= upper
+    let (lower, upper) = match comparison {
+        Comparison::AtMost => (0, Some(bound)),
+        Comparison::AtLeast => (bound, None),
+        Comparison::Exactly => (bound, Some(bound)),
+    };
+    let upper = upper.filter(|upper| *upper < lits.len());
+
+    if lower > lits.len() {
+        return vec![vec![-id]];
+    }
+    if lower == 0 && upper.is_none() {
+        return vec![vec![id]];
+    }
+
+    let needed = upper.map_or(lower, |upper| lower.max(upper + 1));
+    let (outputs, mut clauses) = counter(lits, needed, encoding, fresh);
+
+    let mut conjuncts = Vec::new();
+    if lower > 0 {
+        conjuncts.push(outputs[lower - 1]);
+    }
+    if let Some(upper) = upper {
+        conjuncts.push(-outputs[upper]);
+    }
+
+    for conjunct in conjuncts.iter() {
+        clauses.push(vec![-id, *conjunct]);
+    }
+    clauses.push([id].into_iter().chain(conjuncts.iter().map(|conjunct| -conjunct)).collect());
+    clauses
+}
+
+// Unary count of the true literals in lits, truncated to its first limit outputs
+// Returns the outputs, where outputs[j] is true exactly when at least j + 1 literals are true, and their clauses
+pub fn counter(
+    lits: &[isize],
+    limit: usize,
+    encoding: CardEncoding,
+    fresh: &mut impl FnMut() -> isize,
+) -> (Vec<isize>, Vec<Vec<isize>>) {
+    let mut clauses = Vec::new();
+    let outputs = match encoding {
+        CardEncoding::SequentialCounter => sequential_counter(lits, limit, fresh, &mut clauses),
+        CardEncoding::Totalizer => totalizer(lits, limit, fresh, &mut clauses),
+        CardEncoding::CardinalityNetwork => sorting_network(lits, limit, fresh, &mut clauses),
+    };
+    (outputs, clauses)
+}
+
+fn sequential_counter(
+    lits: &[isize],
+    limit: usize,
+    fresh: &mut impl FnMut() -> isize,
+    clauses: &mut Vec<Vec<isize>>,
+) -> Vec<isize> {
+    // counts[j] is true when at least j + 1 of the literals seen so far are true
+    let mut counts: Vec<isize> = Vec::new();
+    for (i, lit) in lits.iter().enumerate() {
+        let mut next = Vec::new();
+        for j in 0..limit.min(i + 1) {
+            // At least j + 1 so far if there already were, or this literal is true and there were at least j
+            let before = counts.get(j).copied();
+            let one_less = if j == 0 { None } else { Some(counts[j - 1]) };
+
+            if before.is_none() && one_less.is_none() {
+                next.push(*lit);
+                continue;
+            }
+
+            let output = fresh();
+            if let Some(before) = before {
+                clauses.push(vec![-before, output]);
+            }
+            clauses.push([-lit, output].into_iter().chain(one_less.map(|one_less| -one_less)).collect());
+            clauses.push([-output, *lit].into_iter().chain(before).collect());
+            if let Some(one_less) = one_less {
+                clauses.push([-output, one_less].into_iter().chain(before).collect());
+            }
+            next.push(output);
+        }
+        counts = next;
+    }
+    counts
+}
+
+fn totalizer(
+    lits: &[isize],
+    limit: usize,
+    fresh: &mut impl FnMut() -> isize,
+    clauses: &mut Vec<Vec<isize>>,
+) -> Vec<isize> {
+    if lits.len() <= 1 {
+        return lits.iter().copied().take(limit).collect();
+    }
+
+    let (left, right) = lits.split_at(lits.len() / 2);
+    let left = totalizer(left, limit, fresh, clauses);
+    let right = totalizer(right, limit, fresh, clauses);
+    let outputs = (0..limit.min(lits.len())).map(|_| fresh()).collect::<Vec<_>>();
+
+    // With a true on the left and b true on the right, at least a + b are true, and at most a + b
+    // are true when the left has fewer than a + 1 and the right fewer than b + 1
+    // Index 0 stands for the count being at least zero, which always holds
+    for a in 0..=left.len() {
+        for b in 0..=right.len() {
+            if a + b > 0 {
+                let sum = (a + b).min(limit);
+                let mut clause = vec![outputs[sum - 1]];
+                if a > 0 {
+                    clause.push(-left[a - 1]);
+                }
+                if b > 0 {
+                    clause.push(-right[b - 1]);
+                }
+                clauses.push(clause);
+            }
+
+            if a + b < outputs.len() {
+                let mut clause = vec![-outputs[a + b]];
+                clause.extend(left.get(a));
+                clause.extend(right.get(b));
+                clauses.push(clause);
+            }
+        }
+    }
+    outputs
+}
+
+fn sorting_network(
+    lits: &[isize],
+    limit: usize,
+    fresh: &mut impl FnMut() -> isize,
+    clauses: &mut Vec<Vec<isize>>,
+) -> Vec<isize> {
+    // Batcher's odd-even merge sort over the inputs padded to a power of two
+    // The padding is false and sorts to the end, so comparators touching it never swap and are left out
+    let n = lits.len();
+    let mut comparators = Vec::new();
+    let size = n.next_power_of_two();
+    let mut p = 1;
+    while p < size {
+        let mut k = p;
+        while k >= 1 {
+            let mut j = k % p;
+            while j + k < size {
+                for i in 0..k.min(size - j - k) {
+                    if (i + j) / (2 * p) == (i + j + k) / (2 * p) && i + j + k < n {
+                        comparators.push((i + j, i + j + k));
+                    }
+                }
+                j += 2 * k;
+            }
+            k /= 2;
+        }
+        p *= 2;
+    }
+
+    // Only keep the comparators that the first limit outputs depend on
+    let mut needed = (0..n).map(|wire| wire < limit).collect::<Vec<_>>();
+    let mut kept = Vec::new();
+    for (hi, lo) in comparators.into_iter().rev() {
+        if needed[hi] || needed[lo] {
+            needed[hi] = true;
+            needed[lo] = true;
+            kept.push((hi, lo));
+        }
+    }
+
+    // Each comparator puts the disjunction of its wires on top and the conjunction below
+    let mut wires = lits.to_vec();
+    for (hi, lo) in kept.into_iter().rev() {
+        let (a, b) = (wires[hi], wires[lo]);
+        let (max, min) = (fresh(), fresh());
+        clauses.push(vec![-a, max]);
+        clauses.push(vec![-b, max]);
+        clauses.push(vec![-max, a, b]);
+        clauses.push(vec![-min, a]);
+        clauses.push(vec![-min, b]);
+        clauses.push(vec![min, -a, -b]);
+        wires[hi] = max;
+        wires[lo] = min;
+    }
+
+    wires.truncate(limit);
+    wires
+}
@@ -1,6 +1,17 @@
 use std::collections::HashMap;
 
+use crate::bignum::BigUint;
+use crate::count::ModelCounter;
+use crate::dag::{FastMap, Node};
+use crate::dimacs;
 use crate::expr::Expr;
+use crate::models::Models;
+use crate::pb::{self, PbEncoding};
+use crate::proof::{self, Proof};
+use crate::solver::Solver;
+
+// Clauses a Plaisted-Greenbaum gate implies, and clauses implying it
+type DirectedGate = (Vec<Vec<isize>>, Vec<Vec<isize>>);
 
 // Representation of a boolean expression in conjunctive normal form
 #[derive(Clone, Debug)]
@@ -21,17 +32,102 @@ pub struct CNF {
     // Note: used for Tseitin transformations
     // Counter of intermediate variables we've created, TODO base 64
     counter: usize,
+
+    // Maps each gate the Tseitin transformation created to its variable, so equal gates are shared
+    gates: FastMap<Node<isize>, isize>,
+
+    // Maps the clauses defining each Plaisted-Greenbaum gate to its variable and the directions added so far
+    directed_gates: FastMap<DirectedGate, (isize, bool, bool)>,
 }
 
 #[allow(dead_code)]
 impl CNF {
+    // Reads a CNF in the DIMACS format, see dimacs::read
+    pub fn read_dimacs(reader: impl std::io::BufRead) -> Result<CNF, String> {
+        dimacs::read(reader)
+    }
+
+    pub fn from_dimacs(src: &str) -> Result<CNF, String> {
+        dimacs::read(src.as_bytes())
+    }
+
+    // Writes the CNF in the DIMACS format, with the variable names as comments
+    pub fn write_dimacs(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        dimacs::write(self, writer)
+    }
+
+    pub fn to_dimacs(&self) -> String {
+        let mut buffer = Vec::new();
+        self.write_dimacs(&mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    // Reads a linear pseudo-Boolean problem in the OPB format, encoding each constraint into clauses
+    // Its objective function, if any, is ignored
+    pub fn from_opb(src: &str, encoding: PbEncoding) -> Result<CNF, String> {
+        let opb = pb::parse_opb(src)?;
+        let mut cnf = CNF::new(opb.names, Vec::new());
+        for constraint in opb.constraints.iter() {
+            let id = cnf.gen_aux_var() as isize;
+            let clauses = pb::reified(id, constraint, encoding, &mut || cnf.gen_aux_var() as isize);
+            for clause in clauses {
+                cnf.append_clause(clause);
+            }
+            cnf.enforce(id, true);
+        }
+        Ok(cnf)
+    }
+
     // Checks if the CNF is satisfiable
     // If the formula is SAT, returns a list of the truth assignments where truth_assignment[i]
     // is the truth assignment of variable with id i + 1
-    // Otherwise returns Nonesy
-    pub fn find_evidence(&mut self) -> Option<Vec<bool>> {
-        let mut truth_assignment = self.gen_empty_truth_assignment();
-        self.dpll(&mut truth_assignment)
+    // Otherwise returns None
+    pub fn find_evidence(&self) -> Option<Vec<bool>> {
+        Solver::from_cnf(self).solve()
+    }
+
+    // Checks if the CNF is unsatisfiable
+    // If so, returns a refutation whose input clause ids are the positions of the clauses plus one
+    // Otherwise returns None
+    pub fn find_refutation(&self) -> Option<Proof> {
+        let mut solver = Solver::new(self.num_vars());
+        solver.enable_proof();
+        for clause in self.clauses.iter() {
+            solver.append_clause(clause.clone());
+        }
+
+        match solver.solve() {
+            Some(_) => None,
+            None => solver.proof().cloned(),
+        }
+    }
+
+    // Finds a minimal set of clauses that is UNSAT on its own, returned as indices into the clauses
+    // Returns None if the CNF is SAT
+    pub fn unsat_core(&self) -> Option<Vec<usize>> {
+        // Clause i is only active when its selector variable, numbered after every real variable, is assumed
+        let num_vars = self.num_vars();
+        let mut solver = Solver::new(num_vars + self.clauses.len());
+        let mut selectors = Vec::new();
+        for (idx, clause) in self.clauses.iter().enumerate() {
+            let selector = (num_vars + idx + 1) as isize;
+            let mut clause = clause.clone();
+            clause.push(-selector);
+            solver.append_clause(clause);
+            selectors.push(selector);
+        }
+
+        let core = solver.unsat_core(&selectors)?;
+        let mut indices = core.into_iter()
+            .map(|selector| selector as usize - num_vars - 1)
+            .collect::<Vec<_>>();
+        indices.sort_unstable();
+        Some(indices)
+    }
+
+    // Verifies an LRAT refutation of this CNF
+    pub fn check_lrat(&self, proof: &str) -> Result<(), String> {
+        proof::check_lrat(&self.clauses, proof)
     }
 
     // Enforce a certain variable to be either true or false
@@ -40,35 +136,138 @@ impl CNF {
     }
 
     pub fn from_id(&self, id: isize) -> String {
-        self.symbol_table[id.abs() as usize - 1].clone()
+        self.symbol_table[id.unsigned_abs() - 1].clone()
     }
 
     // Geterate intermediate variable for expression and return its id
     pub fn gen_var(&mut self, expr: &Expr) -> usize {
         // Handle simple variable
         if let Expr::Var(name) = expr {
-            if let Some(id) = self.name_to_id.get(name) {
-                return *id;
-            } else {
-                let id = self.add_variable(name.clone());
-                self.name_to_id.insert(name.clone(), id);
-                return id;
-            }
+            return self.named_var(name);
         }
 
         // Handle sub-expression
-        let name = format!("${}", self.counter);
-        self.counter += 1;
+        self.gen_aux_var()
+    }
+
+    // Id of the variable with the given name, added if it doesn't exist yet
+    pub fn named_var(&mut self, name: &str) -> usize {
+        if let Some(id) = self.name_to_id.get(name) {
+            return *id;
+        }
 
-        let id = self.add_variable(name);
+        let id = self.add_variable(name.to_string());
+        self.name_to_id.insert(name.to_string(), id);
+        id
+    }
 
+    // Variable equivalent to a gate over existing literals, adding its Tseitin clauses the first time the gate is seen
+    // A variable node is not a gate and gives the variable with that name
+    pub fn gate(&mut self, node: Node<isize>, display: bool) -> isize {
+        if let Node::Var(name) = &node {
+            return self.named_var(name) as isize;
+        }
+        if let Some(id) = self.gates.get(&node) {
+            return *id;
+        }
+
+        let id = self.gen_aux_var() as isize;
+        if display {
+            let gate = node.map(|lit| Expr::Var(self.from_id(*lit))).into_expr();
+            print!("cnf({} <-> ({})) ", self.from_id(id), gate);
+        }
+
+        let clauses = node.tseitin(id, &mut || self.gen_aux_var() as isize);
+        self.clauses.extend(clauses);
+        self.gates.insert(node, id);
         id
     }
 
+    // Gate variable v with a clause (not v or c) for each positive clause c when positive is set,
+    // and (v or c) for each negative clause c when negative is set
+    // Gates with the same clauses share a variable, which gains whichever direction it was missing
+    pub fn directed_gate(&mut self, clauses: (&[Vec<isize>], &[Vec<isize>]), positive: bool, negative: bool) -> isize {
+        let key = (clauses.0.to_vec(), clauses.1.to_vec());
+        let (id, mut has_positive, mut has_negative) = match self.directed_gates.get(&key) {
+            Some(gate) => *gate,
+            None => (self.gen_aux_var() as isize, false, false),
+        };
+
+        if positive && !has_positive {
+            for clause in clauses.0 {
+                self.append_clause([-id].iter().chain(clause.iter()).copied().collect());
+            }
+            has_positive = true;
+        }
+        if negative && !has_negative {
+            for clause in clauses.1 {
+                self.append_clause([id].iter().chain(clause.iter()).copied().collect());
+            }
+            has_negative = true;
+        }
+
+        self.directed_gates.insert(key, (id, has_positive, has_negative));
+        id
+    }
+
+    // Generate a fresh intermediate variable and return its id
+    pub fn gen_aux_var(&mut self) -> usize {
+        let name = format!("${}", self.counter);
+        self.counter += 1;
+
+        self.add_variable(name)
+    }
+
     pub fn get_symbol_table(&self) -> Vec<String> {
         self.symbol_table.clone()
     }
 
+    pub fn get_id(&self, name: &str) -> Option<usize> {
+        self.name_to_id.get(name).copied()
+    }
+
+    // Checks if a variable was introduced by the Tseitin transformation rather than named by the user
+    pub fn is_auxiliary(&self, id: usize) -> bool {
+        self.symbol_table[id - 1].starts_with('$')
+    }
+
+    // Ids of every variable that isn't auxiliary
+    pub fn named_vars(&self) -> Vec<usize> {
+        (1..=self.num_vars()).filter(|id| !self.is_auxiliary(*id)).collect()
+    }
+
+    // Enumerates the satisfying assignments projected onto the named variables
+    pub fn models(&self) -> Models {
+        self.models_of(self.named_vars())
+    }
+
+    // Enumerates the satisfying assignments projected onto the variables with the given names
+    pub fn models_over(&self, names: &[String]) -> Result<Models, String> {
+        Ok(self.models_of(self.ids_of(names)?))
+    }
+
+    fn ids_of(&self, names: &[String]) -> Result<Vec<usize>, String> {
+        names.iter()
+            .map(|name| self.get_id(name).ok_or(format!("Unknown variable '{}'", name)))
+            .collect()
+    }
+
+    // Number of satisfying assignments of the named variables
+    // Auxiliary variables are projected away, so each assignment of the named ones counts once
+    pub fn count_models(&self) -> BigUint {
+        ModelCounter::new(self.num_vars(), &self.named_vars()).count(&self.clauses)
+    }
+
+    // Number of assignments of the variables with the given names that extend to a model
+    pub fn count_models_over(&self, names: &[String]) -> Result<BigUint, String> {
+        Ok(ModelCounter::new(self.num_vars(), &self.ids_of(names)?).count(&self.clauses))
+    }
+
+    fn models_of(&self, ids: Vec<usize>) -> Models {
+        let names = ids.iter().map(|id| self.symbol_table[id - 1].clone()).collect();
+        Models::new(Solver::from_cnf(self), ids, names)
+    }
+
     pub fn set_symbol_name(&mut self, id: usize, name: String) {
         self.symbol_table[id - 1] = name;
     }
@@ -81,6 +280,10 @@ impl CNF {
         id + 1
     }
 
+    pub fn num_vars(&self) -> usize {
+        self.symbol_table.len()
+    }
+
     pub fn gen_empty_truth_assignment(&self) -> Vec<Option<bool>> {
          vec![None; self.symbol_table.len()]
     }
@@ -100,16 +303,32 @@ impl CNF {
     }
 
     pub fn new(symbol_table: Vec<String>, clauses: Vec<Vec<isize>>) -> CNF {
-        CNF { symbol_table, clauses, counter: 0, name_to_id: HashMap::new() }
+        let name_to_id = symbol_table.iter()
+            .enumerate()
+            .map(|(idx, name)| (name.clone(), idx + 1))
+            .collect();
+
+        // Continue numbering after any intermediate variables already in the table
+        let counter = symbol_table.iter()
+            .filter_map(|name| name.strip_prefix('$')?.parse::<usize>().ok())
+            .map(|n| n + 1)
+            .max()
+            .unwrap_or(0);
+
+        CNF { symbol_table, clauses, counter, name_to_id, gates: FastMap::default(), directed_gates: FastMap::default() }
     }
 
     pub fn get_clauses_clone(&self) -> Vec<Vec<isize>> {
         self.clauses.clone()
     }
 
+    pub fn get_clauses(&self) -> &Vec<Vec<isize>> {
+        &self.clauses
+    }
+
     // Create new CNF with same symbol table
     pub fn from_self(&self, clauses: Vec<Vec<isize>>) -> CNF {
-        CNF { symbol_table: self.symbol_table.clone(), clauses, counter: 0, name_to_id: HashMap::new() }
+        CNF::new(self.symbol_table.clone(), clauses)
     }
 
     // Return a CNF after conditioning on some variable target
@@ -135,64 +354,10 @@ impl CNF {
         self.from_self(new_clauses)
     }
 
-    fn contains_empty_clause(&self) -> bool {
-        for clause in self.clauses.iter() {
-            if clause.len() == 0 {
-                return true;
-            }
-        }
-        false
-    }
-
-    pub fn unit_propigation_old(mut self, truth_assignment: &mut Vec<bool>) -> CNF {
-        let mut unit_clause = self.find_unit_clause();
-
-        while let Some(clause) = unit_clause {
-            let var_index = (clause.abs() as usize) - 1;
-            let truth_value = clause > 0;
-
-            truth_assignment[var_index] = truth_value;
- 
-            self = self.conditioned(clause);
-
-            unit_clause = self.find_unit_clause();
-        }
-
-        self
-    }
-
-    // Returns if CNF is satisfiable (using DPLL algorithm), takes the variable we want to condition on
-    fn dpll_old(mut self, current: isize, truth_assignment: &mut Vec<bool>) -> bool {
-        self = self.unit_propigation_old(truth_assignment);
-        // TODO pure literal elimination
-
-        if self.clauses.len() == 0 {
-            return true;
-        }
-
-        if self.contains_empty_clause() {
-            return false;
-        }
-
-        let next_index = current as usize;
-
-        truth_assignment[next_index] = true;
-        if self.conditioned(current).dpll_old(current + 1, truth_assignment) {
-            return true;
-        }
-
-        truth_assignment[next_index] = false;
-        if self.conditioned(-current).dpll_old(current + 1, truth_assignment) {
-            return true;
-        }
-
-        false
-    }
-
-    pub fn is_falsified(clause: &Vec<isize>, truth_assignment: &Vec<Option<bool>>) -> bool {
+    pub fn is_falsified(clause: &[isize], truth_assignment: &[Option<bool>]) -> bool {
         for var in clause {
             let value = *var > 0;
-            let var_idx = var.abs() as usize - 1;
+            let var_idx = var.unsigned_abs() - 1;
             if let Some(assigned) = truth_assignment[var_idx] {
                 if assigned == value {
                     return false;
@@ -204,27 +369,18 @@ impl CNF {
         true
     }
 
-    pub fn any_falsified(&self, truth_assignment: &Vec<Option<bool>>) -> bool {
-        for clause in self.clauses.iter() {
-            if Self::is_falsified(clause, truth_assignment) {
-                return true;
-            }
-        }
-        false
-    }
-
-    pub fn is_unit_clause(clause: &Vec<isize>, truth_assignment: &Vec<Option<bool>>) -> bool {
+    pub fn is_unit_clause(clause: &[isize], truth_assignment: &[Option<bool>]) -> bool {
         Self::implied_assignment(clause, truth_assignment).is_some()
     }
 
     // Checks if clause is a unit clause
     // If so we return the index and value of the implied variable
     // If not we return None
-    pub fn implied_assignment(clause: &Vec<isize>, truth_assignment: &Vec<Option<bool>>) -> Option<(usize, bool)> {
+    pub fn implied_assignment(clause: &[isize], truth_assignment: &[Option<bool>]) -> Option<(usize, bool)> {
         let mut undef = None;
         for var in clause {
             let value = *var > 0;
-            let idx = var.abs() as usize - 1;
+            let idx = var.unsigned_abs() - 1;
 
             match truth_assignment[idx] {
                 None => {
@@ -244,64 +400,4 @@ impl CNF {
         }
         undef
     }
-
-    fn find_implied_assignment(&self, truth_assignment: &Vec<Option<bool>>) -> Option<(usize, bool)> {
-        for clause in self.clauses.iter() {
-            let implied_assignment = Self::implied_assignment(clause, truth_assignment);
-            if implied_assignment.is_some() {
-                return implied_assignment;
-            }
-        } 
-        None
-    }
-
-    fn next_undef(truth_assignment: &Vec<Option<bool>>) -> Option<usize> {
-        for (idx, assignment) in truth_assignment.iter().enumerate() {
-            if assignment.is_none() {
-                return Some(idx);
-            }
-        }
-        None
-    }
-
-    fn try_assignment(&mut self, truth_assignment: &mut Vec<Option<bool>>, idx: usize, value: bool) -> Option<Vec<bool>> {
-        let mut truth_assignment = truth_assignment.clone();
-        truth_assignment[idx] = Some(value);
-        let assignment =  self.dpll(&mut truth_assignment);
-        // truth_assignment[idx] = None;
-        assignment
-    }
-
-    // Uses dpll algorithm to check for SAT
-    // Returns satisfying truth assignment if SAT,
-    // Returns None if UNSAT
-    fn dpll(&mut self, truth_assignment: &mut Vec<Option<bool>>) -> Option<Vec<bool>> {
-        if self.any_falsified(truth_assignment) {
-            return None;
-        }
-
-        while let Some(assignment) = self.find_implied_assignment(truth_assignment) {
-            let (idx, value) = assignment;
-            truth_assignment[idx] = Some(value);
-
-            if self.any_falsified(truth_assignment) {
-                return None;
-            }
-        }
-
-        let next = match Self::next_undef(truth_assignment) {
-            None => return Some(truth_assignment.clone().into_iter().map(|v| v.unwrap()).collect()),
-            Some(idx) => idx,
-        };
-
-        
-        if let Some(satisfying_assignment) = self.try_assignment(truth_assignment, next, true) {
-            Some(satisfying_assignment)
-        }
-        else if let Some(satisfying_assignment) = self.try_assignment(truth_assignment, next, false) {
-            Some(satisfying_assignment)
-        } else {
-            None
-        }
-    }
 }
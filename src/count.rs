@@ -0,0 +1,169 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::bignum::BigUint;
+use crate::solver::Solver;
+
+// Exact model counter (#SAT) projected onto a set of variables
+// Counts by branching on projected variables, splitting the clauses into independent components
+// whose counts multiply, and caching the count of every component it has seen
+// Components without projected variables only need to be satisfiable, so they count as 1 or 0
+pub struct ModelCounter {
+    // projected[id - 1] is true if variable with id is counted
+    projected: Vec<bool>,
+
+    // Counts of components, keyed by their sorted clauses
+    cache: HashMap<Vec<Vec<isize>>, BigUint>,
+}
+
+impl ModelCounter {
+    pub fn new(num_vars: usize, projection: &[usize]) -> ModelCounter {
+        let mut projected = vec![false; num_vars];
+        for id in projection {
+            projected[id - 1] = true;
+        }
+        ModelCounter { projected, cache: HashMap::new() }
+    }
+
+    // Number of assignments of the projected variables that extend to a model of the clauses
+    pub fn count(&mut self, clauses: &[Vec<isize>]) -> BigUint {
+        let scope = (1..=self.projected.len()).filter(|id| self.projected[id - 1]).collect::<Vec<_>>();
+        self.count_clauses(clauses.to_vec(), &scope)
+    }
+
+    // Counts the assignments of the variables in scope that extend to a model of the clauses
+    fn count_clauses(&mut self, clauses: Vec<Vec<isize>>, scope: &[usize]) -> BigUint {
+        let Some((clauses, assigned)) = Self::propagate_units(clauses) else {
+            return BigUint::zero();
+        };
+
+        // Projected variables that no longer appear anywhere can take either value
+        let occurring = clauses.iter().flatten().map(|lit| lit.unsigned_abs()).collect::<HashSet<_>>();
+        let free = scope.iter()
+            .filter(|id| !assigned.contains(*id) && !occurring.contains(*id))
+            .count();
+
+        let mut total = BigUint::pow2(free);
+        for component in Self::components(clauses) {
+            let count = self.count_component(component);
+            if count.is_zero() {
+                return count;
+            }
+            total = &total * &count;
+        }
+        total
+    }
+
+    fn count_component(&mut self, mut component: Vec<Vec<isize>>) -> BigUint {
+        for clause in component.iter_mut() {
+            clause.sort_unstable();
+        }
+        component.sort_unstable();
+
+        if let Some(count) = self.cache.get(&component) {
+            return count.clone();
+        }
+
+        let mut occurrences = HashMap::new();
+        for lit in component.iter().flatten() {
+            *occurrences.entry(lit.unsigned_abs()).or_insert(0) += 1;
+        }
+
+        let scope = occurrences.keys()
+            .copied()
+            .filter(|id| self.projected[id - 1])
+            .collect::<Vec<_>>();
+
+        // Branch on the projected variable occurring most often, breaking ties by id so counting is deterministic
+        let branch = scope.iter().copied().max_by_key(|id| (occurrences[id], std::cmp::Reverse(*id)));
+
+        let count = match branch {
+            None => {
+                let mut solver = Solver::new(0);
+                for clause in component.iter() {
+                    solver.append_clause(clause.clone());
+                }
+                if solver.solve().is_some() { BigUint::one() } else { BigUint::zero() }
+            }
+            Some(id) => {
+                let rest = scope.iter().copied().filter(|other| *other != id).collect::<Vec<_>>();
+                let positive = match Self::condition(&component, id as isize) {
+                    Some(clauses) => self.count_clauses(clauses, &rest),
+                    None => BigUint::zero(),
+                };
+                let negative = match Self::condition(&component, -(id as isize)) {
+                    Some(clauses) => self.count_clauses(clauses, &rest),
+                    None => BigUint::zero(),
+                };
+                &positive + &negative
+            }
+        };
+
+        self.cache.insert(component, count.clone());
+        count
+    }
+
+    // Makes lit true, removing satisfied clauses and falsified literals
+    // Returns None if a clause becomes empty
+    fn condition(clauses: &[Vec<isize>], lit: isize) -> Option<Vec<Vec<isize>>> {
+        let mut conditioned = Vec::with_capacity(clauses.len());
+        for clause in clauses {
+            if clause.contains(&lit) {
+                continue;
+            }
+
+            let reduced = clause.iter().copied().filter(|other| *other != -lit).collect::<Vec<_>>();
+            if reduced.is_empty() {
+                return None;
+            }
+            conditioned.push(reduced);
+        }
+        Some(conditioned)
+    }
+
+    // Repeatedly conditions on unit clauses, returning the remaining clauses and the variables assigned
+    fn propagate_units(mut clauses: Vec<Vec<isize>>) -> Option<(Vec<Vec<isize>>, HashSet<usize>)> {
+        let mut assigned = HashSet::new();
+        if clauses.iter().any(|clause| clause.is_empty()) {
+            return None;
+        }
+
+        while let Some(unit) = clauses.iter().find(|clause| clause.len() == 1).map(|clause| clause[0]) {
+            assigned.insert(unit.unsigned_abs());
+            clauses = Self::condition(&clauses, unit)?;
+        }
+        Some((clauses, assigned))
+    }
+
+    // Splits clauses into groups that share no variables
+    fn components(clauses: Vec<Vec<isize>>) -> Vec<Vec<Vec<isize>>> {
+        // Union-find over variable ids
+        let mut parent: HashMap<usize, usize> = HashMap::new();
+        fn find(parent: &mut HashMap<usize, usize>, id: usize) -> usize {
+            let next = *parent.entry(id).or_insert(id);
+            if next == id {
+                return id;
+            }
+            let root = find(parent, next);
+            parent.insert(id, root);
+            root
+        }
+
+        for clause in clauses.iter() {
+            let first = find(&mut parent, clause[0].unsigned_abs());
+            for lit in clause[1..].iter() {
+                let root = find(&mut parent, lit.unsigned_abs());
+                parent.insert(root, first);
+            }
+        }
+
+        let mut groups: HashMap<usize, Vec<Vec<isize>>> = HashMap::new();
+        for clause in clauses {
+            let root = find(&mut parent, clause[0].unsigned_abs());
+            groups.entry(root).or_default().push(clause);
+        }
+
+        let mut components = groups.into_values().collect::<Vec<_>>();
+        components.sort_unstable();
+        components
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::sync::{Arc, LazyLock, Mutex, Weak};
+
+use crate::card::{self, CardEncoding, Comparison};
+use crate::expr::Expr;
+use crate::pb::{self, PbConstraint, PbEncoding};
+
+// A single level of an expression, with each operand replaced by a T
+// With T a reference this is a level of an Expr, and with T a literal it is a gate of a CNF
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Node<T> {
+    And(T, T),
+    Or(T, T),
+    Xor(T, T),
+    Iff(T, T),
+    Implies(T, T),
+    Nand(T, T),
+    Nor(T, T),
+    Not(T),
+    Card(Vec<T>, Comparison, usize, CardEncoding),
+    Pb(Vec<(i64, T)>, Comparison, i64, PbEncoding),
+    Literal(bool),
+    Var(String),
+}
+
+impl<T> Node<T> {
+    // Same node with every operand passed through f, in order
+    pub fn map<U>(&self, mut f: impl FnMut(&T) -> U) -> Node<U> {
+        match self {
+            Node::And(l, r) => { let l = f(l); Node::And(l, f(r)) }
+            Node::Or(l, r) => { let l = f(l); Node::Or(l, f(r)) }
+            Node::Xor(l, r) => { let l = f(l); Node::Xor(l, f(r)) }
+            Node::Iff(l, r) => { let l = f(l); Node::Iff(l, f(r)) }
+            Node::Implies(l, r) => { let l = f(l); Node::Implies(l, f(r)) }
+            Node::Nand(l, r) => { let l = f(l); Node::Nand(l, f(r)) }
+            Node::Nor(l, r) => { let l = f(l); Node::Nor(l, f(r)) }
+            Node::Not(expr) => Node::Not(f(expr)),
+            Node::Card(exprs, comparison, bound, encoding) => {
+                Node::Card(exprs.iter().map(f).collect(), *comparison, *bound, *encoding)
+            }
+            Node::Pb(terms, comparison, bound, encoding) => {
+                Node::Pb(terms.iter().map(|(weight, expr)| (*weight, f(expr))).collect(), *comparison, *bound, *encoding)
+            }
+            Node::Literal(value) => Node::Literal(*value),
+            Node::Var(name) => Node::Var(name.clone()),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<&T> {
+        match self {
+            Node::And(l, r) | Node::Or(l, r) | Node::Xor(l, r) | Node::Iff(l, r)
+            | Node::Implies(l, r) | Node::Nand(l, r) | Node::Nor(l, r) => vec![l, r],
+            Node::Not(expr) => vec![expr],
+            Node::Card(exprs, ..) => exprs.iter().collect(),
+            Node::Pb(terms, ..) => terms.iter().map(|(_, expr)| expr).collect(),
+            Node::Literal(_) | Node::Var(_) => Vec::new(),
+        }
+    }
+}
+
+impl Node<Expr> {
+    pub fn into_expr(self) -> Expr {
+        match self {
+            Node::And(l, r) => Expr::and(l, r),
+            Node::Or(l, r) => Expr::or(l, r),
+            Node::Xor(l, r) => Expr::xor(l, r),
+            Node::Iff(l, r) => Expr::iff(l, r),
+            Node::Implies(l, r) => Expr::eif(l, r),
+            Node::Nand(l, r) => Expr::nand(l, r),
+            Node::Nor(l, r) => Expr::nor(l, r),
+            Node::Not(expr) => Expr::not(expr),
+            Node::Card(exprs, comparison, bound, encoding) => Expr::cardinality(exprs, comparison, bound, encoding),
+            Node::Pb(terms, comparison, bound, encoding) => Expr::pseudo_boolean(terms, comparison, bound, encoding),
+            Node::Literal(value) => Expr::Literal(value),
+            Node::Var(name) => Expr::Var(name),
+        }
+    }
+}
+
+impl Node<isize> {
+    // Tseitin clauses making id equivalent to the gate over its operand literals
+    // Variables are not gates and have no clauses
+    pub fn tseitin(&self, id: isize, fresh: &mut impl FnMut() -> isize) -> Vec<Vec<isize>> {
+        match self {
+            Node::And(l, r) => vec![vec![-id, *l], vec![-id, *r], vec![id, -l, -r]],
+            Node::Or(l, r) => vec![vec![-id, *l, *r], vec![id, -l], vec![id, -r]],
+            Node::Xor(l, r) => vec![vec![-id, *l, *r], vec![-id, -l, -r], vec![id, -l, *r], vec![id, *l, -r]],
+            Node::Iff(l, r) => vec![vec![-id, -l, *r], vec![-id, *l, -r], vec![id, *l, *r], vec![id, -l, -r]],
+            Node::Implies(l, r) => vec![vec![-id, -l, *r], vec![id, *l], vec![id, -r]],
+            Node::Nand(l, r) => vec![vec![-id, -l, -r], vec![id, *l], vec![id, *r]],
+            Node::Nor(l, r) => vec![vec![-id, -l], vec![-id, -r], vec![id, *l, *r]],
+            Node::Not(expr) => vec![vec![-id, -expr], vec![id, *expr]],
+            Node::Card(lits, comparison, bound, encoding) => {
+                card::reified(id, lits, *comparison, *bound, *encoding, fresh)
+            }
+            Node::Pb(terms, comparison, bound, encoding) => {
+                let constraint = PbConstraint { terms: terms.clone(), comparison: *comparison, bound: *bound };
+                pb::reified(id, &constraint, *encoding, fresh)
+            }
+            Node::Literal(value) => vec![vec![if *value { id } else { -id }]],
+            Node::Var(_) => Vec::new(),
+        }
+    }
+}
+
+// Hash-consing table, through which structurally equal expressions become one shared node
+// Every constructor of Expr shares its operands here, so a node is looked up by the addresses of its
+// operands, which are already shared, and each lookup hashes one level rather than the whole subtree
+// Entries only hold the node weakly, the ones whose node was dropped are swept out as the table grows
+#[derive(Debug, Default)]
+struct Interner {
+    nodes: FastMap<Node<usize>, Weak<Expr>>,
+
+    // Number of entries past which the next insertion sweeps the table
+    sweep_at: usize,
+}
+
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(|| Mutex::new(Interner::default()));
+
+// The shared node equal to expr, which becomes the shared one if there is none
+// A live entry keeps its operands alive, so the addresses in its key can't have been reused
+pub fn share(expr: Arc<Expr>) -> Arc<Expr> {
+    let key = expr.key();
+    let mut interner = INTERNER.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(node) = interner.nodes.get(&key).and_then(Weak::upgrade) {
+        return node;
+    }
+
+    if interner.nodes.len() >= interner.sweep_at {
+        interner.nodes.retain(|_, node| node.strong_count() > 0);
+        interner.sweep_at = (2 * interner.nodes.len()).max(1024);
+    }
+    interner.nodes.insert(key, Arc::downgrade(&expr));
+    expr
+}
+
+// Multiplicative hash for keys made of a few integers, much cheaper than the default SipHash
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FastHasher(u64);
+
+impl Hasher for FastHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            self.write_u64(u64::from_le_bytes(word.try_into().unwrap()));
+        }
+        for byte in words.remainder() {
+            self.write_u64(*byte as u64);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.0 = (self.0.rotate_left(5) ^ value).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    fn write_isize(&mut self, value: isize) {
+        self.write_u64(value as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+pub type FastMap<K, V> = HashMap<K, V, BuildHasherDefault<FastHasher>>;
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::cnf::CNF;
+
+// Reads a CNF in the DIMACS format, one line at a time
+// Comments of the form "c var <id> <name>" name variables, the rest are ignored
+// Variables without a name are named by their id
+// A line starting with '%' ends the input, as in the SATLIB benchmarks
+pub fn read(reader: impl BufRead) -> Result<CNF, String> {
+    let mut header: Option<(usize, usize)> = None;
+    let mut names: HashMap<usize, (String, usize)> = HashMap::new();
+    let mut clauses = Vec::new();
+    let mut clause = Vec::new();
+
+    let mut line_number = 0;
+    for line in reader.lines() {
+        let line = line.map_err(|err| err.to_string())?;
+        line_number += 1;
+
+        let mut tokens = line.split_whitespace().peekable();
+        match tokens.peek() {
+            None => continue,
+            Some(token) if token.starts_with('%') => break,
+            Some(&"c") => {
+                tokens.next();
+                let (Some("var"), Some(id), Some(name), None) = (tokens.next(), tokens.next(), tokens.next(), tokens.next()) else {
+                    continue;
+                };
+                let Ok(id) = id.parse::<usize>() else {
+                    continue;
+                };
+                if let Some((other, other_line)) = names.insert(id, (name.to_string(), line_number)) {
+                    return Err(format!("Line {}: variable {} is already named '{}' on line {}", line_number, id, other, other_line));
+                }
+                continue;
+            }
+            Some(&"p") => {
+                if header.is_some() {
+                    return Err(format!("Line {}: duplicate problem line", line_number));
+                }
+                header = Some(parse_header(&line).map_err(|err| format!("Line {}: {}", line_number, err))?);
+                continue;
+            }
+            _ => {}
+        }
+
+        let Some((num_vars, _)) = header else {
+            return Err(format!("Line {}: clause before the 'p cnf' problem line", line_number));
+        };
+        for token in tokens {
+            let lit = token.parse::<isize>()
+                .map_err(|_| format!("Line {}: expected a literal, found '{}'", line_number, token))?;
+            if lit == 0 {
+                clauses.push(std::mem::take(&mut clause));
+            } else if lit.unsigned_abs() > num_vars {
+                return Err(format!("Line {}: literal {} is out of range for {} variables", line_number, lit, num_vars));
+            } else {
+                clause.push(lit);
+            }
+        }
+    }
+
+    let Some((num_vars, num_clauses)) = header else {
+        return Err("Missing 'p cnf' problem line".to_string());
+    };
+    if !clause.is_empty() {
+        return Err(format!("Line {}: last clause is missing its terminating 0", line_number));
+    }
+    if clauses.len() != num_clauses {
+        return Err(format!("Problem line declares {} clauses but {} were found", num_clauses, clauses.len()));
+    }
+
+    let mut symbol_table = (1..=num_vars).map(|id| id.to_string()).collect::<Vec<_>>();
+    let mut names = names.into_iter().collect::<Vec<_>>();
+    names.sort_by_key(|(_, (_, line))| *line);
+
+    let mut lines = HashMap::new();
+    for (id, (name, line)) in names {
+        if id == 0 || id > num_vars {
+            return Err(format!("Line {}: named variable {} is out of range for {} variables", line, id, num_vars));
+        }
+        symbol_table[id - 1] = name;
+        lines.insert(id, line);
+    }
+
+    // A name may also clash with the default name of a variable left unnamed, the error points at the
+    // later of the comments involved
+    let mut clashes = Vec::new();
+    let mut ids = HashMap::new();
+    for (idx, name) in symbol_table.iter().enumerate() {
+        if let Some(other) = ids.insert(name, idx + 1) {
+            let line = lines.get(&other).max(lines.get(&(idx + 1))).copied().unwrap_or_default();
+            clashes.push((line, name, other, idx + 1));
+        }
+    }
+    if let Some((line, name, other, id)) = clashes.into_iter().min() {
+        return Err(format!("Line {}: name '{}' is used by variables {} and {}", line, name, other, id));
+    }
+
+    Ok(CNF::new(symbol_table, clauses))
+}
+
+// Parses "p cnf <variables> <clauses>"
+fn parse_header(line: &str) -> Result<(usize, usize), String> {
+    let tokens = line.split_whitespace().collect::<Vec<_>>();
+    let [_, format, num_vars, num_clauses] = tokens[..] else {
+        return Err(format!("expected 'p cnf <variables> <clauses>', found '{}'", line.trim()));
+    };
+    if format != "cnf" {
+        return Err(format!("unsupported format '{}', expected 'cnf'", format));
+    }
+
+    let num_vars = num_vars.parse::<usize>()
+        .map_err(|_| format!("expected the number of variables, found '{}'", num_vars))?;
+    let num_clauses = num_clauses.parse::<usize>()
+        .map_err(|_| format!("expected the number of clauses, found '{}'", num_clauses))?;
+    Ok((num_vars, num_clauses))
+}
+
+// Writes a CNF in the DIMACS format, naming every variable whose name isn't its id in a comment
+pub fn write(cnf: &CNF, writer: &mut impl Write) -> io::Result<()> {
+    for (idx, name) in cnf.get_symbol_table().iter().enumerate() {
+        if *name != (idx + 1).to_string() {
+            writeln!(writer, "c var {} {}", idx + 1, name)?;
+        }
+    }
+
+    writeln!(writer, "p cnf {} {}", cnf.num_vars(), cnf.get_clauses().len())?;
+    for clause in cnf.get_clauses() {
+        for lit in clause {
+            write!(writer, "{} ", lit)?;
+        }
+        writeln!(writer, "0")?;
+    }
+    Ok(())
+}
@@ -1,194 +1,717 @@
-use std::collections::HashSet;
-use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::Arc;
 
+use crate::bdd::{self, Bdd};
+use crate::bignum::BigUint;
+use crate::card::{CardEncoding, Comparison};
+use crate::pb::PbEncoding;
 use crate::cnf::CNF;
-use crate::runtime::{env::Env, vm::VM};
+use crate::dag::{self, Node};
+use crate::minimize::{self, MinimizeMethod};
+use crate::models::Models;
+use crate::normal;
+use crate::simplify::Simplifier;
+use crate::solver::Solver;
+use crate::truth_table::{self, TruthTable};
+use crate::runtime::{env::Env, peephole, partial::{self, Partial}, program::Program, vm::{OpCode, VM}};
 use crate::lexer::Lexer;
 use crate::parser::Parser;
-use crate::runtime::vm::OpCode;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug)]
 #[allow(dead_code)]
 pub enum Expr {
     And(And),
     Or(Or),
+    Xor(Xor),
+    Iff(Iff),
+    Implies(Implies),
+    Nand(Nand),
+    Nor(Nor),
     Not(Not),
+    Card(Card),
+    Pb(Pb),
     Literal(bool),
     Var(String),
 }
 
+// Operands are shared through dag::share, so equal operands are the same node, and expressions
+// compare and hash by their top level and the addresses of their operands rather than whole subtrees
+impl PartialEq for Expr {
+    fn eq(&self, other: &Expr) -> bool {
+        match (self, other) {
+            (Expr::Var(l), Expr::Var(r)) => l == r,
+            (Expr::Literal(l), Expr::Literal(r)) => l == r,
+            _ => self.key() == other.key(),
+        }
+    }
+}
+
+impl Eq for Expr {}
+
+impl Hash for Expr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Expr::Var(name) => name.hash(state),
+            _ => self.key().hash(state),
+        }
+    }
+}
+
+// Ways of turning an expression into an equisatisfiable CNF
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CnfEncoding {
+    // Every gate variable is equivalent to its subformula
+    #[default]
+    Tseitin,
+
+    // Gate variables only imply, or are implied by, their subformula depending on the polarity it
+    // occurs with, and negations become negative literals rather than new variables
+    PlaistedGreenbaum,
+}
+
+impl FromStr for CnfEncoding {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<CnfEncoding, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "tseitin" => Ok(CnfEncoding::Tseitin),
+            "plaisted-greenbaum" | "pg" => Ok(CnfEncoding::PlaistedGreenbaum),
+            _ => Err(format!("Unknown CNF encoding '{}', expected 'tseitin' or 'plaisted-greenbaum'", name)),
+        }
+    }
+}
+
+// Decision procedures for tautology, contradiction and equivalence checks
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Backend {
+    // Looks for a counterexample in the Tseitin CNF with the solver
+    #[default]
+    Sat,
+
+    // Builds the reduced ordered BDD, which is canonical, so the answer is a comparison of handles
+    Bdd,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Backend, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "sat" => Ok(Backend::Sat),
+            "bdd" => Ok(Backend::Bdd),
+            _ => Err(format!("Unknown backend '{}', expected 'sat' or 'bdd'", name)),
+        }
+    }
+}
+
+// What a binary connective with one constant operand reduces to
+enum Folded {
+    Constant(bool),
+    // The other operand
+    Operand,
+    // The negation of the other operand
+    Negated,
+}
+
+// Order of an expression's variables in truth tables, models and symbol tables
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum VariableOrder {
+    // Order they first occur in, reading the expression left to right
+    #[default]
+    FirstOccurrence,
+    Alphabetical,
+    // Caller-supplied order, which has to hold every variable of the expression and may hold others
+    Given(Vec<String>),
+}
+
+impl FromStr for VariableOrder {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<VariableOrder, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "first-occurrence" | "first" => Ok(VariableOrder::FirstOccurrence),
+            "alphabetical" => Ok(VariableOrder::Alphabetical),
+            _ => Err(format!("Unknown variable order '{}', expected 'first-occurrence' or 'alphabetical'", name)),
+        }
+    }
+}
+
+// Literal of each node encoded by the Plaisted-Greenbaum encoding, at each polarity it was encoded with
+type Literals = HashMap<(*const Expr, Polarity), isize>;
+
+// Whether a subformula has to be made true, made false, or both, for the whole formula to hold
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Polarity {
+    Positive,
+    Negative,
+    Both,
+}
+
+impl Polarity {
+    fn flip(self) -> Polarity {
+        match self {
+            Polarity::Positive => Polarity::Negative,
+            Polarity::Negative => Polarity::Positive,
+            Polarity::Both => Polarity::Both,
+        }
+    }
+
+    fn positive(self) -> bool {
+        self != Polarity::Negative
+    }
+
+    fn negative(self) -> bool {
+        self != Polarity::Positive
+    }
+}
+
 #[allow(dead_code)]
 impl Expr {
     pub fn parse(src: &[u8]) -> Result<Expr, String> {
         Parser::from(Lexer::new(src)?).statement()
     }
 
-    pub fn get_variables_aux(&self, vars: &mut HashSet<String>) {
-        use Expr::*;
-        match self {
-            And(and) => {
-                and.l.get_variables_aux(vars);
-                and.r.get_variables_aux(vars);
-            }
+    // Variables in the order they first occur in
+    pub fn get_variables(&self) -> Vec<String> {
+        let mut vars = Vec::new();
+        self.get_variables_aux(&mut HashSet::new(), &mut HashSet::new(), &mut vars);
+        vars
+    }
 
-            Or(or) => {
-                or.l.get_variables_aux(vars);
-                or.r.get_variables_aux(vars);
+    // Visits each shared node once, visited holds the nodes seen so far
+    fn get_variables_aux<'a>(&'a self, visited: &mut HashSet<*const Expr>, seen: &mut HashSet<&'a str>, vars: &mut Vec<String>) {
+        if !visited.insert(self) {
+            return;
+        }
+        match self {
+            Expr::Var(name) => {
+                if seen.insert(name) {
+                    vars.push(name.clone());
+                }
             }
-            Not(not) => {
-                not.expr.get_variables_aux(vars);
+            _ => {
+                for operand in self.node().operands() {
+                    operand.get_variables_aux(visited, seen, vars);
+                }
             }
-            Literal(_) => {}
-            Var(name) => {
-                vars.insert(name.clone());
+        }
+    }
+
+    // Variables in the given order, an error if a given order misses one of them or repeats a name
+    pub fn variables(&self, order: &VariableOrder) -> Result<Vec<String>, String> {
+        let mut vars = self.get_variables();
+        match order {
+            VariableOrder::FirstOccurrence => {}
+            VariableOrder::Alphabetical => vars.sort(),
+            VariableOrder::Given(names) => {
+                if let Some(name) = vars.iter().find(|name| !names.contains(name)) {
+                    return Err(format!("Variable '{}' is missing from the order", name));
+                }
+                let mut seen = HashSet::new();
+                if let Some(name) = names.iter().find(|name| !seen.insert(*name)) {
+                    return Err(format!("Variable '{}' occurs more than once in the order", name));
+                }
+                vars = names.clone();
             }
         }
+        Ok(vars)
     }
 
-    pub fn get_variables(&self) -> Vec<String> {
-        let mut vars = HashSet::new();
-        self.get_variables_aux(&mut vars);
+    // Value under every assignment of the variables, with intermediate columns for the subformulas if asked for
+    // Limited to truth_table::DEFAULT_MAX_VARIABLES variables, TruthTable::new takes another limit
+    pub fn truth_table(&self, subformulas: bool, order: &VariableOrder) -> Result<TruthTable, String> {
+        TruthTable::new(self, subformulas, order, truth_table::DEFAULT_MAX_VARIABLES)
+    }
 
-        vars.into_iter().collect::<Vec<_>>()
+    // Equivalent expression rewritten with the built-in rules of Simplifier until none of them applies
+    pub fn simplify(&self) -> Expr {
+        Simplifier::new().simplify(self)
     }
 
-    fn truth_table_aux(expr: &Vec<OpCode>, i: usize, vars: &Vec<String>, env: &mut Env) {
-        if i >= vars.len() {
-            let result = if VM::new(env, expr.clone()).eval().unwrap() {
-                "T"
-            } else {
-                "F"
-            };
+    // Number of nodes in the expression tree, where a shared subexpression counts once per occurrence
+    // Each shared node is only measured once, and a tree too large to count gives usize::MAX
+    pub fn size(&self) -> usize {
+        self.size_aux(&mut HashMap::new())
+    }
 
-            let vars_list = vars.iter()
-                .map(|var| format!("{}={}", var, if env.get(var).unwrap() { "T" } else { "F" }))
-                .collect::<Vec<_>>()
-                .join(", ");
+    fn size_aux(&self, sizes: &mut HashMap<*const Expr, usize>) -> usize {
+        if let Some(size) = sizes.get(&(self as *const Expr)) {
+            return *size;
+        }
+        let size = self.node().operands().into_iter().fold(1, |size: usize, operand| size.saturating_add(operand.size_aux(sizes)));
+        sizes.insert(self, size);
+        size
+    }
 
-            print!("{vars_list}: {result}");
-            return;
+    // Number of nodes held in memory, where a subexpression shared by several operands counts once
+    pub fn dag_size(&self) -> usize {
+        let mut seen = HashSet::new();
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            if seen.insert(expr as *const Expr) {
+                stack.extend(expr.node().operands());
+            }
         }
+        seen.len()
+    }
 
-        let var = vars[i].clone();
+    // Equivalent expression in negation normal form, or an error if it would have more than max_size nodes
+    pub fn to_nnf(&self, max_size: usize) -> Result<Expr, String> {
+        normal::nnf(self, max_size)
+    }
 
-        env.define(var.clone(), false);
-        Self::truth_table_aux(expr, i + 1, vars, env);
+    // Equivalent conjunction of disjunctions, or an error if it would have more than max_size nodes
+    // Unlike the Tseitin transformation this adds no variables, but can be exponentially larger
+    pub fn to_cnf(&self, max_size: usize) -> Result<Expr, String> {
+        normal::cnf(self, max_size)
+    }
 
-        env.define(var.clone(), true);
-        Self::truth_table_aux(expr, i + 1, vars, env);
+    // Equivalent disjunction of conjunctions, or an error if it would have more than max_size nodes
+    pub fn to_dnf(&self, max_size: usize) -> Result<Expr, String> {
+        normal::dnf(self, max_size)
     }
 
-    pub fn truth_table(prop: String) -> Result<(), String> {
-        println!("{prop}:");
-        let expr = Expr::parse(prop.as_bytes())?;
-        let vars = expr.get_variables();
+    // Smallest sum of products equal to the expression wherever dont_care is false
+    pub fn minimize_sop(&self, dont_care: Option<&Expr>, method: MinimizeMethod) -> Result<Expr, String> {
+        minimize::sum_of_products(self, dont_care, method)
+    }
 
-        let mut env = Env::new();
-        Self::truth_table_aux(&expr.compile(), 0, &vars, &mut env);
-        Ok(())
+    // Smallest product of sums equal to the expression wherever dont_care is false
+    pub fn minimize_pos(&self, dont_care: Option<&Expr>, method: MinimizeMethod) -> Result<Expr, String> {
+        minimize::product_of_sums(self, dont_care, method)
     }
 
     // Converts expression into an equisatisfyable CNF via the tseitin transformation
     pub fn tseitin(&self, display: bool) -> CNF {
-        let mut cnf = CNF::new(Vec::new(), Vec::new());
-        let id = cnf.gen_var(self) as isize;
+        let mut cnf = Self::variables_cnf(&self.get_variables());
+        let id = self.tseitin_into(&mut cnf, display);
 
         cnf.enforce(id, true);       // Enforces that the entire expression is true
+        cnf
+    }
 
-        let cnf_refcell = RefCell::new(cnf);
-        self.tseitin_aux(id, &cnf_refcell, display);
+    // Adds the tseitin clauses of the expression to an existing CNF, reusing its variables
+    // Structurally equal subexpressions, including ones added earlier, share a single variable
+    // Returns the id of the variable equivalent to the expression, which is not enforced
+    pub fn tseitin_into(&self, cnf: &mut CNF, display: bool) -> isize {
+        let id = self.tseitin_aux(cnf, display, &mut HashMap::new());
         if display {
             println!();
         }
+        id
+    }
 
-        cnf = cnf_refcell.into_inner();
+    // Empty CNF whose first variables are the given ones, so the symbol table starts in their order
+    fn variables_cnf(vars: &[String]) -> CNF {
+        let mut cnf = CNF::new(Vec::new(), Vec::new());
+        for name in vars {
+            cnf.named_var(name);
+        }
         cnf
     }
 
-    // Performs a Tseitin transformation
-    // Takes its own id in the CNF, and a refrence to the CNF which we are building
-    // Mutate the CNF rather than returning a value
-    pub fn tseitin_aux(&self, id: isize, cnf: &RefCell<CNF>, display: bool) {
-        match self {
-            Expr::Var(name) => self.sub_var_name(name.clone(), id as usize, cnf),
-            Expr::Literal(value) => {
-                if display {
-                    print!("{}", if *value { "T" } else { "F" });
+    // Converts expression into an equisatisfyable CNF with the given encoding
+    pub fn encode(&self, encoding: CnfEncoding) -> CNF {
+        self.encode_ordered(encoding, &VariableOrder::FirstOccurrence).unwrap()
+    }
+
+    // Same as encode, with the named variables numbered in the given order
+    pub fn encode_ordered(&self, encoding: CnfEncoding, order: &VariableOrder) -> Result<CNF, String> {
+        let mut cnf = Self::variables_cnf(&self.variables(order)?);
+        let mut lits = HashMap::new();
+        match encoding {
+            CnfEncoding::Tseitin => {
+                let id = self.tseitin_into(&mut cnf, false);
+                cnf.enforce(id, true);
+                Ok(cnf)
+            }
+            CnfEncoding::PlaistedGreenbaum => {
+                // A conjunction at the top is enforced operand by operand, and a disjunction as one clause
+                let mut operands = Vec::new();
+                match self.junction(false) {
+                    Some((true, _)) => self.collect_operands(false, true, &mut operands, &mut HashSet::new()),
+                    Some((false, _)) => {
+                        self.collect_operands(false, false, &mut operands, &mut HashSet::new());
+                        let clause = Self::operand_lits(&operands, Polarity::Positive, &mut cnf, &mut lits);
+                        cnf.append_clause(clause);
+                        return Ok(cnf);
+                    }
+                    None => operands.push((self, false)),
                 }
-                cnf.borrow_mut().enforce(id, *value);
+
+                for lit in Self::operand_lits(&operands, Polarity::Positive, &mut cnf, &mut lits) {
+                    cnf.append_clause(vec![lit]);
+                }
+                Ok(cnf)
+            }
+        }
+    }
+
+    // Adds the Plaisted-Greenbaum clauses of the expression to an existing CNF, reusing its variables
+    // Returns a literal that implies the expression, so it may only be enforced, never assumed false
+    pub fn plaisted_greenbaum_into(&self, cnf: &mut CNF) -> isize {
+        self.plaisted_greenbaum_aux(Polarity::Positive, cnf, &mut HashMap::new())
+    }
+
+    // Views the expression, negated if asked, as a conjunction (true) or disjunction (false) of two
+    // operands, each of which may be negated
+    fn junction(&self, negated: bool) -> Option<(bool, [(&Expr, bool); 2])> {
+        let (is_and, operands) = match self {
+            Expr::And(And { l, r }) => (true, [(&**l, false), (&**r, false)]),
+            Expr::Or(Or { l, r }) => (false, [(&**l, false), (&**r, false)]),
+            Expr::Nand(Nand { l, r }) => (false, [(&**l, true), (&**r, true)]),
+            Expr::Nor(Nor { l, r }) => (true, [(&**l, true), (&**r, true)]),
+            Expr::Implies(Implies { l, r }) => (false, [(&**l, true), (&**r, false)]),
+            Expr::Not(not) => return not.expr.junction(!negated),
+            _ => return None,
+        };
+
+        // De Morgan: a negated conjunction is a disjunction of the negated operands
+        if negated {
+            Some((!is_and, operands.map(|(expr, negated)| (expr, !negated))))
+        } else {
+            Some((is_and, operands))
+        }
+    }
+
+    // Flattens nested conjunctions, or disjunctions, into a list of operands that may be negated
+    // An operand reached along several paths is listed once, seen holds the nodes visited so far
+    fn collect_operands<'a>(&'a self, negated: bool, is_and: bool, operands: &mut Vec<(&'a Expr, bool)>, seen: &mut HashSet<(*const Expr, bool)>) {
+        if !seen.insert((self, negated)) {
+            return;
+        }
+        match self.junction(negated) {
+            Some((kind, [l, r])) if kind == is_and => {
+                l.0.collect_operands(l.1, is_and, operands, seen);
+                r.0.collect_operands(r.1, is_and, operands, seen);
             }
-            Expr::Or(or) => or.tseitin(id, cnf, display),
-            Expr::And(and) => and.tseitin(id, cnf, display),
-            Expr::Not(not) => not.tseitin(id, cnf, display),
+            _ => operands.push((self, negated)),
         }
     }
 
-    // Substitute variable's temporary name for its actual name
-    pub fn sub_var_name(&self, name: String, id: usize, cnf: &RefCell<CNF>) {
-        cnf.borrow_mut().set_symbol_name(id, name);
+    // Encodes each operand at the polarity it occurs with, returning their literals
+    fn operand_lits(operands: &[(&Expr, bool)], polarity: Polarity, cnf: &mut CNF, lits: &mut Literals) -> Vec<isize> {
+        operands.iter()
+            .map(|(expr, negated)| {
+                if *negated {
+                    -expr.plaisted_greenbaum_aux(polarity.flip(), cnf, lits)
+                } else {
+                    expr.plaisted_greenbaum_aux(polarity, cnf, lits)
+                }
+            })
+            .collect()
+    }
+
+    // Literal of the expression at the polarity, lits holds the one of each node and polarity encoded so far
+    fn plaisted_greenbaum_aux(&self, polarity: Polarity, cnf: &mut CNF, lits: &mut Literals) -> isize {
+        if let Some(lit) = lits.get(&(self as *const Expr, polarity)) {
+            return *lit;
+        }
+        let lit = self.plaisted_greenbaum_gate(polarity, cnf, lits);
+        lits.insert((self, polarity), lit);
+        lit
+    }
+
+    fn plaisted_greenbaum_gate(&self, polarity: Polarity, cnf: &mut CNF, lits: &mut Literals) -> isize {
+        // Adds a gate variable with the clauses for the directions the polarity needs
+        fn gate(polarity: Polarity, cnf: &mut CNF, positive: &[Vec<isize>], negative: &[Vec<isize>]) -> isize {
+            cnf.directed_gate((positive, negative), polarity.positive(), polarity.negative())
+        }
+
+        // Chains of conjunctions or disjunctions, seen through negations, share one gate
+        if let Some((is_and, _)) = self.junction(false) {
+            let mut operands = Vec::new();
+            self.collect_operands(false, is_and, &mut operands, &mut HashSet::new());
+            let operand_lits = Self::operand_lits(&operands, polarity, cnf, lits);
+
+            let each = operand_lits.iter().map(|lit| vec![if is_and { *lit } else { -lit }]).collect::<Vec<_>>();
+            let all = vec![operand_lits.iter().map(|lit| if is_and { -lit } else { *lit }).collect::<Vec<_>>()];
+            return if is_and {
+                gate(polarity, cnf, &each, &all)
+            } else {
+                gate(polarity, cnf, &all, &each)
+            };
+        }
+
+        match self {
+            Expr::Var(_) => cnf.gen_var(self) as isize,
+            Expr::Literal(value) => cnf.gate(Node::Literal(*value), false),
+            Expr::Not(not) => -not.expr.plaisted_greenbaum_aux(polarity.flip(), cnf, lits),
+            Expr::Xor(Xor { l, r }) => {
+                let (l, r) = (l.plaisted_greenbaum_aux(Polarity::Both, cnf, lits), r.plaisted_greenbaum_aux(Polarity::Both, cnf, lits));
+                gate(polarity, cnf, &[vec![l, r], vec![-l, -r]], &[vec![-l, r], vec![l, -r]])
+            }
+            Expr::Iff(Iff { l, r }) => {
+                let (l, r) = (l.plaisted_greenbaum_aux(Polarity::Both, cnf, lits), r.plaisted_greenbaum_aux(Polarity::Both, cnf, lits));
+                gate(polarity, cnf, &[vec![-l, r], vec![l, -r]], &[vec![l, r], vec![-l, -r]])
+            }
+            // Counters are only encoded as full equivalences, so they share the Tseitin gates
+            Expr::Card(_) | Expr::Pb(_) => {
+                let node = self.node().map(|operand| operand.plaisted_greenbaum_aux(Polarity::Both, cnf, lits));
+                cnf.gate(node, false)
+            }
+            Expr::And(_) | Expr::Or(_) | Expr::Nand(_) | Expr::Nor(_) | Expr::Implies(_) => unreachable!(),
+        }
+    }
+
+    // Enumerates the assignments of the expression's variables that make it true
+    pub fn models(&self) -> Models {
+        self.tseitin(false).models()
+    }
+
+    // Number of assignments of the expression's variables that make it true
+    pub fn count_models(&self) -> BigUint {
+        self.tseitin(false).count_models()
+    }
+
+    // True under every assignment of the expression's variables
+    pub fn is_tautology(&self, backend: Backend) -> bool {
+        match backend {
+            Backend::Sat => Expr::not(self.clone()).tseitin(false).find_evidence().is_none(),
+            Backend::Bdd => Bdd::new(Vec::new()).build(self) == bdd::TRUE,
+        }
+    }
+
+    // False under every assignment of the expression's variables
+    pub fn is_contradiction(&self, backend: Backend) -> bool {
+        match backend {
+            Backend::Sat => self.clone().tseitin(false).find_evidence().is_none(),
+            Backend::Bdd => Bdd::new(Vec::new()).build(self) == bdd::FALSE,
+        }
+    }
+
+    // Same value as other under every assignment
+    pub fn is_equivalent(&self, other: &Expr, backend: Backend) -> bool {
+        match backend {
+            Backend::Sat => Expr::not(Expr::iff(self.clone(), other.clone())).is_contradiction(Backend::Sat),
+            Backend::Bdd => {
+                let mut bdd = Bdd::new(Vec::new());
+                bdd.build(self) == bdd.build(other)
+            }
+        }
+    }
+
+    // Finds a minimal set of premises that contradicts the conclusion, or that is inconsistent
+    // on its own if there is no conclusion, returned as indices into premises
+    // Returns None if the argument is invalid or the premises are consistent
+    pub fn premise_core(premises: &[Expr], conclusion: Option<&Expr>) -> Option<Vec<usize>> {
+        let mut cnf = CNF::new(Vec::new(), Vec::new());
+
+        // Each premise is assumed through its own selector rather than enforced, so the solver can say which
+        // ones it needed, even when equal premises share one Tseitin variable
+        let selectors = premises.iter()
+            .map(|premise| {
+                let root = premise.tseitin_into(&mut cnf, false);
+                let selector = cnf.gen_aux_var() as isize;
+                cnf.append_clause(vec![-selector, root]);
+                selector
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(conclusion) = conclusion {
+            let id = conclusion.tseitin_into(&mut cnf, false);
+            cnf.enforce(id, false);
+        }
+
+        let core = Solver::from_cnf(&cnf).unsat_core(&selectors)?;
+        Some((0..premises.len()).filter(|idx| core.contains(&selectors[*idx])).collect())
+    }
+
+    // Performs a Tseitin transformation bottom up, so each gate is keyed by the variables of its operands
+    // Returns the id of the variable equivalent to the expression, ids holds the one of each node encoded so far
+    fn tseitin_aux(&self, cnf: &mut CNF, display: bool, ids: &mut HashMap<*const Expr, isize>) -> isize {
+        // Spares node from copying the name
+        if let Expr::Var(name) = self {
+            return cnf.named_var(name) as isize;
+        }
+        if let Some(id) = ids.get(&(self as *const Expr)) {
+            return *id;
+        }
+        let node = self.node().map(|operand| operand.tseitin_aux(cnf, display, ids));
+        let id = cnf.gate(node, display);
+        ids.insert(self, id);
+        id
+    }
+
+    // Top level of the expression with each operand replaced by its address, which identifies it
+    // since equal operands are shared
+    pub(crate) fn key(&self) -> Node<usize> {
+        self.node().map(|operand| *operand as *const Expr as usize)
+    }
+
+    // Top level of the expression, with its operands left in place
+    pub fn node(&self) -> Node<&Expr> {
+        match self {
+            Expr::And(And { l, r }) => Node::And(l, r),
+            Expr::Or(Or { l, r }) => Node::Or(l, r),
+            Expr::Xor(Xor { l, r }) => Node::Xor(l, r),
+            Expr::Iff(Iff { l, r }) => Node::Iff(l, r),
+            Expr::Implies(Implies { l, r }) => Node::Implies(l, r),
+            Expr::Nand(Nand { l, r }) => Node::Nand(l, r),
+            Expr::Nor(Nor { l, r }) => Node::Nor(l, r),
+            Expr::Not(not) => Node::Not(&not.expr),
+            Expr::Card(card) => Node::Card(card.exprs.iter().map(|expr| &**expr).collect(), card.comparison, card.bound, card.encoding),
+            Expr::Pb(pb) => {
+                let terms = pb.terms.iter().map(|(weight, expr)| (*weight, &**expr)).collect();
+                Node::Pb(terms, pb.comparison, pb.bound, pb.encoding)
+            }
+            Expr::Literal(value) => Node::Literal(*value),
+            Expr::Var(name) => Node::Var(name.clone()),
+        }
     }
 
     // Create "and" expression
     pub fn and(l: Expr, r: Expr) -> Expr {
-        Expr::And(And::new(Box::new(l), Box::new(r)))
+        Expr::And(And::new(Arc::new(l), Arc::new(r)))
     }
 
     // Create "or" expression
     pub fn or(l: Expr, r: Expr) -> Expr {
-        Expr::Or(Or::new(Box::new(l), Box::new(r)))
+        Expr::Or(Or::new(Arc::new(l), Arc::new(r)))
     }
 
     // Create "not" expression
     pub fn not(subexpr: Expr) -> Expr {
-        Expr::Not(Not::new(Box::new(subexpr)))
+        Expr::Not(Not::new(Arc::new(subexpr)))
+    }
+
+    // Create "at most bound of exprs are true" expression
+    pub fn at_most(exprs: Vec<Expr>, bound: usize) -> Expr {
+        Expr::cardinality(exprs, Comparison::AtMost, bound, CardEncoding::default())
+    }
+
+    // Create "at least bound of exprs are true" expression
+    pub fn at_least(exprs: Vec<Expr>, bound: usize) -> Expr {
+        Expr::cardinality(exprs, Comparison::AtLeast, bound, CardEncoding::default())
+    }
+
+    // Create "exactly bound of exprs are true" expression
+    pub fn exactly(exprs: Vec<Expr>, bound: usize) -> Expr {
+        Expr::cardinality(exprs, Comparison::Exactly, bound, CardEncoding::default())
+    }
+
+    // Create cardinality expression, encoded into CNF with the given encoding
+    pub fn cardinality(exprs: Vec<Expr>, comparison: Comparison, bound: usize, encoding: CardEncoding) -> Expr {
+        Expr::Card(Card::new(exprs, comparison, bound, encoding))
+    }
+
+    // Create pseudo-Boolean expression comparing the weighted sum of the true exprs with bound
+    pub fn pseudo_boolean(terms: Vec<(i64, Expr)>, comparison: Comparison, bound: i64, encoding: PbEncoding) -> Expr {
+        Expr::Pb(Pb::new(terms, comparison, bound, encoding))
     }
 
     // Create "if" expression
     pub fn eif(l: Expr, r: Expr) -> Expr {
-        Expr::or(Expr::not(l), r)
+        Expr::Implies(Implies::new(Arc::new(l), Arc::new(r)))
     }
 
     // Create "iff" expression
     pub fn iff(l: Expr, r: Expr) -> Expr {
-        Expr::and(Expr::eif(l.clone(), r.clone()), Expr::eif(r, l))
+        Expr::Iff(Iff::new(Arc::new(l), Arc::new(r)))
     }
 
     // Create "xor" expression
     pub fn xor(l: Expr, r: Expr) -> Expr {
-        Expr::and(Expr::or(l.clone(), r.clone()), Expr::not(Expr::and(l, r)))
+        Expr::Xor(Xor::new(Arc::new(l), Arc::new(r)))
     }
 
     // Create "nand" expression
     pub fn nand(l: Expr, r: Expr) -> Expr {
-        Expr::not(Expr::and(l, r))
+        Expr::Nand(Nand::new(Arc::new(l), Arc::new(r)))
     }
 
     // Create "nor" expression
     pub fn nor(l: Expr, r: Expr) -> Expr {
-        Expr::not(Expr::or(l, r))
+        Expr::Nor(Nor::new(Arc::new(l), Arc::new(r)))
     }
 
+    // Value if the defined variables decide it, otherwise the simplified expression over the ones still needed
+    pub fn partial_eval(&self, env: &Env) -> Partial {
+        partial::eval(self, env)
+    }
+
+    // Compiled once, to be evaluated against any number of environments
+    pub fn program(&self) -> Program {
+        Program::new(self.compile())
+    }
+
+    // Postfix program with short-circuit jumps, where constant operands are folded away
     pub fn compile(&self) -> Vec<OpCode> {
-        let mut codes = Vec::new();
-        self.compile_aux(&mut codes);
-        codes
+        Compilation::new(self, true).run(self)
+    }
+
+    // Postfix program that evaluates every operand that isn't folded away, keeping each connective's own operator
+    // Needed under Lukasiewicz logic, where a -> b isn't the same as not a or b
+    pub fn compile_eager(&self) -> Vec<OpCode> {
+        Compilation::new(self, false).run(self)
+    }
+
+    pub fn program_eager(&self) -> Program {
+        Program::new(self.compile_eager())
     }
 
-    fn compile_aux(&self, codes: &mut Vec<OpCode>) {
+    // A shared node is computed where it is first reached and stored, and later occurrences recall it from its
+    // slot when that computation is sure to have run before them, otherwise they compute it again
+    fn compile_aux(&self, codes: &mut Vec<OpCode>, compilation: &mut Compilation) {
+        // Past the budget the program is thrown away, so anything will do
+        compilation.overflowed |= compilation.jumps && codes.len() > compilation.max_len;
+        if compilation.overflowed {
+            codes.push(OpCode::F);
+            return;
+        }
+
+        let ptr = self as *const Expr;
+        if !compilation.shared.contains(&ptr) {
+            return self.compile_node(codes, compilation);
+        }
+        if let Some(code) = compilation.recall(ptr) {
+            codes.push(code);
+            return;
+        }
+
+        let start = codes.len();
+        self.compile_node(codes, compilation);
+        let code = match Self::constant(codes, start) {
+            Some(value) => if value { OpCode::T } else { OpCode::F },
+            None => {
+                let slots = compilation.slots.len();
+                let slot = *compilation.slots.entry(ptr).or_insert(slots);
+                codes.push(OpCode::Store(slot));
+                OpCode::Recall(slot)
+            }
+        };
+        compilation.computed.insert(ptr, Computed { code, regions: compilation.regions.clone(), start });
+    }
+
+    fn compile_node(&self, codes: &mut Vec<OpCode>, compilation: &mut Compilation) {
+        let start = codes.len();
         match self {
             Expr::Not(not) => {
-                not.expr.compile_aux(codes);
+                not.expr.compile_aux(codes, compilation);
                 codes.push(OpCode::Not);
             }
-            Expr::And(and) => {
-                and.l.compile_aux(codes);
-                and.r.compile_aux(codes);
-                codes.push(OpCode::And);
+            Expr::And(And { l, r }) => Self::compile_binary(l, r, OpCode::And, codes, compilation),
+            Expr::Or(Or { l, r }) => Self::compile_binary(l, r, OpCode::Or, codes, compilation),
+            Expr::Xor(Xor { l, r }) => Self::compile_binary(l, r, OpCode::Xor, codes, compilation),
+            Expr::Iff(Iff { l, r }) => Self::compile_binary(l, r, OpCode::Iff, codes, compilation),
+            Expr::Implies(Implies { l, r }) => Self::compile_binary(l, r, OpCode::Implies, codes, compilation),
+            Expr::Nand(Nand { l, r }) => Self::compile_binary(l, r, OpCode::Nand, codes, compilation),
+            Expr::Nor(Nor { l, r }) => Self::compile_binary(l, r, OpCode::Nor, codes, compilation),
+            Expr::Card(card) => {
+                for expr in card.exprs.iter() {
+                    expr.compile_aux(codes, compilation);
+                }
+                codes.push(OpCode::Card(card.comparison, card.exprs.len(), card.bound));
             }
-            Expr::Or(and) => {
-                and.l.compile_aux(codes);
-                and.r.compile_aux(codes);
-                codes.push(OpCode::Or);
+            Expr::Pb(pb) => {
+                for (_, expr) in pb.terms.iter() {
+                    expr.compile_aux(codes, compilation);
+                }
+                let weights = pb.terms.iter().map(|(weight, _)| *weight).collect();
+                codes.push(OpCode::Pb(weights, pb.comparison, pb.bound));
             }
             Expr::Literal(b) => {
                 codes.push(if *b { OpCode::T } else { OpCode::F });
@@ -197,6 +720,104 @@ impl Expr {
                 codes.push(OpCode::Load(name.clone()));
             }
         }
+
+        // An operator over constants is a constant, which lets the operators above it fold in turn
+        if let Some(value) = peephole::fold(&codes[start..]) {
+            compilation.truncate(codes, start);
+            codes.push(if value { OpCode::T } else { OpCode::F });
+        }
+    }
+
+    // Value of the program from start on if it is a single constant
+    fn constant(codes: &[OpCode], start: usize) -> Option<bool> {
+        match &codes[start..] {
+            [OpCode::T] => Some(true),
+            [OpCode::F] => Some(false),
+            _ => None,
+        }
+    }
+
+    // What the operator reduces to when the left or right operand is the given constant
+    // The other operand is then not evaluated at all if the result doesn't depend on it
+    fn fold_operand(op: &OpCode, value: bool, left: bool) -> Folded {
+        match (op, value, left) {
+            (OpCode::And, false, _) | (OpCode::Nor, true, _) => Folded::Constant(false),
+            (OpCode::Or, true, _) | (OpCode::Nand, false, _) => Folded::Constant(true),
+            (OpCode::Implies, false, true) | (OpCode::Implies, true, false) => Folded::Constant(true),
+            (OpCode::And, true, _) | (OpCode::Or, false, _) | (OpCode::Xor, false, _) | (OpCode::Iff, true, _)
+            | (OpCode::Implies, true, true) => Folded::Operand,
+            (OpCode::Xor, true, _) | (OpCode::Iff, false, _) | (OpCode::Nand, true, _) | (OpCode::Nor, false, _)
+            | (OpCode::Implies, false, false) => Folded::Negated,
+            _ => unreachable!(),
+        }
+    }
+
+    fn compile_binary(l: &Expr, r: &Expr, op: OpCode, codes: &mut Vec<OpCode>, compilation: &mut Compilation) {
+        let start = codes.len();
+        l.compile_aux(codes, compilation);
+        if let Some(value) = Self::constant(codes, start) {
+            compilation.truncate(codes, start);
+            match Self::fold_operand(&op, value, true) {
+                Folded::Constant(value) => codes.push(if value { OpCode::T } else { OpCode::F }),
+                Folded::Operand => r.compile_aux(codes, compilation),
+                Folded::Negated => {
+                    r.compile_aux(codes, compilation);
+                    codes.push(OpCode::Not);
+                }
+            }
+            return;
+        }
+
+        // The left-hand side of And, Or and the connectives built on them decides the result half the time,
+        // in which case the jump skips the right-hand side, leaving the left-hand side as the value
+        let l_end = codes.len();
+        let jump = match op {
+            _ if !compilation.jumps => None,
+            OpCode::And | OpCode::Nand => Some(OpCode::JumpIfFalse(0)),
+            OpCode::Or | OpCode::Nor => Some(OpCode::JumpIfTrue(0)),
+            OpCode::Implies => {
+                codes.push(OpCode::Not);
+                Some(OpCode::JumpIfTrue(0))
+            }
+            _ => None,
+        };
+        let jump_at = codes.len();
+        let short_circuit = jump.is_some();
+        codes.extend(jump);
+
+        // Whatever the right-hand side computes behind the jump may not have run once past it
+        let r_start = codes.len();
+        if short_circuit {
+            compilation.regions.push(r_start);
+        }
+        r.compile_aux(codes, compilation);
+        if short_circuit {
+            compilation.regions.pop();
+        }
+        if let Some(value) = Self::constant(codes, r_start) {
+            compilation.truncate(codes, l_end);
+            match Self::fold_operand(&op, value, false) {
+                Folded::Constant(value) => {
+                    compilation.truncate(codes, start);
+                    codes.push(if value { OpCode::T } else { OpCode::F });
+                }
+                Folded::Operand => {}
+                Folded::Negated => codes.push(OpCode::Not),
+            }
+            return;
+        }
+
+        // Without a jump, Nand and Nor are their positive operator followed by the Not
+        let end = codes.len();
+        match (&mut codes[jump_at], &op) {
+            (OpCode::JumpIfFalse(target) | OpCode::JumpIfTrue(target), _) if short_circuit => *target = end,
+            (_, OpCode::Nand) => codes.push(OpCode::And),
+            (_, OpCode::Nor) => codes.push(OpCode::Or),
+            _ => codes.push(op.clone()),
+        }
+        if matches!(op, OpCode::Nand | OpCode::Nor) {
+            codes.push(OpCode::Not);
+        }
     }
 
     pub fn is_valid_sat_proof(&self, proof: &Vec<bool>, symbol_table: &Vec<String>) -> bool {
@@ -209,106 +830,295 @@ impl Expr {
             env.define(symbol, *val);
         }
 
-        let mut vm = VM::new(&mut env, self.compile());
+        let mut vm = VM::new(&env, self.compile());
 
         vm.eval().unwrap_or(false)
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct And {
-    l: Box<Expr>,       // left-hand side
-    r: Box<Expr>,       // right-hand side
+// Instructions per operand a program may take, each operand is a few at most unless shared nodes are computed again
+const MAX_PROGRAM_GROWTH: usize = 8;
+
+// State of compiling an expression, where each subexpression reached along more than one path is
+// computed where it is first reached, and recalled from its slot wherever that computation has run
+struct Compilation {
+    // Whether And, Or and the connectives built on them skip the right-hand side when the left decides them
+    jumps: bool,
+
+    // Nodes that are operands of more than one node, or twice of the same one
+    shared: HashSet<*const Expr>,
+
+    // Last computation of each shared node, and the slot each one is stored in
+    computed: HashMap<*const Expr, Computed>,
+    slots: HashMap<*const Expr, usize>,
+
+    // Right-hand sides of jumps being compiled, outermost first, each identified by its first instruction
+    regions: Vec<usize>,
+
+    // Computing shared nodes again can make the program exponentially longer than the expression
+    // Past this length the program is compiled without jumps instead, where every computation is recalled
+    max_len: usize,
+    overflowed: bool,
 }
 
-impl And {
-    pub fn new(l: Box<Expr>, r: Box<Expr>) -> And {
-        And {l, r}
+// Instruction standing for a shared node after its computation, a constant or the Recall of its slot,
+// along with the regions the computation is in and the index of its first instruction
+struct Computed {
+    code: OpCode,
+    regions: Vec<usize>,
+    start: usize,
+}
+
+impl Compilation {
+    fn new(expr: &Expr, jumps: bool) -> Compilation {
+        let mut references = HashMap::<*const Expr, (&Expr, usize)>::new();
+        let mut edges = 0;
+        let mut stack = vec![expr];
+        while let Some(expr) = stack.pop() {
+            for operand in expr.node().operands() {
+                edges += 1;
+                let (_, count) = references.entry(*operand).or_insert((operand, 0));
+                *count += 1;
+                if *count == 1 {
+                    stack.push(operand);
+                }
+            }
+        }
+
+        // Variables and literals are single instructions anyway
+        let shared = references.into_iter()
+            .filter(|(_, (expr, count))| *count > 1 && !matches!(expr, Expr::Var(_) | Expr::Literal(_)))
+            .map(|(ptr, _)| ptr)
+            .collect();
+        Compilation {
+            jumps,
+            shared,
+            computed: HashMap::new(),
+            slots: HashMap::new(),
+            regions: Vec::new(),
+            max_len: MAX_PROGRAM_GROWTH * (edges + 1),
+            overflowed: false,
+        }
     }
 
-    pub fn tseitin(&self, id: isize, cnf: &RefCell<CNF>, display: bool) {
-        let (l_id, r_id) = {
-            let mut cnf_ref = cnf.borrow_mut();
+    fn run(mut self, expr: &Expr) -> Vec<OpCode> {
+        let mut codes = Vec::new();
+        expr.compile_aux(&mut codes, &mut self);
+        if self.overflowed {
+            return Compilation::new(expr, false).run(expr);
+        }
+        peephole::optimize(codes)
+    }
 
-            let l_id = cnf_ref.gen_var(&self.l) as isize;
-            let r_id = cnf_ref.gen_var(&self.r) as isize;
+    // Instruction standing for a shared node, if its last computation runs before whatever is compiled now
+    // That is the case when the computation isn't behind a jump that the code compiled now isn't also behind
+    fn recall(&self, ptr: *const Expr) -> Option<OpCode> {
+        self.computed.get(&ptr)
+            .filter(|computed| self.regions.starts_with(&computed.regions))
+            .map(|computed| computed.code.clone())
+    }
+
+    // Drops the instructions from len on, along with the computations among them
+    fn truncate(&mut self, codes: &mut Vec<OpCode>, len: usize) {
+        codes.truncate(len);
+        self.computed.retain(|_, computed| computed.start < len);
+    }
+}
 
-            if display {
-                print!("cnf({} <-> ({} or {}))) ", cnf_ref.from_id(id), cnf_ref.from_id(l_id), cnf_ref.from_id(r_id));
+// Prints expressions in the syntax the parser reads, with only the parentheses it needs
+// Cardinality and pseudo-Boolean constraints have no syntax, and are always parenthesized when nested
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::And(And { l, r }) => self.fmt_binary(f, l, "and", r),
+            Expr::Or(Or { l, r }) => self.fmt_binary(f, l, "or", r),
+            Expr::Xor(Xor { l, r }) => self.fmt_binary(f, l, "xor", r),
+            Expr::Iff(Iff { l, r }) => self.fmt_binary(f, l, "<->", r),
+            Expr::Implies(Implies { l, r }) => self.fmt_binary(f, l, "->", r),
+            Expr::Nand(Nand { l, r }) => self.fmt_binary(f, l, "nand", r),
+            Expr::Nor(Nor { l, r }) => self.fmt_binary(f, l, "nor", r),
+            Expr::Not(not) => {
+                write!(f, "not ")?;
+                not.expr.fmt_operand(f, u8::MAX)
+            }
+            Expr::Card(card) => {
+                let exprs = card.exprs.iter().map(|expr| expr.to_string()).collect::<Vec<_>>();
+                write!(f, "{} {} of ({})", card.comparison, card.bound, exprs.join(", "))
             }
+            Expr::Pb(pb) => {
+                for (idx, (weight, expr)) in pb.terms.iter().enumerate() {
+                    match (idx, *weight < 0) {
+                        (0, _) => write!(f, "{} ", weight)?,
+                        (_, false) => write!(f, " + {} ", weight)?,
+                        (_, true) => write!(f, " - {} ", -weight)?,
+                    }
+                    expr.fmt_operand(f, u8::MAX)?;
+                }
+                if pb.terms.is_empty() {
+                    write!(f, "0")?;
+                }
+                write!(f, " {} {}", pb.comparison.operator(), pb.bound)
+            }
+            Expr::Literal(value) => write!(f, "{}", if *value { "T" } else { "F" }),
+            Expr::Var(name) => write!(f, "{}", name),
+        }
+    }
+}
 
-            cnf_ref.append_clause(vec![-id, l_id]);
-            cnf_ref.append_clause(vec![-id, r_id]);
-            cnf_ref.append_clause(vec![id, -l_id, -r_id]);
+impl Expr {
+    // How tightly the parser binds the expression's top-level operator, variables and literals bind tightest
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Card(_) | Expr::Pb(_) => 0,
+            Expr::Iff(_) => 1,
+            Expr::Implies(_) => 2,
+            Expr::Or(_) => 3,
+            Expr::Xor(_) => 4,
+            Expr::And(_) => 5,
+            Expr::Nand(_) | Expr::Nor(_) => 6,
+            Expr::Not(_) => 7,
+            Expr::Literal(_) | Expr::Var(_) => u8::MAX,
+        }
+    }
 
-            (l_id, r_id)
-        };
+    // Prints the expression, parenthesized unless it binds at least as tightly as precedence
+    fn fmt_operand(&self, f: &mut fmt::Formatter<'_>, precedence: u8) -> fmt::Result {
+        if self.precedence() >= precedence {
+            write!(f, "{}", self)
+        } else {
+            write!(f, "({})", self)
+        }
+    }
+
+    // Binary operators associate to the left, so the right operand has to bind more tightly
+    fn fmt_binary(&self, f: &mut fmt::Formatter<'_>, l: &Expr, op: &str, r: &Expr) -> fmt::Result {
+        l.fmt_operand(f, self.precedence())?;
+        write!(f, " {} ", op)?;
+        r.fmt_operand(f, self.precedence() + 1)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct And {
+    l: Arc<Expr>,       // left-hand side
+    r: Arc<Expr>,       // right-hand side
+}
 
-        self.l.tseitin_aux(l_id, cnf, display);
-        self.r.tseitin_aux(r_id, cnf, display);
+impl And {
+    pub fn new(l: Arc<Expr>, r: Arc<Expr>) -> And {
+        And { l: dag::share(l), r: dag::share(r) }
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug)]
 pub struct Or {
-    l: Box<Expr>,       // left-hand side
-    r: Box<Expr>,       // right-hand side
+    l: Arc<Expr>,       // left-hand side
+    r: Arc<Expr>,       // right-hand side
 }
 
 impl Or {
-    pub fn new(l: Box<Expr>, r: Box<Expr>) -> Or {
-        Or {l, r}
+    pub fn new(l: Arc<Expr>, r: Arc<Expr>) -> Or {
+        Or { l: dag::share(l), r: dag::share(r) }
     }
+}
 
-    pub fn tseitin(&self, id: isize, cnf: &RefCell<CNF>, display: bool) {
-        let (l_id, r_id) = {
-            let mut cnf_ref = cnf.borrow_mut();
+#[derive(Clone, Debug)]
+pub struct Xor {
+    l: Arc<Expr>,       // left-hand side
+    r: Arc<Expr>,       // right-hand side
+}
 
-            let l_id = cnf_ref.gen_var(&self.l) as isize;
-            let r_id = cnf_ref.gen_var(&self.r) as isize;
+impl Xor {
+    pub fn new(l: Arc<Expr>, r: Arc<Expr>) -> Xor {
+        Xor { l: dag::share(l), r: dag::share(r) }
+    }
+}
 
-            if display {
-                print!("cnf({} <-> ({} or {}))) ", cnf_ref.from_id(id), cnf_ref.from_id(l_id), cnf_ref.from_id(r_id));
-            }
+#[derive(Clone, Debug)]
+pub struct Iff {
+    l: Arc<Expr>,       // left-hand side
+    r: Arc<Expr>,       // right-hand side
+}
 
-            cnf_ref.append_clause(vec![-id, l_id, r_id]);
-            cnf_ref.append_clause(vec![id, -l_id]);
-            cnf_ref.append_clause(vec![id, -r_id]);
+impl Iff {
+    pub fn new(l: Arc<Expr>, r: Arc<Expr>) -> Iff {
+        Iff { l: dag::share(l), r: dag::share(r) }
+    }
+}
 
-            (l_id, r_id)
-        };
+#[derive(Clone, Debug)]
+pub struct Implies {
+    l: Arc<Expr>,       // left-hand side
+    r: Arc<Expr>,       // right-hand side
+}
 
-        self.l.tseitin_aux(l_id, cnf, display);
-        self.r.tseitin_aux(r_id, cnf, display);
+impl Implies {
+    pub fn new(l: Arc<Expr>, r: Arc<Expr>) -> Implies {
+        Implies { l: dag::share(l), r: dag::share(r) }
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Not {
-    expr: Box<Expr>
+#[derive(Clone, Debug)]
+pub struct Nand {
+    l: Arc<Expr>,       // left-hand side
+    r: Arc<Expr>,       // right-hand side
 }
 
-impl Not {
-    pub fn new(expr: Box<Expr>) -> Not {
-        Not { expr }
+impl Nand {
+    pub fn new(l: Arc<Expr>, r: Arc<Expr>) -> Nand {
+        Nand { l: dag::share(l), r: dag::share(r) }
     }
+}
 
-    pub fn tseitin(&self, id: isize, cnf: &RefCell<CNF>, display: bool) {
-        let subexpr_id = {
-            let mut cnf_ref = cnf.borrow_mut();
+#[derive(Clone, Debug)]
+pub struct Nor {
+    l: Arc<Expr>,       // left-hand side
+    r: Arc<Expr>,       // right-hand side
+}
 
-            let subexpr_id = cnf_ref.gen_var(&self.expr) as isize;
+impl Nor {
+    pub fn new(l: Arc<Expr>, r: Arc<Expr>) -> Nor {
+        Nor { l: dag::share(l), r: dag::share(r) }
+    }
+}
 
-            if display {
-                print!("cnf({} <-> not {}) ", cnf_ref.from_id(id), cnf_ref.from_id(subexpr_id));
-            }
+#[derive(Clone, Debug)]
+pub struct Not {
+    expr: Arc<Expr>
+}
 
-            cnf_ref.append_clause(vec![-id, -subexpr_id]);
-            cnf_ref.append_clause(vec![id, subexpr_id]);
+impl Not {
+    pub fn new(expr: Arc<Expr>) -> Not {
+        Not { expr: dag::share(expr) }
+    }
+}
 
-            subexpr_id
-        };
+#[derive(Clone, Debug)]
+pub struct Card {
+    exprs: Vec<Arc<Expr>>,
+    comparison: Comparison,
+    bound: usize,
+    encoding: CardEncoding,
+}
+
+impl Card {
+    pub fn new(exprs: Vec<Expr>, comparison: Comparison, bound: usize, encoding: CardEncoding) -> Card {
+        let exprs = exprs.into_iter().map(|expr| dag::share(Arc::new(expr))).collect();
+        Card { exprs, comparison, bound, encoding }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Pb {
+    terms: Vec<(i64, Arc<Expr>)>,
+    comparison: Comparison,
+    bound: i64,
+    encoding: PbEncoding,
+}
 
-        self.expr.tseitin_aux(subexpr_id, cnf, display);
+impl Pb {
+    pub fn new(terms: Vec<(i64, Expr)>, comparison: Comparison, bound: i64, encoding: PbEncoding) -> Pb {
+        let terms = terms.into_iter().map(|(weight, expr)| (weight, dag::share(Arc::new(expr)))).collect();
+        Pb { terms, comparison, bound, encoding }
     }
 }
@@ -1,8 +1,25 @@
 #[cfg(test)]
+// The original tests predate these lints
+#[allow(unused_mut, clippy::nonminimal_bool, clippy::redundant_locals, clippy::unnecessary_mut_passed, clippy::useless_vec)]
 mod tests;
 
 pub mod expr;
 pub mod cnf;
+pub mod solver;
+pub mod proof;
+pub mod models;
+pub mod bignum;
+pub mod bdd;
+pub mod card;
+pub mod count;
+pub mod dag;
+pub mod dimacs;
+pub mod maxsat;
+pub mod minimize;
+pub mod normal;
+pub mod pb;
+pub mod simplify;
+pub mod truth_table;
 pub mod lexer;
 pub mod parser;
 pub mod runtime;
@@ -11,10 +28,49 @@ pub mod runtime;
 use pyo3::prelude::*;
 #[pymodule]
 pub mod easypls {
-    use pyo3::exceptions::PyException;
+    use pyo3::exceptions::{PyException, PyIndexError};
     use pyo3::prelude::*;
+    use pyo3::types::{PyDict, PyInt};
 
     use crate::cnf::CNF;
+    use crate::proof::ProofFormat;
+
+    fn parse_proof_format(format: &str) -> PyResult<ProofFormat> {
+        format.parse::<ProofFormat>().map_err(PyException::new_err)
+    }
+
+    use crate::models::Models;
+    use crate::bignum::BigUint;
+
+    // Python ints have arbitrary precision, so go through the decimal representation
+    fn to_py_int<'py>(py: Python<'py>, value: &BigUint) -> PyResult<Bound<'py, PyAny>> {
+        py.get_type::<PyInt>().call1((value.to_string(),))
+    }
+
+    // Lazy iterator over models, each given as a dict from variable name to value
+    #[pyclass(name="ModelIterator")]
+    struct PyModels {
+        models: Models,
+    }
+
+    #[pymethods]
+    impl PyModels {
+        fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+            slf
+        }
+
+        fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+            let Some(model) = self.models.next() else {
+                return Ok(None);
+            };
+
+            let dict = PyDict::new(py);
+            for (name, value) in self.models.names().iter().zip(model) {
+                dict.set_item(name, value)?;
+            }
+            Ok(Some(dict))
+        }
+    }
 
     #[pyclass(name="CNF")]
     struct PyCNF {
@@ -29,20 +85,281 @@ pub mod easypls {
 
     #[pymethods]
     impl PyCNF {
+        #[staticmethod]
+        fn from_dimacs(src: &str) -> PyResult<PyCNF> {
+            let cnf = CNF::from_dimacs(src).map_err(PyException::new_err)?;
+            Ok(PyCNF::new(cnf))
+        }
+
+        #[staticmethod]
+        fn read_dimacs(path: &str) -> PyResult<PyCNF> {
+            let file = std::io::BufReader::new(std::fs::File::open(path)?);
+            let cnf = CNF::read_dimacs(file).map_err(PyException::new_err)?;
+            Ok(PyCNF::new(cnf))
+        }
+
+        fn to_dimacs(&self) -> String {
+            self.cnf.to_dimacs()
+        }
+
+        fn write_dimacs(&self, path: &str) -> PyResult<()> {
+            let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+            self.cnf.write_dimacs(&mut writer)?;
+            Ok(())
+        }
+
+        // Reads the constraints of a pseudo-Boolean problem in the OPB format
+        #[staticmethod]
+        #[pyo3(signature = (src, encoding="bdd"))]
+        fn from_opb(src: &str, encoding: &str) -> PyResult<PyCNF> {
+            let encoding = encoding.parse::<PbEncoding>().map_err(PyException::new_err)?;
+            let cnf = CNF::from_opb(src, encoding).map_err(PyException::new_err)?;
+            Ok(PyCNF::new(cnf))
+        }
+
+        #[staticmethod]
+        #[pyo3(signature = (path, encoding="bdd"))]
+        fn read_opb(path: &str, encoding: &str) -> PyResult<PyCNF> {
+            PyCNF::from_opb(&std::fs::read_to_string(path)?, encoding)
+        }
+
         fn is_sat(&self) -> bool {
-            self.cnf.clone().find_evidence().is_some()
+            self.cnf.find_evidence().is_some()
         }
 
         fn sat_evidence(&self) -> Option<Vec<bool>> {
-            self.cnf.clone().find_evidence()
+            self.cnf.find_evidence()
+        }
+        fn get_symbol_table(&self) -> Vec<String> {
+            self.cnf.get_symbol_table()
+        }
+
+        #[pyo3(signature = (format="lrat"))]
+        fn refutation(&self, format: &str) -> PyResult<Option<String>> {
+            let format = parse_proof_format(format)?;
+            Ok(self.cnf.find_refutation().map(|proof| proof.to_text(format)))
+        }
+
+        // Writes a refutation to the file at path, returns false without writing if the CNF is SAT
+        #[pyo3(signature = (path, format="lrat"))]
+        fn write_refutation(&self, path: &str, format: &str) -> PyResult<bool> {
+            let format = parse_proof_format(format)?;
+            let Some(proof) = self.cnf.find_refutation() else {
+                return Ok(false);
+            };
+
+            let file = std::fs::File::create(path)?;
+            let mut writer = std::io::BufWriter::new(file);
+            proof.write(format, &mut writer)?;
+            Ok(true)
+        }
+
+        fn check_lrat(&self, proof: &str) -> bool {
+            self.cnf.check_lrat(proof).is_ok()
+        }
+
+        fn unsat_core(&self) -> Option<Vec<usize>> {
+            self.cnf.unsat_core()
+        }
+
+        // Counts models projected onto the given variables, or onto every named variable
+        #[pyo3(signature = (variables=None))]
+        fn count_models<'py>(&self, py: Python<'py>, variables: Option<Vec<String>>) -> PyResult<Bound<'py, PyAny>> {
+            let count = match variables {
+                Some(names) => self.cnf.count_models_over(&names).map_err(PyException::new_err)?,
+                None => self.cnf.count_models(),
+            };
+            to_py_int(py, &count)
+        }
+
+        // Enumerates models projected onto the given variables, or onto every named variable
+        #[pyo3(signature = (variables=None))]
+        fn models(&self, variables: Option<Vec<String>>) -> PyResult<PyModels> {
+            let models = match variables {
+                Some(names) => self.cnf.models_over(&names).map_err(PyException::new_err)?,
+                None => self.cnf.models(),
+            };
+            Ok(PyModels { models })
+        }
+    }
+
+    // Refutes the negation of expr, returning the CNF that was refuted along with the proof
+    fn certificate(expr: Expr, format: &str) -> PyResult<Option<(PyCNF, String)>> {
+        let format = parse_proof_format(format)?;
+        let cnf = Expr::not(expr).tseitin(false);
+
+        Ok(cnf.find_refutation().map(|proof| {
+            let text = proof.to_text(format);
+            (PyCNF::new(cnf), text)
+        }))
+    }
+
+
+    use crate::solver::Solver;
+
+    // Incremental solver that keeps learned clauses between calls
+    // Expressions added to it share one symbol table, so each is only transformed once
+    #[pyclass(name="Solver")]
+    struct PySolver {
+        cnf: CNF,
+        solver: Solver,
+        synced: usize,          // Number of clauses of cnf already given to the solver
+    }
+
+    impl PySolver {
+        // Passes clauses added to the CNF since the last sync on to the solver
+        fn sync(&mut self) {
+            let clauses = self.cnf.get_clauses();
+            for clause in clauses[self.synced..].iter() {
+                self.solver.append_clause(clause.clone());
+            }
+            self.synced = clauses.len();
+            self.solver.reserve_vars(self.cnf.num_vars());
+        }
+
+        fn check_literals(&self, lits: &[isize]) -> PyResult<()> {
+            check_literals(&self.cnf, lits)
+        }
+    }
+
+    fn check_literals(cnf: &CNF, lits: &[isize]) -> PyResult<()> {
+        for lit in lits {
+            if *lit == 0 || lit.unsigned_abs() > cnf.num_vars() {
+                return Err(PyException::new_err(format!("Literal {} does not refer to a variable", lit)));
+            }
+        }
+        Ok(())
+    }
+
+    #[pymethods]
+    impl PySolver {
+        #[new]
+        #[pyo3(signature = (cnf=None))]
+        fn new(cnf: Option<&PyCNF>) -> PySolver {
+            let cnf = cnf.map(|cnf| cnf.cnf.clone()).unwrap_or(CNF::new(Vec::new(), Vec::new()));
+            let mut solver = PySolver { cnf, solver: Solver::new(0), synced: 0 };
+            solver.sync();
+            solver
+        }
+
+        fn append_clause(&mut self, clause: Vec<isize>) -> PyResult<()> {
+            self.check_literals(&clause)?;
+            self.cnf.append_clause(clause);
+            self.sync();
+            Ok(())
+        }
+
+        // Requires the expression to be true in every later solve
+        fn add_expr(&mut self, expr: &PyExpr) {
+            let id = expr.expr.tseitin_into(&mut self.cnf, false);
+            self.cnf.enforce(id, true);
+            self.sync();
+        }
+
+        // Returns a literal equivalent to the expression, which can be used as an assumption
+        fn literal(&mut self, expr: &PyExpr) -> isize {
+            let id = expr.expr.tseitin_into(&mut self.cnf, false);
+            self.sync();
+            id
+        }
+
+        #[pyo3(signature = (assumptions=Vec::new()))]
+        fn solve(&mut self, assumptions: Vec<isize>) -> PyResult<bool> {
+            Ok(self.sat_evidence(assumptions)?.is_some())
+        }
+
+        #[pyo3(signature = (assumptions=Vec::new()))]
+        fn sat_evidence(&mut self, assumptions: Vec<isize>) -> PyResult<Option<Vec<bool>>> {
+            self.check_literals(&assumptions)?;
+            Ok(self.solver.solve_with_assumptions(&assumptions))
+        }
+
+        // Assumptions that together made the last solve UNSAT
+        fn failed_assumptions(&self) -> Vec<isize> {
+            self.solver.failed_assumptions().to_vec()
+        }
+
+        // Minimal subset of the assumptions under which the solver is UNSAT, None if it is SAT
+        fn unsat_core(&mut self, assumptions: Vec<isize>) -> PyResult<Option<Vec<isize>>> {
+            self.check_literals(&assumptions)?;
+            Ok(self.solver.unsat_core(&assumptions))
         }
+
         fn get_symbol_table(&self) -> Vec<String> {
             self.cnf.get_symbol_table()
         }
     }
 
+    use crate::maxsat::MaxSat;
+
+    // Weighted MaxSAT problem built from hard and weighted soft constraints
+    #[pyclass(name="MaxSAT")]
+    struct PyMaxSat {
+        maxsat: MaxSat,
+    }
+
+    #[pymethods]
+    impl PyMaxSat {
+        #[new]
+        fn new() -> PyMaxSat {
+            PyMaxSat { maxsat: MaxSat::new(CNF::new(Vec::new(), Vec::new())) }
+        }
+
+        fn add_hard(&mut self, expr: &PyExpr) {
+            self.maxsat.add_hard_expr(&expr.expr);
+        }
+
+        fn add_soft(&mut self, expr: &PyExpr, weight: u64) {
+            self.maxsat.add_soft_expr(&expr.expr, weight);
+        }
+
+        fn add_hard_clause(&mut self, clause: Vec<isize>) -> PyResult<()> {
+            check_literals(self.maxsat.get_cnf(), &clause)?;
+            self.maxsat.add_hard(clause);
+            Ok(())
+        }
+
+        fn add_soft_clause(&mut self, clause: Vec<isize>, weight: u64) -> PyResult<()> {
+            check_literals(self.maxsat.get_cnf(), &clause)?;
+            self.maxsat.add_soft(clause, weight);
+            Ok(())
+        }
+
+        // Literal equivalent to an expression, for use in clauses
+        fn literal(&mut self, expr: &PyExpr) -> isize {
+            self.maxsat.literal(&expr.expr)
+        }
+
+        // Returns an optimal assignment of the named variables and its cost, or None if the hard constraints are UNSAT
+        fn solve<'py>(&self, py: Python<'py>) -> PyResult<Option<(Bound<'py, PyDict>, u64)>> {
+            let Some(solution) = self.maxsat.solve() else {
+                return Ok(None);
+            };
+
+            let cnf = self.maxsat.get_cnf();
+            let dict = PyDict::new(py);
+            for id in cnf.named_vars() {
+                dict.set_item(cnf.from_id(id as isize), solution.model[id - 1])?;
+            }
+            Ok(Some((dict, solution.cost)))
+        }
+    }
+
+    use std::collections::HashMap;
+    use crate::expr::{Backend, CnfEncoding, Expr, VariableOrder};
+    use crate::runtime::batch::BatchVM;
 
-    use crate::expr::Expr;
+    // Variable order from Python, either "first-occurrence", "alphabetical" or a list of names
+    fn variable_order(order: Option<&Bound<'_, PyAny>>) -> PyResult<VariableOrder> {
+        match order {
+            None => Ok(VariableOrder::FirstOccurrence),
+            Some(order) => match order.extract::<String>() {
+                Ok(name) => name.parse::<VariableOrder>().map_err(PyException::new_err),
+                Err(_) => Ok(VariableOrder::Given(order.extract::<Vec<String>>()?)),
+            },
+        }
+    }
+    use crate::minimize::MinimizeMethod;
     #[pyclass(name="Expr")]
     #[derive(Clone)]
     struct PyExpr {
@@ -62,19 +379,32 @@ pub mod easypls {
         #[classattr]
         const F: PyExpr = PyExpr { expr: Expr::Literal(false) };
 
-        pub fn is_tautology(&self) -> bool {
-            !Expr::not(self.expr.clone()).tseitin(false).find_evidence().is_some()
+        #[pyo3(signature = (backend="sat"))]
+        pub fn is_tautology(&self, backend: &str) -> PyResult<bool> {
+            let backend = backend.parse::<Backend>().map_err(PyException::new_err)?;
+            Ok(self.expr.is_tautology(backend))
         }
 
-        pub fn is_contradiction(&self) -> bool {
-            !self.expr.clone().tseitin(false).find_evidence().is_some()
+        #[pyo3(signature = (backend="sat"))]
+        pub fn is_contradiction(&self, backend: &str) -> PyResult<bool> {
+            let backend = backend.parse::<Backend>().map_err(PyException::new_err)?;
+            Ok(self.expr.is_contradiction(backend))
         }
 
-        pub fn is_logically_eq(&self, other: &PyExpr) -> bool {
-            !Expr::not(Expr::iff(self.expr.clone(), other.expr.clone()))
-                .tseitin(false)
-                .find_evidence()
-                .is_some()
+        #[pyo3(signature = (other, backend="sat"))]
+        pub fn is_logically_eq(&self, other: &PyExpr, backend: &str) -> PyResult<bool> {
+            let backend = backend.parse::<Backend>().map_err(PyException::new_err)?;
+            Ok(self.expr.is_equivalent(&other.expr, backend))
+        }
+
+        #[pyo3(signature = (format="lrat"))]
+        fn tautology_certificate(&self, format: &str) -> PyResult<Option<(PyCNF, String)>> {
+            certificate(self.expr.clone(), format)
+        }
+
+        #[pyo3(signature = (other, format="lrat"))]
+        fn equivalence_certificate(&self, other: &PyExpr, format: &str) -> PyResult<Option<(PyCNF, String)>> {
+            certificate(Expr::iff(self.expr.clone(), other.expr.clone()), format)
         }
 
         #[staticmethod]
@@ -106,6 +436,34 @@ pub mod easypls {
             Ok(PyExpr::new(Expr::Var(name)))
         }
 
+        #[staticmethod]
+        #[pyo3(name="AtMost", signature = (exprs, bound, encoding="totalizer"))]
+        fn at_most(exprs: Vec<PyExpr>, bound: usize, encoding: &str) -> PyResult<PyExpr> {
+            cardinality(exprs, Comparison::AtMost, bound, encoding)
+        }
+
+        #[staticmethod]
+        #[pyo3(name="AtLeast", signature = (exprs, bound, encoding="totalizer"))]
+        fn at_least(exprs: Vec<PyExpr>, bound: usize, encoding: &str) -> PyResult<PyExpr> {
+            cardinality(exprs, Comparison::AtLeast, bound, encoding)
+        }
+
+        #[staticmethod]
+        #[pyo3(name="Exactly", signature = (exprs, bound, encoding="totalizer"))]
+        fn exactly(exprs: Vec<PyExpr>, bound: usize, encoding: &str) -> PyResult<PyExpr> {
+            cardinality(exprs, Comparison::Exactly, bound, encoding)
+        }
+
+        // Weighted sum of the true exprs compared with bound, e.g. PB([(3, a), (2, b)], "<=", 4)
+        #[staticmethod]
+        #[pyo3(name="PB", signature = (terms, comparison, bound, encoding="bdd"))]
+        fn pseudo_boolean(terms: Vec<(i64, PyExpr)>, comparison: &str, bound: i64, encoding: &str) -> PyResult<PyExpr> {
+            let comparison = comparison.parse::<Comparison>().map_err(PyException::new_err)?;
+            let encoding = encoding.parse::<PbEncoding>().map_err(PyException::new_err)?;
+            let terms = terms.into_iter().map(|(weight, expr)| (weight, expr.expr)).collect();
+            Ok(PyExpr::new(Expr::pseudo_boolean(terms, comparison, bound, encoding)))
+        }
+
         #[staticmethod]
         #[pyo3(name="If")]
         fn eif(l: Bound<'_, PyExpr>, r: Bound<'_, PyExpr>) -> PyResult<PyExpr> {
@@ -130,6 +488,12 @@ pub mod easypls {
             Ok(PyExpr::new(Expr::xor(l, r)))
         }
 
+        #[staticmethod]
+        #[pyo3(name="Xor")]
+        fn xor_capitalized(l: Bound<'_, PyExpr>, r: Bound<'_, PyExpr>) -> PyResult<PyExpr> {
+            PyExpr::xor(l, r)
+        }
+
         #[staticmethod]
         #[pyo3(name="nand")]
         fn nand(l: Bound<'_, PyExpr>, r: Bound<'_, PyExpr>) -> PyResult<PyExpr> {
@@ -138,6 +502,12 @@ pub mod easypls {
             Ok(PyExpr::new(Expr::nand(l, r)))
         }
 
+        #[staticmethod]
+        #[pyo3(name="Nand")]
+        fn nand_capitalized(l: Bound<'_, PyExpr>, r: Bound<'_, PyExpr>) -> PyResult<PyExpr> {
+            PyExpr::nand(l, r)
+        }
+
         #[staticmethod]
         #[pyo3(name="nor")]
         fn nor(l: Bound<'_, PyExpr>, r: Bound<'_, PyExpr>) -> PyResult<PyExpr> {
@@ -146,32 +516,336 @@ pub mod easypls {
             Ok(PyExpr::new(Expr::nor(l, r)))
         }
 
+        #[staticmethod]
+        #[pyo3(name="Nor")]
+        fn nor_capitalized(l: Bound<'_, PyExpr>, r: Bound<'_, PyExpr>) -> PyResult<PyExpr> {
+            PyExpr::nor(l, r)
+        }
+
         #[staticmethod]
         fn parse(src: String) -> PyResult<PyExpr> {
             let expr = Expr::parse(src.as_bytes()).map_err(|msg| PyException::new_err(msg))?;
             Ok(PyExpr::new(expr))
         }
 
-        fn tseitin(&self) -> PyCNF {
-            PyCNF::new(self.expr.tseitin(false))
+        // Models are dicts over the variables in the given order, unless variables are given
+        #[pyo3(signature = (variables=None, order=None))]
+        fn models(&self, variables: Option<Vec<String>>, order: Option<&Bound<'_, PyAny>>) -> PyResult<PyModels> {
+            let cnf = self.expr.encode_ordered(CnfEncoding::Tseitin, &variable_order(order)?).map_err(PyException::new_err)?;
+            PyCNF::new(cnf).models(variables)
+        }
+
+        // Value under each record, a dict from variable name to value, evaluated 64 records at a time
+        fn eval_batch(&self, records: Vec<HashMap<String, bool>>) -> PyResult<Vec<bool>> {
+            let mut vm = BatchVM::new(self.expr.compile());
+            let rows = records.iter()
+                .map(|record| vm.variables().iter()
+                    .map(|name| record.get(name).copied().ok_or(format!("Undefined variable '{}'", name)))
+                    .collect::<Result<Vec<_>, _>>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(PyException::new_err)?;
+            vm.eval_rows(&rows).map_err(PyException::new_err)
+        }
+
+        #[pyo3(signature = (order=None))]
+        fn get_variables(&self, order: Option<&Bound<'_, PyAny>>) -> PyResult<Vec<String>> {
+            self.expr.variables(&variable_order(order)?).map_err(PyException::new_err)
+        }
+
+        fn count_models<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+            to_py_int(py, &self.expr.count_models())
+        }
+
+        fn __str__(&self) -> String {
+            self.expr.to_string()
+        }
+
+        #[pyo3(signature = (subformulas=false, order=None, max_variables=truth_table::DEFAULT_MAX_VARIABLES))]
+        fn truth_table(&self, subformulas: bool, order: Option<&Bound<'_, PyAny>>, max_variables: usize) -> PyResult<PyTruthTable> {
+            let table = TruthTable::new(&self.expr, subformulas, &variable_order(order)?, max_variables).map_err(PyException::new_err)?;
+            Ok(PyTruthTable { table })
+        }
+
+        fn __repr__(&self) -> String {
+            format!("Expr({})", self.expr)
+        }
+
+        // The symbol table, and so sat_evidence, starts with the variables in the given order
+        #[pyo3(signature = (order=None))]
+        fn tseitin(&self, order: Option<&Bound<'_, PyAny>>) -> PyResult<PyCNF> {
+            self.encode("tseitin", order)
+        }
+
+        // Converts to CNF with the given encoding, "tseitin" or "plaisted-greenbaum"
+        #[pyo3(signature = (encoding="tseitin", order=None))]
+        fn encode(&self, encoding: &str, order: Option<&Bound<'_, PyAny>>) -> PyResult<PyCNF> {
+            let encoding = encoding.parse::<CnfEncoding>().map_err(PyException::new_err)?;
+            let cnf = self.expr.encode_ordered(encoding, &variable_order(order)?).map_err(PyException::new_err)?;
+            Ok(PyCNF::new(cnf))
         }
 
         fn display_tseitin(&self) -> PyCNF {
             PyCNF::new(self.expr.tseitin(true))
         }
+
+        fn simplify(&self) -> PyExpr {
+            PyExpr::new(self.expr.simplify())
+        }
+
+        // Number of nodes in the expression tree
+        fn size(&self) -> usize {
+            self.expr.size()
+        }
+
+        // Number of nodes in memory, with shared subexpressions counted once
+        fn dag_size(&self) -> usize {
+            self.expr.dag_size()
+        }
+
+        #[pyo3(signature = (max_size=10000))]
+        fn to_nnf(&self, max_size: usize) -> PyResult<PyExpr> {
+            self.expr.to_nnf(max_size).map(PyExpr::new).map_err(PyException::new_err)
+        }
+
+        #[pyo3(signature = (max_size=10000))]
+        fn to_cnf(&self, max_size: usize) -> PyResult<PyExpr> {
+            self.expr.to_cnf(max_size).map(PyExpr::new).map_err(PyException::new_err)
+        }
+
+        // Smallest sum of products equal to the expression wherever dont_care is false
+        #[pyo3(signature = (dont_care=None, method="auto"))]
+        fn minimize_sop(&self, dont_care: Option<PyExpr>, method: &str) -> PyResult<PyExpr> {
+            let method = method.parse::<MinimizeMethod>().map_err(PyException::new_err)?;
+            let dont_care = dont_care.map(|dont_care| dont_care.expr);
+            self.expr.minimize_sop(dont_care.as_ref(), method).map(PyExpr::new).map_err(PyException::new_err)
+        }
+
+        // Smallest product of sums equal to the expression wherever dont_care is false
+        #[pyo3(signature = (dont_care=None, method="auto"))]
+        fn minimize_pos(&self, dont_care: Option<PyExpr>, method: &str) -> PyResult<PyExpr> {
+            let method = method.parse::<MinimizeMethod>().map_err(PyException::new_err)?;
+            let dont_care = dont_care.map(|dont_care| dont_care.expr);
+            self.expr.minimize_pos(dont_care.as_ref(), method).map(PyExpr::new).map_err(PyException::new_err)
+        }
+
+        #[pyo3(signature = (max_size=10000))]
+        fn to_dnf(&self, max_size: usize) -> PyResult<PyExpr> {
+            self.expr.to_dnf(max_size).map(PyExpr::new).map_err(PyException::new_err)
+        }
+    }
+
+    use std::sync::{Arc, Mutex};
+    use crate::simplify::Simplifier;
+
+    // Rewrites expressions with the built-in rules, if asked for, and any rules added from Python
+    #[pyclass(name="Simplifier")]
+    struct PySimplifier {
+        simplifier: Simplifier,
+
+        // First exception raised by a Python rule, rules are skipped once there is one
+        error: Arc<Mutex<Option<PyErr>>>,
+    }
+
+    #[pymethods]
+    impl PySimplifier {
+        #[new]
+        #[pyo3(signature = (builtin=true))]
+        fn new(builtin: bool) -> PySimplifier {
+            let simplifier = if builtin { Simplifier::new() } else { Simplifier::empty() };
+            PySimplifier { simplifier, error: Arc::new(Mutex::new(None)) }
+        }
+
+        // Adds a rule replacing every match of pattern, whose variables match any subexpression, by replacement
+        fn add_pattern(&mut self, pattern: &str, replacement: &str) -> PyResult<()> {
+            let pattern = Expr::parse(pattern.as_bytes()).map_err(PyException::new_err)?;
+            let replacement = Expr::parse(replacement.as_bytes()).map_err(PyException::new_err)?;
+            self.simplifier.add_pattern(pattern, replacement).map_err(PyException::new_err)
+        }
+
+        // Adds a rule calling rule with an Expr, which returns the Expr to replace it with or None
+        fn add_rule(&mut self, name: &str, rule: Py<PyAny>) {
+            let error = self.error.clone();
+            self.simplifier.add_rule(name, move |expr| Python::attach(|py| {
+                if error.lock().unwrap().is_some() {
+                    return None;
+                }
+
+                let rewritten = rule.call1(py, (PyExpr::new(expr.clone()),))
+                    .and_then(|rewritten| rewritten.bind(py).extract::<Option<PyExpr>>().map_err(PyErr::from));
+                match rewritten {
+                    Ok(rewritten) => rewritten.map(|rewritten| rewritten.expr),
+                    Err(err) => {
+                        error.lock().unwrap().get_or_insert(err);
+                        None
+                    }
+                }
+            }));
+        }
+
+        fn remove_rule(&mut self, name: &str) -> PyResult<()> {
+            self.simplifier.remove_rule(name).map_err(PyException::new_err)
+        }
+
+        fn rules(&self) -> Vec<String> {
+            self.simplifier.rule_names()
+        }
+
+        fn simplify(&self, expr: &PyExpr) -> PyResult<PyExpr> {
+            let simplified = self.simplifier.simplify(&expr.expr);
+            match self.error.lock().unwrap().take() {
+                Some(err) => Err(err),
+                None => Ok(PyExpr::new(simplified)),
+            }
+        }
+    }
+
+    use crate::bdd::{self, Bdd, BddOp, BddRef};
+
+    // Reduced ordered BDDs, handed to Python as integer handles where 0 is false and 1 is true
+    // Equal handles mean equivalent functions
+    #[pyclass(name="BDD")]
+    struct PyBdd {
+        bdd: Bdd,
+    }
+
+    impl PyBdd {
+        fn check(&self, fs: &[BddRef]) -> PyResult<()> {
+            match fs.iter().find(|f| !self.bdd.contains(**f)) {
+                Some(f) => Err(PyException::new_err(format!("Unknown BDD node {}", f))),
+                None => Ok(()),
+            }
+        }
+    }
+
+    #[pymethods]
+    impl PyBdd {
+        #[new]
+        #[pyo3(signature = (order=None))]
+        fn new(order: Option<Vec<String>>) -> PyBdd {
+            PyBdd { bdd: Bdd::new(order.unwrap_or_default()) }
+        }
+
+        #[classattr]
+        const FALSE: BddRef = bdd::FALSE;
+
+        #[classattr]
+        const TRUE: BddRef = bdd::TRUE;
+
+        fn order(&self) -> Vec<String> {
+            self.bdd.order().to_vec()
+        }
+
+        fn var(&mut self, name: &str) -> BddRef {
+            self.bdd.var(name)
+        }
+
+        fn build(&mut self, expr: &PyExpr) -> BddRef {
+            self.bdd.build(&expr.expr)
+        }
+
+        #[pyo3(name="not_")]
+        fn not(&mut self, f: BddRef) -> PyResult<BddRef> {
+            self.check(&[f])?;
+            Ok(self.bdd.not(f))
+        }
+
+        fn apply(&mut self, op: &str, f: BddRef, g: BddRef) -> PyResult<BddRef> {
+            let op = op.parse::<BddOp>().map_err(PyException::new_err)?;
+            self.check(&[f, g])?;
+            Ok(self.bdd.apply(op, f, g))
+        }
+
+        fn ite(&mut self, f: BddRef, g: BddRef, h: BddRef) -> PyResult<BddRef> {
+            self.check(&[f, g, h])?;
+            Ok(self.bdd.ite(f, g, h))
+        }
+
+        fn restrict(&mut self, f: BddRef, name: &str, value: bool) -> PyResult<BddRef> {
+            self.check(&[f])?;
+            Ok(self.bdd.restrict(f, name, value))
+        }
+
+        fn exists(&mut self, f: BddRef, names: Vec<String>) -> PyResult<BddRef> {
+            self.check(&[f])?;
+            Ok(self.bdd.exists(f, &names))
+        }
+
+        fn forall(&mut self, f: BddRef, names: Vec<String>) -> PyResult<BddRef> {
+            self.check(&[f])?;
+            Ok(self.bdd.forall(f, &names))
+        }
+
+        // Counts assignments of every variable in the order
+        fn count_models<'py>(&self, py: Python<'py>, f: BddRef) -> PyResult<Bound<'py, PyAny>> {
+            self.check(&[f])?;
+            to_py_int(py, &self.bdd.count_models(f))
+        }
+
+        fn size(&self, roots: Vec<BddRef>) -> PyResult<usize> {
+            self.check(&roots)?;
+            Ok(self.bdd.size(&roots))
+        }
+
+        // All three return the roots in the new table, every other handle becomes invalid
+        fn collect(&mut self, roots: Vec<BddRef>) -> PyResult<Vec<BddRef>> {
+            self.check(&roots)?;
+            Ok(self.bdd.collect(&roots))
+        }
+
+        fn reorder(&mut self, order: Vec<String>, roots: Vec<BddRef>) -> PyResult<Vec<BddRef>> {
+            self.check(&roots)?;
+            self.bdd.reorder(order, &roots).map_err(PyException::new_err)
+        }
+
+        fn sift(&mut self, roots: Vec<BddRef>) -> PyResult<Vec<BddRef>> {
+            self.check(&roots)?;
+            Ok(self.bdd.sift(&roots))
+        }
+    }
+
+    use crate::card::{CardEncoding, Comparison};
+    use crate::pb::PbEncoding;
+    fn cardinality(exprs: Vec<PyExpr>, comparison: Comparison, bound: usize, encoding: &str) -> PyResult<PyExpr> {
+        let encoding = encoding.parse::<CardEncoding>().map_err(PyException::new_err)?;
+        let exprs = exprs.into_iter().map(|expr| expr.expr).collect();
+        Ok(PyExpr::new(Expr::cardinality(exprs, comparison, bound, encoding)))
     }
 
-    use crate::runtime::{vm::VM, env::Env};
+    use crate::runtime::{program::Program, env::Env, partial::Partial, ternary::Logic};
     #[pyclass(name="Engine")]
+    #[derive(Default)]
     pub struct PyEngine {
         env: Env,
+
+        // Three-valued logic where undefined variables are unknown, otherwise they are an error
+        logic: Option<Logic>,
     }   
 
+    impl PyEngine {
+        fn program(&self, expr: &Expr) -> Program {
+            if self.logic == Some(Logic::Lukasiewicz) {
+                expr.program_eager()
+            } else {
+                expr.program()
+            }
+        }
+
+        // A bool, or under a three-valued logic None when the value is unknown
+        fn eval_program<'py>(&self, py: Python<'py>, program: &Program, env: &Env) -> PyResult<Bound<'py, PyAny>> {
+            match self.logic {
+                Some(logic) => Ok(program.eval_ternary(env, logic).known().into_pyobject(py)?),
+                None => Ok(program.eval(env).map_err(PyException::new_err)?.into_pyobject(py)?.to_owned().into_any()),
+            }
+        }
+    }
+
     #[pymethods]
     impl PyEngine {
         #[new]
-        pub fn new() -> PyEngine {
-            PyEngine { env: Env::new() }
+        #[pyo3(signature = (logic=None))]
+        pub fn new(logic: Option<&str>) -> PyResult<PyEngine> {
+            let logic = logic.map(|logic| logic.parse::<Logic>()).transpose().map_err(PyException::new_err)?;
+            Ok(PyEngine { logic, ..PyEngine::default() })
         }
 
         fn define(&mut self, name: String, val: bool) {
@@ -182,28 +856,205 @@ pub mod easypls {
             self.env.undefine(&name);
         }
 
-        fn eval(&mut self, expr: Bound<'_, PyExpr>) -> PyResult<bool> {
+        // Compiles expr on every call, compile keeps the program for expressions evaluated repeatedly
+        fn eval<'py>(&self, py: Python<'py>, expr: Bound<'py, PyExpr>) -> PyResult<Bound<'py, PyAny>> {
             let expr = expr.extract::<PyExpr>().unwrap();
-            let mut vm = VM::new(&mut self.env, expr.expr.compile());
+            let program = self.program(&expr.expr);
+            self.eval_program(py, &program, &self.env)
+        }
 
-            vm.eval().map_err(|msg| PyException::new_err(msg))
+        // Value of expr if the defined variables decide it, otherwise the simplified Expr over the ones still needed
+        fn partial_eval<'py>(&self, py: Python<'py>, expr: &PyExpr) -> PyResult<Bound<'py, PyAny>> {
+            match expr.expr.partial_eval(&self.env) {
+                Partial::Value(value) => Ok(value.into_pyobject(py)?.to_owned().into_any()),
+                Partial::Residual(residual) => Ok(Bound::new(py, PyExpr::new(residual))?.into_any()),
+            }
+        }
+
+        // Program that evaluates expr against this engine's variables each time it is called
+        fn compile(slf: &Bound<'_, Self>, expr: &PyExpr) -> PyProgram {
+            let program = slf.borrow().program(&expr.expr);
+            PyProgram { expr: expr.expr.clone(), program, engine: slf.clone().unbind() }
         }
     }
 
-    #[pyfunction]
-    fn display_truth_table(prop: String) -> PyResult<()> {
-        Expr::truth_table(prop)
-            .map_err(|msg| PyException::new_err(msg))
+    // Compiled expression, called with no arguments to use its engine's variables or with a dict of them
+    #[pyclass(name="Program")]
+    struct PyProgram {
+        expr: Expr,
+        program: Program,
+        engine: Py<PyEngine>,
+    }
+
+    #[pymethods]
+    impl PyProgram {
+        #[pyo3(signature = (values=None))]
+        fn __call__<'py>(&self, py: Python<'py>, values: Option<HashMap<String, bool>>) -> PyResult<Bound<'py, PyAny>> {
+            let engine = self.engine.borrow(py);
+            match values {
+                Some(values) => {
+                    let mut env = Env::new();
+                    for (name, value) in values {
+                        env.define(name, value);
+                    }
+                    engine.eval_program(py, &self.program, &env)
+                }
+                None => engine.eval_program(py, &self.program, &engine.env),
+            }
+        }
+
+        #[getter]
+        fn expr(&self) -> PyExpr {
+            PyExpr::new(self.expr.clone())
+        }
+
+        fn __repr__(&self) -> String {
+            format!("Program({})", self.expr)
+        }
     }
 
     #[pyfunction]
-    fn is_valid_argument(premises: Vec<PyExpr>, conclusion: PyExpr) -> bool {
+    #[pyo3(signature = (prop, order=None))]
+    fn display_truth_table(prop: String, order: Option<&Bound<'_, PyAny>>) -> PyResult<()> {
+        let order = variable_order(order)?;
+        let table = Expr::parse(prop.as_bytes()).and_then(|expr| expr.truth_table(false, &order)).map_err(PyException::new_err)?;
+        print!("{}", table);
+        Ok(())
+    }
+
+    use crate::truth_table::{self, TableFormat, TruthTable};
+
+    // Rows are dicts from column header to value, in column order
+    #[pyclass(name="TruthTable")]
+    struct PyTruthTable {
+        table: TruthTable,
+    }
+
+    impl PyTruthTable {
+        fn row<'py>(&self, py: Python<'py>, idx: usize) -> PyResult<Bound<'py, PyDict>> {
+            let dict = PyDict::new(py);
+            for (column, value) in self.table.columns().into_iter().zip(self.table.row(idx).values()) {
+                dict.set_item(column, value)?;
+            }
+            Ok(dict)
+        }
+    }
+
+    #[pymethods]
+    impl PyTruthTable {
+        #[getter]
+        fn expr(&self) -> PyExpr {
+            PyExpr::new(self.table.expr.clone())
+        }
+
+        #[getter]
+        fn variables(&self) -> Vec<String> {
+            self.table.variables.clone()
+        }
+
+        #[getter]
+        fn subformulas(&self) -> Vec<PyExpr> {
+            self.table.subformulas.iter().cloned().map(PyExpr::new).collect()
+        }
+
+        #[getter]
+        fn columns(&self) -> Vec<String> {
+            self.table.columns()
+        }
+
+        // Value of the expression in each row
+        #[getter]
+        fn results(&self) -> Vec<bool> {
+            (0..self.table.num_rows()).map(|idx| self.table.result(idx)).collect()
+        }
+
+        fn __len__(&self) -> usize {
+            self.table.num_rows()
+        }
+
+        fn __getitem__<'py>(&self, py: Python<'py>, idx: isize) -> PyResult<Bound<'py, PyDict>> {
+            let len = self.table.num_rows() as isize;
+            let idx = if idx < 0 { idx + len } else { idx };
+            if !(0..len).contains(&idx) {
+                return Err(PyIndexError::new_err("Truth table row out of range"));
+            }
+            self.row(py, idx as usize)
+        }
+
+        fn __iter__(slf: Bound<'_, Self>) -> PyTruthTableRows {
+            PyTruthTableRows { table: slf.unbind(), next: 0 }
+        }
+
+        #[pyo3(signature = (format="text"))]
+        fn render(&self, format: &str) -> PyResult<String> {
+            let format = format.parse::<TableFormat>().map_err(PyException::new_err)?;
+            Ok(self.table.render(format))
+        }
+
+        fn __str__(&self) -> String {
+            self.table.to_string().trim_end().to_string()
+        }
+
+        fn _repr_html_(&self) -> String {
+            self.table.render(TableFormat::Html)
+        }
+    }
+
+    // Lazy iterator over the rows of a truth table, each put together when it is reached
+    #[pyclass(name="TruthTableIterator")]
+    struct PyTruthTableRows {
+        table: Py<PyTruthTable>,
+        next: usize,
+    }
+
+    #[pymethods]
+    impl PyTruthTableRows {
+        fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+            slf
+        }
+
+        fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+            let table = self.table.borrow(py);
+            if self.next == table.table.num_rows() {
+                return Ok(None);
+            }
+            self.next += 1;
+            table.row(py, self.next - 1).map(Some)
+        }
+    }
+
+    fn argument(premises: Vec<PyExpr>, conclusion: PyExpr) -> Expr {
         let premises_conjunction = premises.into_iter()
             .map(|pyexpr| pyexpr.expr.clone())
             .reduce(|acc, expr| Expr::and(acc, expr))
             .unwrap_or(Expr::Literal(true));
 
-        PyExpr::new(Expr::eif(premises_conjunction, conclusion.expr)).is_tautology()
+        Expr::eif(premises_conjunction, conclusion.expr)
+    }
+
+    #[pyfunction]
+    fn is_valid_argument(premises: Vec<PyExpr>, conclusion: PyExpr) -> bool {
+        argument(premises, conclusion).is_tautology(Backend::Sat)
+    }
+
+    // Indices of a minimal set of premises the conclusion follows from, None if the argument is invalid
+    #[pyfunction]
+    fn argument_core(premises: Vec<PyExpr>, conclusion: PyExpr) -> Option<Vec<usize>> {
+        let premises = premises.into_iter().map(|pyexpr| pyexpr.expr).collect::<Vec<_>>();
+        Expr::premise_core(&premises, Some(&conclusion.expr))
+    }
+
+    // Indices of a minimal set of premises that contradict each other, None if they are consistent
+    #[pyfunction]
+    fn inconsistency_core(premises: Vec<PyExpr>) -> Option<Vec<usize>> {
+        let premises = premises.into_iter().map(|pyexpr| pyexpr.expr).collect::<Vec<_>>();
+        Expr::premise_core(&premises, None)
+    }
+
+    #[pyfunction]
+    #[pyo3(signature = (premises, conclusion, format="lrat"))]
+    fn argument_certificate(premises: Vec<PyExpr>, conclusion: PyExpr, format: &str) -> PyResult<Option<(PyCNF, String)>> {
+        certificate(argument(premises, conclusion), format)
     }
 
 }
@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+
+use crate::cnf::CNF;
+use crate::expr::Expr;
+use crate::solver::Solver;
+
+// Weighted MaxSAT problem: every hard clause must hold, and the total weight of the
+// soft clauses that are falsified is minimised
+#[derive(Clone, Debug)]
+pub struct MaxSat {
+    // Symbol table and hard clauses
+    cnf: CNF,
+
+    soft: Vec<(Vec<isize>, u64)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MaxSatSolution {
+    // model[i] is the value of variable i + 1
+    pub model: Vec<bool>,
+
+    // Total weight of the soft clauses the model falsifies
+    pub cost: u64,
+}
+
+impl MaxSat {
+    pub fn new(cnf: CNF) -> MaxSat {
+        MaxSat { cnf, soft: Vec::new() }
+    }
+
+    pub fn get_cnf(&self) -> &CNF {
+        &self.cnf
+    }
+
+    pub fn add_hard(&mut self, clause: Vec<isize>) {
+        self.cnf.append_clause(clause);
+    }
+
+    pub fn add_soft(&mut self, clause: Vec<isize>, weight: u64) {
+        self.soft.push((clause, weight));
+    }
+
+    pub fn add_hard_expr(&mut self, expr: &Expr) {
+        let id = expr.tseitin_into(&mut self.cnf, false);
+        self.cnf.enforce(id, true);
+    }
+
+    // The expression's Tseitin clauses are hard, only its root variable is soft
+    pub fn add_soft_expr(&mut self, expr: &Expr, weight: u64) {
+        let id = self.literal(expr);
+        self.add_soft(vec![id], weight);
+    }
+
+    // Returns a variable equivalent to the expression without constraining it
+    pub fn literal(&mut self, expr: &Expr) -> isize {
+        expr.tseitin_into(&mut self.cnf, false)
+    }
+
+    // Total weight of the soft clauses falsified by model
+    pub fn cost(&self, model: &[bool]) -> u64 {
+        self.soft.iter()
+            .filter(|(clause, _)| !clause.iter().any(|lit| model[lit.unsigned_abs() - 1] == (*lit > 0)))
+            .map(|(_, weight)| weight)
+            .sum()
+    }
+
+    // Finds an optimal assignment with the core-guided WPM1 algorithm
+    // Each round the soft clauses in an unsatisfiable core are relaxed by fresh variables, of which
+    // exactly one may be used, and the smallest weight in the core is added to the lower bound
+    // Soft clauses heavier than that are split so the rest of their weight stays unrelaxed
+    // Returns None if the hard clauses are UNSAT
+    pub fn solve(&self) -> Option<MaxSatSolution> {
+        let num_vars = self.cnf.num_vars();
+        let mut solver = Solver::from_cnf(&self.cnf);
+        let mut next_var = num_vars;
+        let mut fresh = move || {
+            next_var += 1;
+            next_var as isize
+        };
+
+        // Active soft clauses with their remaining weight and the selector variable that enables them
+        let mut soft = Vec::new();
+        for (clause, weight) in self.soft.iter() {
+            if *weight == 0 {
+                continue;
+            }
+            let selector = fresh();
+            solver.append_clause(clause.iter().copied().chain([-selector]).collect());
+            soft.push((clause.clone(), *weight, selector));
+        }
+
+        loop {
+            let assumptions = soft.iter().map(|(_, _, selector)| *selector).collect::<Vec<_>>();
+            if let Some(mut model) = solver.solve_with_assumptions(&assumptions) {
+                model.truncate(num_vars);
+                let cost = self.cost(&model);
+                return Some(MaxSatSolution { model, cost });
+            }
+
+            let failed = solver.failed_assumptions().iter().copied().collect::<HashSet<_>>();
+            if failed.is_empty() {
+                return None;
+            }
+
+            let core = (0..soft.len()).filter(|idx| failed.contains(&soft[*idx].2)).collect::<Vec<_>>();
+            let min_weight = core.iter().map(|idx| soft[*idx].1).min().unwrap();
+
+            let mut relaxation = Vec::new();
+            for idx in core {
+                let relax = fresh();
+                let selector = fresh();
+                relaxation.push(relax);
+
+                let mut relaxed = soft[idx].0.clone();
+                relaxed.push(relax);
+                solver.append_clause(relaxed.iter().copied().chain([-selector]).collect());
+
+                soft[idx].1 -= min_weight;
+                soft.push((relaxed, min_weight, selector));
+            }
+            soft.retain(|(_, weight, _)| *weight > 0);
+
+            for clause in exactly_one(&relaxation, &mut fresh) {
+                solver.append_clause(clause);
+            }
+        }
+    }
+}
+
+// Clauses requiring exactly one of lits to be true, using the sequential encoding for the at most one part
+pub fn exactly_one(lits: &[isize], fresh: &mut impl FnMut() -> isize) -> Vec<Vec<isize>> {
+    let mut clauses = vec![lits.to_vec()];
+
+    // Each prefix variable is forced true once any literal up to it is true
+    let mut prev: Option<isize> = None;
+    for (i, lit) in lits.iter().enumerate() {
+        if let Some(prev) = prev {
+            clauses.push(vec![-lit, -prev]);
+        }
+        if i + 1 < lits.len() {
+            let prefix = fresh();
+            clauses.push(vec![-lit, prefix]);
+            if let Some(prev) = prev {
+                clauses.push(vec![-prev, prefix]);
+            }
+            prev = Some(prefix);
+        }
+    }
+    clauses
+}
@@ -0,0 +1,497 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::dag::Node;
+use crate::expr::Expr;
+use crate::normal;
+use crate::runtime::{env::Env, vm::VM};
+
+// Auto minimises exactly up to this many variables, and switches to the heuristic past it
+const AUTO_EXACT_LIMIT: usize = 8;
+
+// The truth table of an exact minimisation has 2^EXACT_LIMIT rows
+const EXACT_LIMIT: usize = 16;
+
+// Largest DNF the heuristic starts from
+const MAX_DNF_SIZE: usize = 1_000_000;
+
+// Petrick's method gives up on multiplying out the cover once it has this many candidates, since absorbing
+// each one takes a pass over the others, Auto then settles for a greedy cover while Exact searches for one
+const MAX_PETRICK_PRODUCTS: usize = 200;
+
+// Branches the exact search for a cover may take before it fails
+const MAX_COVER_BRANCHES: usize = 100_000;
+
+// Ways of finding a small two-level form
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MinimizeMethod {
+    // Exact for few variables, heuristic otherwise
+    #[default]
+    Auto,
+
+    // Quine-McCluskey prime implicants, with Petrick's method or a search picking the fewest that cover the truth table
+    Exact,
+
+    // Espresso-style expand, irredundant and reduce passes over cube covers, without a truth table
+    Espresso,
+}
+
+impl FromStr for MinimizeMethod {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<MinimizeMethod, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "auto" => Ok(MinimizeMethod::Auto),
+            "exact" => Ok(MinimizeMethod::Exact),
+            "espresso" => Ok(MinimizeMethod::Espresso),
+            _ => Err(format!("Unknown minimisation method '{}', expected 'auto', 'exact' or 'espresso'", name)),
+        }
+    }
+}
+
+// Conjunction of literals, variable i is in the cube when bit i of mask is set, with the value of bit i of value
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Cube {
+    mask: u64,
+    value: u64,
+}
+
+impl Cube {
+    const UNIVERSE: Cube = Cube { mask: 0, value: 0 };
+
+    fn literals(&self) -> u32 {
+        self.mask.count_ones()
+    }
+
+    fn intersects(&self, other: &Cube) -> bool {
+        self.mask & other.mask & (self.value ^ other.value) == 0
+    }
+
+    fn contains(&self, other: &Cube) -> bool {
+        self.mask & !other.mask == 0 && (self.value ^ other.value) & self.mask == 0
+    }
+
+    fn with(&self, var: usize, value: bool) -> Cube {
+        let bit = 1 << var;
+        Cube { mask: self.mask | bit, value: if value { self.value | bit } else { self.value & !bit } }
+    }
+
+    fn without(&self, var: usize) -> Cube {
+        let bit = 1 << var;
+        Cube { mask: self.mask & !bit, value: self.value & !bit }
+    }
+}
+
+// Smallest disjunction of conjunctions equivalent to expr wherever dont_care is false
+pub fn sum_of_products(expr: &Expr, dont_care: Option<&Expr>, method: MinimizeMethod) -> Result<Expr, String> {
+    let (vars, cover) = minimize(expr, dont_care, method)?;
+    Ok(join(cover.iter().map(|cube| join(cube_literals(cube, &vars, false), true)).collect(), false))
+}
+
+// Smallest conjunction of disjunctions equivalent to expr wherever dont_care is false
+// Found by minimising the negation, whose cubes are the negated clauses
+pub fn product_of_sums(expr: &Expr, dont_care: Option<&Expr>, method: MinimizeMethod) -> Result<Expr, String> {
+    let (vars, cover) = minimize(&Expr::not(expr.clone()), dont_care, method)?;
+    Ok(join(cover.iter().map(|cube| join(cube_literals(cube, &vars, true), false)).collect(), true))
+}
+
+// Variables in order of their bits, and the cover of the on-set
+fn minimize(expr: &Expr, dont_care: Option<&Expr>, method: MinimizeMethod) -> Result<(Vec<String>, Vec<Cube>), String> {
+    let dont_care = dont_care.cloned().unwrap_or(Expr::Literal(false));
+    let mut vars = expr.get_variables();
+    for var in dont_care.get_variables() {
+        if !vars.contains(&var) {
+            vars.push(var);
+        }
+    }
+    vars.sort();
+    if vars.len() > 64 {
+        return Err(format!("Minimisation supports at most 64 variables, found {}", vars.len()));
+    }
+
+    let exact = match method {
+        MinimizeMethod::Auto => vars.len() <= AUTO_EXACT_LIMIT,
+        MinimizeMethod::Exact if vars.len() > EXACT_LIMIT => {
+            return Err(format!("Exact minimisation supports at most {} variables, found {}", EXACT_LIMIT, vars.len()));
+        }
+        MinimizeMethod::Exact => true,
+        MinimizeMethod::Espresso => false,
+    };
+
+    let mut cover = if exact {
+        quine_mccluskey(expr, &dont_care, &vars, method == MinimizeMethod::Auto)?
+    } else {
+        let index = vars.iter().enumerate().map(|(idx, var)| (var.clone(), idx)).collect::<HashMap<_, _>>();
+        let on = cubes(expr, &index)?;
+        let dc = cubes(&dont_care, &index)?;
+        let off = complement(&on.iter().chain(dc.iter()).copied().collect::<Vec<_>>());
+        espresso(on, &dc, &off)
+    };
+
+    // Positive literals before negative ones, and earlier variables first
+    cover.sort_by_key(|cube| (0..vars.len()).filter(|var| cube.mask >> var & 1 == 1).map(|var| (var, cube.value >> var & 1 == 0)).collect::<Vec<_>>());
+    Ok((vars, cover))
+}
+
+fn cube_literals(cube: &Cube, vars: &[String], negated: bool) -> Vec<Expr> {
+    (0..vars.len())
+        .filter(|var| cube.mask >> var & 1 == 1)
+        .map(|var| {
+            let lit = Expr::Var(vars[var].clone());
+            if (cube.value >> var & 1 == 1) != negated { lit } else { Expr::not(lit) }
+        })
+        .collect()
+}
+
+fn join(exprs: Vec<Expr>, is_and: bool) -> Expr {
+    exprs.into_iter()
+        .reduce(|l, r| if is_and { Expr::and(l, r) } else { Expr::or(l, r) })
+        .unwrap_or(Expr::Literal(is_and))
+}
+
+// Cubes of the DNF of expr
+fn cubes(expr: &Expr, index: &HashMap<String, usize>) -> Result<Vec<Cube>, String> {
+    let mut cubes = Vec::new();
+    'term: for term in normal::dnf_terms(expr, MAX_DNF_SIZE)? {
+        let mut cube = Cube::UNIVERSE;
+        for lit in term {
+            let (var, value) = match lit.node() {
+                Node::Var(name) => (index[&name], true),
+                Node::Not(Expr::Var(name)) => (index[name], false),
+                _ => return Err(String::from("Minimisation doesn't support cardinality or pseudo-Boolean constraints")),
+            };
+
+            // A term with a variable and its negation is false
+            if cube.mask >> var & 1 == 1 && (cube.value >> var & 1 == 1) != value {
+                continue 'term;
+            }
+            cube = cube.with(var, value);
+        }
+        cubes.push(cube);
+    }
+    Ok(cubes)
+}
+
+// Prime implicants of the on-set and don't-cares, and a smallest set of them covering the on-set
+// With greedy, a cover Petrick's method can't find within its limit is picked greedily instead
+fn quine_mccluskey(expr: &Expr, dont_care: &Expr, vars: &[String], greedy: bool) -> Result<Vec<Cube>, String> {
+    let full = (1u64 << vars.len()) - 1;
+    let (expr, dont_care) = (expr.compile(), dont_care.compile());
+    let mut on = Vec::new();
+    let mut implicants = HashSet::new();
+    for minterm in 0..1u64 << vars.len() {
+        let mut env = Env::new();
+        for (var, name) in vars.iter().enumerate() {
+            env.define(name.clone(), minterm >> var & 1 == 1);
+        }
+
+        let is_dont_care = VM::new(&env, dont_care.clone()).eval()?;
+        let is_on = VM::new(&env, expr.clone()).eval()?;
+        if is_on && !is_dont_care {
+            on.push(minterm);
+        }
+        if is_on || is_dont_care {
+            implicants.insert(Cube { mask: full, value: minterm });
+        }
+    }
+
+    // Merges implicants differing in a single variable until only prime implicants are left
+    let mut primes: Vec<Cube> = Vec::new();
+    while !implicants.is_empty() {
+        let mut merged = HashSet::new();
+        let mut next = HashSet::new();
+        for cube in implicants.iter() {
+            for var in (0..vars.len()).filter(|var| cube.mask >> var & 1 == 1) {
+                let other = cube.with(var, cube.value >> var & 1 == 0);
+                if implicants.contains(&other) {
+                    merged.insert(*cube);
+                    next.insert(cube.without(var));
+                }
+            }
+        }
+        primes.extend(implicants.iter().filter(|cube| !merged.contains(cube)));
+        implicants = next;
+    }
+    primes.sort_by_key(|cube| (cube.literals(), cube.mask, cube.value));
+
+    petrick(&primes, &on, greedy)
+}
+
+// Smallest set of primes covering every minterm, fewest cubes first and then fewest literals
+// Past MAX_PETRICK_PRODUCTS candidates this is a greedy cover with greedy, and a branch and bound search otherwise
+fn petrick(primes: &[Cube], minterms: &[u64], greedy: bool) -> Result<Vec<Cube>, String> {
+    let minterm = |value: u64| Cube { mask: u64::MAX, value };
+    let covering = |m: u64| (0..primes.len()).filter(|idx| primes[*idx].contains(&minterm(m))).collect::<Vec<_>>();
+
+    // Essential primes are the only ones covering some minterm
+    let mut chosen = HashSet::new();
+    for m in minterms.iter() {
+        if let [only] = covering(*m)[..] {
+            chosen.insert(only);
+        }
+    }
+    let left = minterms.iter()
+        .copied()
+        .filter(|m| !chosen.iter().any(|idx| primes[*idx].contains(&minterm(*m))))
+        .collect::<Vec<_>>();
+
+    let covers = left.iter().map(|m| covering(*m)).collect::<Vec<_>>();
+    let best = match expand_products(&covers) {
+        Some(products) => products.into_iter().min_by_key(|product| cover_cost(primes, product)).unwrap_or_default(),
+        None if greedy => greedy_cover(primes, &left),
+        None => {
+            let mut best = greedy_cover(primes, &left);
+            let mut branches = 0;
+            let uncovered = (0..covers.len()).collect::<Vec<_>>();
+            branch_and_bound(primes, &covers, &uncovered, &mut Vec::new(), &mut best, &mut branches)?;
+            best
+        }
+    };
+    chosen.extend(best);
+
+    let mut chosen = chosen.into_iter().collect::<Vec<_>>();
+    chosen.sort_unstable();
+    Ok(chosen.into_iter().map(|idx| primes[idx]).collect())
+}
+
+fn cover_cost(primes: &[Cube], cover: &[usize]) -> (usize, u32) {
+    (cover.len(), cover.iter().map(|idx| primes[*idx].literals()).sum())
+}
+
+// Multiplies out the product of sums of the primes covering each minterm, absorbing supersets
+// None once there are more than MAX_PETRICK_PRODUCTS products
+fn expand_products(covers: &[Vec<usize>]) -> Option<Vec<Vec<usize>>> {
+    // Products are kept sorted, so containment is a single merge
+    let subset = |small: &[usize], large: &[usize]| {
+        let mut large = large.iter();
+        small.iter().all(|idx| large.any(|other| other == idx))
+    };
+
+    let mut products: Vec<Vec<usize>> = vec![Vec::new()];
+    for covering in covers {
+        let mut next: Vec<Vec<usize>> = Vec::new();
+        for product in products.iter() {
+            for idx in covering {
+                let mut product = product.clone();
+                if let Err(at) = product.binary_search(idx) {
+                    product.insert(at, *idx);
+                }
+                if !next.iter().any(|other| subset(other, &product)) {
+                    next.retain(|other| !subset(&product, other));
+                    next.push(product);
+                }
+                if next.len() > MAX_PETRICK_PRODUCTS {
+                    return None;
+                }
+            }
+        }
+        products = next;
+    }
+    Some(products)
+}
+
+// Repeatedly picks the prime covering the most minterms left
+fn greedy_cover(primes: &[Cube], minterms: &[u64]) -> Vec<usize> {
+    let mut left = minterms.iter().map(|m| Cube { mask: u64::MAX, value: *m }).collect::<Vec<_>>();
+    let mut chosen = Vec::new();
+    while !left.is_empty() {
+        let best = (0..primes.len())
+            .max_by_key(|idx| left.iter().filter(|m| primes[*idx].contains(m)).count())
+            .unwrap();
+        left.retain(|m| !primes[best].contains(m));
+        chosen.push(best);
+    }
+    chosen
+}
+
+// Improves best to the cheapest cover extending selected to the uncovered minterms, if there is a cheaper one
+// covers lists the primes covering each minterm, and the search fails after MAX_COVER_BRANCHES branches
+fn branch_and_bound(
+    primes: &[Cube],
+    covers: &[Vec<usize>],
+    uncovered: &[usize],
+    selected: &mut Vec<usize>,
+    best: &mut Vec<usize>,
+    branches: &mut usize,
+) -> Result<(), String> {
+    *branches += 1;
+    if *branches > MAX_COVER_BRANCHES {
+        return Err(format!("Exact cover search exceeded {} branches, use method 'espresso' or 'auto' for a heuristic cover", MAX_COVER_BRANCHES));
+    }
+
+    let (count, literals) = cover_cost(primes, selected);
+    if uncovered.is_empty() {
+        if (count, literals) < cover_cost(primes, best) {
+            *best = selected.clone();
+        }
+        return Ok(());
+    }
+
+    // Minterms with no prime in common each need a prime of their own
+    let mut used = HashSet::new();
+    let mut needed = 0;
+    for m in uncovered {
+        if covers[*m].iter().all(|idx| !used.contains(idx)) {
+            used.extend(covers[*m].iter().copied());
+            needed += 1;
+        }
+    }
+    if (count + needed, literals) >= cover_cost(primes, best) {
+        return Ok(());
+    }
+
+    // Branches on the primes covering the minterm with the fewest of them, broadest first
+    let m = *uncovered.iter().min_by_key(|m| covers[**m].len()).unwrap();
+    let mut options = covers[m].clone();
+    options.sort_by_key(|idx| (std::cmp::Reverse(uncovered.iter().filter(|m| covers[**m].contains(idx)).count()), primes[*idx].literals()));
+    for idx in options {
+        let rest = uncovered.iter().copied().filter(|m| !covers[*m].contains(&idx)).collect::<Vec<_>>();
+        selected.push(idx);
+        branch_and_bound(primes, covers, &rest, selected, best, branches)?;
+        selected.pop();
+    }
+    Ok(())
+}
+
+// Improves a cover of the on-set until neither the number of cubes nor of literals goes down
+// dc covers the don't-cares and off the off-set
+fn espresso(on: Vec<Cube>, dc: &[Cube], off: &[Cube]) -> Vec<Cube> {
+    let cost = |cover: &Vec<Cube>| (cover.len(), cover.iter().map(Cube::literals).sum::<u32>());
+
+    let mut cover = irredundant(expand(on, off), dc);
+    loop {
+        let next = irredundant(expand(reduce(cover.clone(), dc), off), dc);
+        if cost(&next) >= cost(&cover) {
+            return cover;
+        }
+        cover = next;
+    }
+}
+
+// Drops as many literals from each cube as possible without touching the off-set,
+// then drops the cubes another one contains
+fn expand(mut cover: Vec<Cube>, off: &[Cube]) -> Vec<Cube> {
+    cover.sort_by_key(|cube| cube.literals());
+    let mut expanded: Vec<Cube> = Vec::new();
+    for mut cube in cover {
+        if expanded.iter().any(|other| other.contains(&cube)) {
+            continue;
+        }
+        for var in 0..64 {
+            if cube.mask >> var & 1 == 1 {
+                let raised = cube.without(var);
+                if !off.iter().any(|other| other.intersects(&raised)) {
+                    cube = raised;
+                }
+            }
+        }
+        expanded.retain(|other| !cube.contains(other));
+        expanded.push(cube);
+    }
+    expanded
+}
+
+// Drops each cube, largest first, that the rest of the cover and the don't-cares already cover
+fn irredundant(mut cover: Vec<Cube>, dc: &[Cube]) -> Vec<Cube> {
+    cover.sort_by_key(|cube| cube.literals());
+    let mut idx = cover.len();
+    while idx > 0 {
+        idx -= 1;
+        let others = cover.iter()
+            .enumerate()
+            .filter(|(other, _)| *other != idx)
+            .map(|(_, cube)| *cube)
+            .chain(dc.iter().copied())
+            .collect::<Vec<_>>();
+        if covers(&others, &cover[idx]) {
+            cover.remove(idx);
+        }
+    }
+    cover
+}
+
+// Shrinks each cube to the part the rest of the cover and the don't-cares don't cover, so expand can grow it elsewhere
+fn reduce(mut cover: Vec<Cube>, dc: &[Cube]) -> Vec<Cube> {
+    for idx in 0..cover.len() {
+        let others = cover.iter()
+            .enumerate()
+            .filter(|(other, _)| *other != idx)
+            .map(|(_, cube)| *cube)
+            .chain(dc.iter().copied())
+            .collect::<Vec<_>>();
+        for var in 0..64 {
+            let cube = cover[idx];
+            if cube.mask >> var & 1 == 0 {
+                for value in [true, false] {
+                    if covers(&others, &cube.with(var, !value)) {
+                        cover[idx] = cube.with(var, value);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    cover
+}
+
+// Checks if the union of the cover contains the cube, by checking that the cover restricted to it is a tautology
+fn covers(cover: &[Cube], cube: &Cube) -> bool {
+    let restricted = cover.iter()
+        .filter(|other| other.intersects(cube))
+        .map(|other| Cube { mask: other.mask & !cube.mask, value: other.value & !cube.mask })
+        .collect::<Vec<_>>();
+    tautology(&restricted)
+}
+
+// Cover of everything the cover leaves out, by Shannon expansion on the variable appearing in the most cubes
+fn complement(cover: &[Cube]) -> Vec<Cube> {
+    if cover.iter().any(|cube| cube.mask == 0) {
+        return Vec::new();
+    }
+    if let [cube] = cover {
+        // De Morgan: each literal of the cube negated
+        return (0..64)
+            .filter(|var| cube.mask >> var & 1 == 1)
+            .map(|var| Cube::UNIVERSE.with(var, cube.value >> var & 1 == 0))
+            .collect();
+    }
+    let Some(var) = most_frequent(cover) else {
+        return vec![Cube::UNIVERSE];
+    };
+
+    let halves = [true, false].map(|value| complement(&cofactor(cover, var, value)));
+
+    // Cubes in both halves don't depend on the variable
+    let shared = halves[0].iter().filter(|cube| halves[1].contains(cube)).copied().collect::<Vec<_>>();
+    let mut result = shared.clone();
+    for (half, value) in halves.iter().zip([true, false]) {
+        result.extend(half.iter().filter(|cube| !shared.contains(cube)).map(|cube| cube.with(var, value)));
+    }
+    result
+}
+
+// Cubes of the cover in the half where var has the value, with var left out
+fn cofactor(cover: &[Cube], var: usize, value: bool) -> Vec<Cube> {
+    let half = Cube::UNIVERSE.with(var, value);
+    cover.iter()
+        .filter(|cube| cube.intersects(&half))
+        .map(|cube| cube.without(var))
+        .collect()
+}
+
+fn most_frequent(cover: &[Cube]) -> Option<usize> {
+    (0..64)
+        .filter(|var| cover.iter().any(|cube| cube.mask >> var & 1 == 1))
+        .max_by_key(|var| cover.iter().filter(|cube| cube.mask >> var & 1 == 1).count())
+}
+
+// Shannon expansion on the variable appearing in the most cubes
+fn tautology(cover: &[Cube]) -> bool {
+    if cover.iter().any(|cube| cube.mask == 0) {
+        return true;
+    }
+    let Some(var) = most_frequent(cover) else {
+        return false;
+    };
+    [true, false].into_iter().all(|value| tautology(&cofactor(cover, var, value)))
+}
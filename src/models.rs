@@ -0,0 +1,56 @@
+use crate::solver::Solver;
+
+// Lazily enumerates the satisfying assignments of a CNF projected onto a set of its variables
+// After each model a clause excluding it is added, so every projected assignment is produced exactly once
+pub struct Models {
+    solver: Solver,
+
+    // Ids of the variables models are projected onto, and their names
+    projection: Vec<usize>,
+    names: Vec<String>,
+
+    done: bool,
+}
+
+impl Models {
+    pub fn new(solver: Solver, projection: Vec<usize>, names: Vec<String>) -> Models {
+        Models { solver, projection, names, done: false }
+    }
+
+    // Names of the variables, in the same order as the values of each model
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+}
+
+impl Iterator for Models {
+    type Item = Vec<bool>;
+
+    fn next(&mut self) -> Option<Vec<bool>> {
+        if self.done {
+            return None;
+        }
+
+        let Some(assignment) = self.solver.solve() else {
+            self.done = true;
+            return None;
+        };
+
+        let model = self.projection.iter()
+            .map(|id| assignment[id - 1])
+            .collect::<Vec<_>>();
+
+        // With nothing to project onto there is a single, empty, model
+        if self.projection.is_empty() {
+            self.done = true;
+        }
+
+        let blocking_clause = self.projection.iter()
+            .zip(model.iter())
+            .map(|(id, value)| if *value { -(*id as isize) } else { *id as isize })
+            .collect();
+        self.solver.append_clause(blocking_clause);
+
+        Some(model)
+    }
+}
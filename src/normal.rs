@@ -0,0 +1,167 @@
+use crate::card::Comparison;
+use crate::dag::Node;
+use crate::expr::Expr;
+
+// Logically equivalent normal forms, built as expressions of at most max_size nodes
+// Cardinality and pseudo-Boolean constraints are kept as atoms, with negations folded into their comparison
+
+fn too_large(max_size: usize) -> String {
+    format!("Normal form has more than {} nodes", max_size)
+}
+
+// Negation normal form, where negations only apply to variables
+pub fn nnf(expr: &Expr, max_size: usize) -> Result<Expr, String> {
+    Ok(nnf_aux(expr, false, max_size)?.0)
+}
+
+// Conjunction of disjunctions of literals
+pub fn cnf(expr: &Expr, max_size: usize) -> Result<Expr, String> {
+    let clauses = clauses(&nnf(expr, max_size)?, true, max_size)?;
+    join_clauses(clauses, true, max_size)
+}
+
+// Disjunction of conjunctions of literals
+pub fn dnf(expr: &Expr, max_size: usize) -> Result<Expr, String> {
+    let clauses = clauses(&nnf(expr, max_size)?, false, max_size)?;
+    join_clauses(clauses, false, max_size)
+}
+
+// Terms of the DNF, each a list of literals, before they are joined into an expression
+pub fn dnf_terms(expr: &Expr, max_size: usize) -> Result<Vec<Vec<Expr>>, String> {
+    clauses(&nnf(expr, max_size)?, false, max_size)
+}
+
+// NNF of the expression, or of its negation if negated, along with its size
+fn nnf_aux(expr: &Expr, negated: bool, max_size: usize) -> Result<(Expr, usize), String> {
+    let nnf = |expr: &Expr, negated: bool| nnf_aux(expr, negated, max_size);
+    let join = |is_and: bool, l: (Expr, usize), r: (Expr, usize)| join(is_and, l, r, max_size);
+
+    match expr.node() {
+        Node::Var(_) if negated => Ok((Expr::not(expr.clone()), 2)),
+        Node::Var(_) => Ok((expr.clone(), 1)),
+        Node::Literal(value) => Ok((Expr::Literal(value != negated), 1)),
+        Node::Not(expr) => nnf(expr, !negated),
+
+        // Negating a connective swaps conjunctions and disjunctions, and negates the operands
+        Node::And(l, r) => join(!negated, nnf(l, negated)?, nnf(r, negated)?),
+        Node::Or(l, r) => join(negated, nnf(l, negated)?, nnf(r, negated)?),
+        Node::Nand(l, r) => join(negated, nnf(l, !negated)?, nnf(r, !negated)?),
+        Node::Nor(l, r) => join(!negated, nnf(l, !negated)?, nnf(r, !negated)?),
+        Node::Implies(l, r) => join(negated, nnf(l, !negated)?, nnf(r, negated)?),
+
+        // l <-> r is (l and r) or (not l and not r), while l xor r is (l and not r) or (not l and r)
+        Node::Xor(l, r) | Node::Iff(l, r) => {
+            let equal = matches!(expr, Expr::Iff(_)) != negated;
+            let l_true = join(true, nnf(l, false)?, nnf(r, !equal)?)?;
+            let l_false = join(true, nnf(l, true)?, nnf(r, equal)?)?;
+            join(false, l_true, l_false)
+        }
+
+        Node::Card(exprs, comparison, bound, encoding) => {
+            let exprs = exprs.into_iter().cloned().collect::<Vec<_>>();
+            let card = |comparison: Comparison, bound: usize| {
+                let size = 1 + exprs.iter().map(Expr::size).sum::<usize>();
+                (Expr::cardinality(exprs.clone(), comparison, bound, encoding), size)
+            };
+            match (negated, comparison) {
+                (false, _) => Ok(card(comparison, bound)),
+                (true, Comparison::AtMost) => Ok(card(Comparison::AtLeast, bound + 1)),
+                (true, Comparison::AtLeast) if bound == 0 => Ok((Expr::Literal(false), 1)),
+                (true, Comparison::AtLeast) => Ok(card(Comparison::AtMost, bound - 1)),
+                (true, Comparison::Exactly) if bound == 0 => Ok(card(Comparison::AtLeast, 1)),
+                (true, Comparison::Exactly) => join(false, card(Comparison::AtMost, bound - 1), card(Comparison::AtLeast, bound + 1)),
+            }
+        }
+
+        Node::Pb(terms, comparison, bound, encoding) => {
+            let terms = terms.into_iter().map(|(weight, expr)| (weight, expr.clone())).collect::<Vec<_>>();
+            let pb = |comparison: Comparison, bound: i64| {
+                let size = 1 + terms.iter().map(|(_, expr)| expr.size()).sum::<usize>();
+                (Expr::pseudo_boolean(terms.clone(), comparison, bound, encoding), size)
+            };
+            match (negated, comparison) {
+                (false, _) => Ok(pb(comparison, bound)),
+                (true, Comparison::AtMost) => Ok(pb(Comparison::AtLeast, bound + 1)),
+                (true, Comparison::AtLeast) => Ok(pb(Comparison::AtMost, bound - 1)),
+                (true, Comparison::Exactly) => join(false, pb(Comparison::AtMost, bound - 1), pb(Comparison::AtLeast, bound + 1)),
+            }
+        }
+    }
+}
+
+fn join(is_and: bool, (l, l_size): (Expr, usize), (r, r_size): (Expr, usize), max_size: usize) -> Result<(Expr, usize), String> {
+    let size = l_size + r_size + 1;
+    if size > max_size {
+        return Err(too_large(max_size));
+    }
+    Ok((if is_and { Expr::and(l, r) } else { Expr::or(l, r) }, size))
+}
+
+// Clauses of an NNF, disjunctions when is_cnf and conjunctions otherwise
+// No clauses is the identity of the outer connective, and an empty clause that of the inner one
+fn clauses(expr: &Expr, is_cnf: bool, max_size: usize) -> Result<Vec<Vec<Expr>>, String> {
+    let (l, r) = match expr.node() {
+        Node::And(l, r) | Node::Or(l, r) => (l, r),
+        Node::Literal(value) if value == is_cnf => return Ok(Vec::new()),
+        Node::Literal(_) => return Ok(vec![Vec::new()]),
+        _ => return Ok(vec![vec![expr.clone()]]),
+    };
+    let l = clauses(l, is_cnf, max_size)?;
+    let r = clauses(r, is_cnf, max_size)?;
+
+    // The outer connective puts the clauses side by side
+    if matches!(expr, Expr::And(_)) == is_cnf {
+        return Ok(l.into_iter().chain(r).collect());
+    }
+
+    // The inner one distributes, pairing every clause on the left with every clause on the right
+    let literals = |clauses: &Vec<Vec<Expr>>| clauses.iter().map(|clause| clause.iter().map(Expr::size).sum::<usize>()).sum::<usize>();
+    if literals(&l).saturating_mul(r.len()).saturating_add(literals(&r).saturating_mul(l.len())) > max_size {
+        return Err(too_large(max_size));
+    }
+
+    let mut product = Vec::new();
+    for l in l.iter() {
+        for r in r.iter() {
+            let mut clause = l.clone();
+            for lit in r {
+                if !clause.contains(lit) {
+                    clause.push(lit.clone());
+                }
+            }
+
+            // A clause with a literal and its negation is the identity of the outer connective
+            let tautology = clause.iter().any(|lit| clause.contains(&Expr::not(lit.clone())));
+            if !tautology && !product.contains(&clause) {
+                product.push(clause);
+            }
+        }
+    }
+    Ok(product)
+}
+
+// Joins the clauses into an expression, leaving out each clause that contains all the literals of another one
+fn join_clauses(clauses: Vec<Vec<Expr>>, is_cnf: bool, max_size: usize) -> Result<Expr, String> {
+    let subsumed = |i: usize| (0..clauses.len()).any(|j| {
+        j != i
+            && clauses[j].iter().all(|lit| clauses[i].contains(lit))
+            && (clauses[j].len() < clauses[i].len() || j < i)
+    });
+    let clauses = (0..clauses.len())
+        .filter(|idx| !subsumed(*idx))
+        .map(|idx| clauses[idx].clone())
+        .collect::<Vec<_>>();
+
+    let join = |is_and: bool, exprs: Vec<Expr>, identity: bool| {
+        exprs.into_iter()
+            .reduce(|l, r| if is_and { Expr::and(l, r) } else { Expr::or(l, r) })
+            .unwrap_or(Expr::Literal(identity))
+    };
+
+    let clauses = clauses.into_iter().map(|clause| join(!is_cnf, clause, !is_cnf)).collect();
+    let expr = join(is_cnf, clauses, is_cnf);
+    if expr.size() > max_size {
+        return Err(too_large(max_size));
+    }
+    Ok(expr)
+}
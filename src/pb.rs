@@ -0,0 +1,438 @@
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+
+use crate::card::Comparison;
+
+// Ways of turning a pseudo-Boolean constraint into clauses
+// Both build a circuit whose output is equivalent to the constraint, so it can be negated and nested
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PbEncoding {
+    // Decision diagram over the terms, sharing nodes whose remaining bounds behave the same
+    #[default]
+    Bdd,
+
+    // Binary sum of the terms built from full adders, compared against the bound
+    // Grows with the number of bits in the weights rather than their size
+    Adder,
+}
+
+impl FromStr for PbEncoding {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<PbEncoding, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "bdd" => Ok(PbEncoding::Bdd),
+            "adder" => Ok(PbEncoding::Adder),
+            _ => Err(format!("Unknown pseudo-Boolean encoding '{}', expected 'bdd' or 'adder'", name)),
+        }
+    }
+}
+
+// Linear constraint sum(weight * lit) compared with bound, where a literal counts as 1 when true
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PbConstraint {
+    pub terms: Vec<(i64, isize)>,
+    pub comparison: Comparison,
+    pub bound: i64,
+}
+
+impl PbConstraint {
+    pub fn holds(&self, model: &[bool]) -> bool {
+        let sum = self.terms.iter()
+            .filter(|(_, lit)| model[lit.unsigned_abs() - 1] == (*lit > 0))
+            .map(|(weight, _)| weight)
+            .sum::<i64>();
+        self.comparison.holds(sum, self.bound)
+    }
+}
+
+// Clauses making id equivalent to the constraint
+// fresh returns a new auxiliary variable each time it is called
+pub fn reified(
+    id: isize,
+    constraint: &PbConstraint,
+    encoding: PbEncoding,
+    fresh: &mut impl FnMut() -> isize,
+) -> Vec<Vec<isize>> {
+    // Negative weights are moved onto the negated literal: w * x = w + -w * not x
+    let mut terms = Vec::new();
+    let mut bound = constraint.bound;
+    for (weight, lit) in constraint.terms.iter() {
+        if *weight < 0 {
+            terms.push((-weight, -lit));
+            bound -= weight;
+        } else if *weight > 0 {
+            terms.push((*weight, *lit));
+        }
+    }
+
+    let mut circuit = Circuit { fresh, clauses: Vec::new() };
+    let mut bdd = LinearBdd::new(terms.clone(), [Signal::Const(false), Signal::Const(true)]);
+    let sum = match encoding {
+        PbEncoding::Bdd => None,
+        PbEncoding::Adder => Some(circuit.sum(&terms)),
+    };
+    let mut at_most = |circuit: &mut Circuit<_>, bound| match &sum {
+        Some(bits) => circuit.at_most(bits, bound),
+        None => bdd.at_most(bound, &mut |lit, taken, skipped| circuit.ite(Signal::Lit(lit), taken, skipped)),
+    };
+
+    let signal = match constraint.comparison {
+        Comparison::AtMost => at_most(&mut circuit, bound),
+        Comparison::AtLeast => {
+            let below = at_most(&mut circuit, bound - 1);
+            circuit.not(below)
+        }
+        Comparison::Exactly => {
+            let at_most_bound = at_most(&mut circuit, bound);
+            let below = at_most(&mut circuit, bound - 1);
+            let at_least_bound = circuit.not(below);
+            circuit.and(at_most_bound, at_least_bound)
+        }
+    };
+
+    let mut clauses = circuit.clauses;
+    match signal {
+        Signal::Const(value) => clauses.push(vec![if value { id } else { -id }]),
+        Signal::Lit(lit) => {
+            clauses.push(vec![-id, lit]);
+            clauses.push(vec![id, -lit]);
+        }
+    }
+    clauses
+}
+
+// Wire of a circuit, either a constant or a literal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Signal {
+    Const(bool),
+    Lit(isize),
+}
+
+// Builds gates whose outputs are equivalent to their inputs' function, folding away constants
+struct Circuit<'a, F: FnMut() -> isize> {
+    fresh: &'a mut F,
+    clauses: Vec<Vec<isize>>,
+}
+
+impl<F: FnMut() -> isize> Circuit<'_, F> {
+    fn not(&mut self, a: Signal) -> Signal {
+        match a {
+            Signal::Const(value) => Signal::Const(!value),
+            Signal::Lit(lit) => Signal::Lit(-lit),
+        }
+    }
+
+    fn and(&mut self, a: Signal, b: Signal) -> Signal {
+        match (a, b) {
+            (Signal::Const(false), _) | (_, Signal::Const(false)) => Signal::Const(false),
+            (Signal::Const(true), other) | (other, Signal::Const(true)) => other,
+            (Signal::Lit(a), Signal::Lit(b)) if a == b => Signal::Lit(a),
+            (Signal::Lit(a), Signal::Lit(b)) if a == -b => Signal::Const(false),
+            (Signal::Lit(a), Signal::Lit(b)) => {
+                let out = (self.fresh)();
+                self.clauses.push(vec![-out, a]);
+                self.clauses.push(vec![-out, b]);
+                self.clauses.push(vec![out, -a, -b]);
+                Signal::Lit(out)
+            }
+        }
+    }
+
+    fn or(&mut self, a: Signal, b: Signal) -> Signal {
+        let (a, b) = (self.not(a), self.not(b));
+        let nor = self.and(a, b);
+        self.not(nor)
+    }
+
+    fn xor(&mut self, a: Signal, b: Signal) -> Signal {
+        match (a, b) {
+            (Signal::Const(value), other) | (other, Signal::Const(value)) => {
+                if value { self.not(other) } else { other }
+            }
+            (Signal::Lit(a), Signal::Lit(b)) if a == b => Signal::Const(false),
+            (Signal::Lit(a), Signal::Lit(b)) if a == -b => Signal::Const(true),
+            (Signal::Lit(a), Signal::Lit(b)) => {
+                let out = (self.fresh)();
+                self.clauses.push(vec![-out, a, b]);
+                self.clauses.push(vec![-out, -a, -b]);
+                self.clauses.push(vec![out, -a, b]);
+                self.clauses.push(vec![out, a, -b]);
+                Signal::Lit(out)
+            }
+        }
+    }
+
+    // If cond then a else b
+    fn ite(&mut self, cond: Signal, a: Signal, b: Signal) -> Signal {
+        match (cond, a, b) {
+            (Signal::Const(value), _, _) => if value { a } else { b },
+            _ if a == b => a,
+            (Signal::Lit(cond), Signal::Lit(a), Signal::Lit(b)) => {
+                let out = (self.fresh)();
+                self.clauses.push(vec![-cond, -a, out]);
+                self.clauses.push(vec![-cond, a, -out]);
+                self.clauses.push(vec![cond, -b, out]);
+                self.clauses.push(vec![cond, b, -out]);
+                Signal::Lit(out)
+            }
+            _ => {
+                let not_cond = self.not(cond);
+                let then = self.and(cond, a);
+                let otherwise = self.and(not_cond, b);
+                self.or(then, otherwise)
+            }
+        }
+    }
+
+    // Binary sum of the terms, least significant bit first
+    fn sum(&mut self, terms: &[(i64, isize)]) -> Vec<Signal> {
+        // Every set bit of a weight puts the literal in that bit's column
+        let mut columns: Vec<VecDeque<Signal>> = Vec::new();
+        for (weight, lit) in terms {
+            for bit in 0..64 - weight.leading_zeros() as usize {
+                if weight >> bit & 1 == 1 {
+                    if columns.len() <= bit {
+                        columns.resize(bit + 1, VecDeque::new());
+                    }
+                    columns[bit].push_back(Signal::Lit(*lit));
+                }
+            }
+        }
+
+        // Add up each column with full and half adders, carrying into the next one
+        let mut bits = Vec::new();
+        let mut bit = 0;
+        while bit < columns.len() {
+            while columns[bit].len() > 1 {
+                let a = columns[bit].pop_front().unwrap();
+                let b = columns[bit].pop_front().unwrap();
+                let c = columns[bit].pop_front().unwrap_or(Signal::Const(false));
+
+                let partial = self.xor(a, b);
+                let sum = self.xor(partial, c);
+                let both = self.and(a, b);
+                let either = self.and(partial, c);
+                let carry = self.or(both, either);
+
+                columns[bit].push_back(sum);
+                if carry != Signal::Const(false) {
+                    if columns.len() <= bit + 1 {
+                        columns.push(VecDeque::new());
+                    }
+                    columns[bit + 1].push_back(carry);
+                }
+            }
+            bits.push(columns[bit].pop_front().unwrap_or(Signal::Const(false)));
+            bit += 1;
+        }
+        bits
+    }
+
+    // Whether the binary number bits is at most bound
+    fn at_most(&mut self, bits: &[Signal], bound: i64) -> Signal {
+        if bound < 0 {
+            return Signal::Const(false);
+        }
+
+        // Going up from the least significant bit, the low bits are at most the bound's low bits
+        // when this bit is smaller, or it is equal and the lower bits were at most
+        let mut at_most = Signal::Const(true);
+        let width = bits.len().max(64 - bound.leading_zeros() as usize);
+        for bit in 0..width {
+            let value = bits.get(bit).copied().unwrap_or(Signal::Const(false));
+            let zero = self.not(value);
+            at_most = if bound >> bit & 1 == 1 {
+                self.or(zero, at_most)
+            } else {
+                self.and(zero, at_most)
+            };
+        }
+        at_most
+    }
+}
+
+// Decision diagram deciding whether the sum of the terms from some index on is at most a bound
+// Nodes are cached with the interval of bounds they answer the same for, so equivalent nodes are shared
+// Terms are anything a node can decide on, such as literals of a circuit or functions in a bdd::Bdd
+pub(crate) struct LinearBdd<T, S> {
+    terms: Vec<(i64, T)>,
+
+    // suffix[i] is the total weight of the terms from index i on
+    suffix: Vec<i64>,
+
+    // Nodes for false and true
+    constants: [S; 2],
+
+    // For each index, (lowest bound, highest bound, node) for the nodes built so far
+    cache: HashMap<usize, Vec<(i64, i64, S)>>,
+}
+
+impl<T: Copy, S: Copy> LinearBdd<T, S> {
+    // Weights have to be positive
+    pub(crate) fn new(mut terms: Vec<(i64, T)>, constants: [S; 2]) -> LinearBdd<T, S> {
+        // Heaviest terms first keeps the diagram small
+        terms.sort_by_key(|(weight, _)| std::cmp::Reverse(*weight));
+
+        let mut suffix = vec![0i64; terms.len() + 1];
+        for i in (0..terms.len()).rev() {
+            suffix[i] = suffix[i + 1].saturating_add(terms[i].0);
+        }
+        LinearBdd { terms, suffix, constants, cache: HashMap::new() }
+    }
+
+    // ite builds the node deciding on a term, with the nodes for when it is taken and when it is skipped
+    pub(crate) fn at_most(&mut self, bound: i64, ite: &mut impl FnMut(T, S, S) -> S) -> S {
+        self.node(ite, 0, bound).2
+    }
+
+    // Returns the node for the terms from index on with the given bound, along with the interval of
+    // bounds it is also the node for
+    fn node(&mut self, ite: &mut impl FnMut(T, S, S) -> S, index: usize, bound: i64) -> (i64, i64, S) {
+        if bound < 0 {
+            return (i64::MIN, -1, self.constants[0]);
+        }
+        if self.suffix[index] <= bound {
+            return (self.suffix[index], i64::MAX, self.constants[1]);
+        }
+
+        let cached = self.cache.get(&index)
+            .and_then(|nodes| nodes.iter().find(|(low, high, _)| *low <= bound && bound <= *high));
+        if let Some(node) = cached {
+            return *node;
+        }
+
+        let (weight, term) = self.terms[index];
+        let (taken_low, taken_high, taken) = self.node(ite, index + 1, bound - weight);
+        let (skipped_low, skipped_high, skipped) = self.node(ite, index + 1, bound);
+
+        let low = taken_low.saturating_add(weight).max(skipped_low);
+        let high = taken_high.saturating_add(weight).min(skipped_high);
+        let node = ite(term, taken, skipped);
+
+        self.cache.entry(index).or_default().push((low, high, node));
+        (low, high, node)
+    }
+}
+
+// Contents of an OPB file: the names of its variables, numbered from 1 in order of appearance, and its constraints
+// The objective function, if there is one, is parsed but not returned
+pub struct Opb {
+    pub names: Vec<String>,
+    pub constraints: Vec<PbConstraint>,
+}
+
+// Reads a linear pseudo-Boolean problem in the OPB format, such as
+//     * comment
+//     min: +1 x1 +2 x2 ;
+//     +3 x1 -2 ~x2 >= 1 ;
+//     +1 x1 +1 x2 = 1 ;
+pub fn parse_opb(src: &str) -> Result<Opb, String> {
+    let mut names = Vec::new();
+    let mut ids = HashMap::new();
+    let mut constraints = Vec::new();
+
+    // Statements end with ';' and may span lines, so track the line each one starts on for errors
+    let mut statement = String::new();
+    let mut start_line = 1;
+    for (idx, line) in src.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('*') {
+            continue;
+        }
+        if statement.trim().is_empty() {
+            start_line = idx + 1;
+        }
+
+        let mut rest = line;
+        while let Some(end) = rest.find(';') {
+            statement.push(' ');
+            statement.push_str(&rest[..end]);
+            let parsed = parse_statement(&statement, &mut names, &mut ids)
+                .map_err(|err| format!("Line {}: {}", start_line, err))?;
+            constraints.extend(parsed);
+
+            statement.clear();
+            start_line = idx + 1;
+            rest = &rest[end + 1..];
+        }
+        statement.push(' ');
+        statement.push_str(rest);
+    }
+
+    if !statement.trim().is_empty() {
+        return Err(format!("Line {}: Expected ';' at the end of the constraint", start_line));
+    }
+    Ok(Opb { names, constraints })
+}
+
+// Parses a single statement without its ';', returning None for the objective
+fn parse_statement(
+    statement: &str,
+    names: &mut Vec<String>,
+    ids: &mut HashMap<String, usize>,
+) -> Result<Option<PbConstraint>, String> {
+    let statement = statement.trim();
+    let (objective, body) = match statement.split_once(':') {
+        Some((kind @ ("min" | "max"), body)) => (Some(kind), body),
+        Some((kind, _)) => return Err(format!("Unknown objective '{}', expected 'min' or 'max'", kind.trim())),
+        None => (None, statement),
+    };
+
+    // Space out the operators so they can be split like the other tokens
+    let body = body.replace(">=", " >= ").replace("<=", " <= ");
+    let body = body.split_whitespace()
+        .flat_map(|token| if token.contains(['<', '>']) { vec![token.to_string()] } else {
+            token.replace('=', " = ").split_whitespace().map(String::from).collect()
+        })
+        .collect::<Vec<_>>();
+
+    let (lhs, comparison, rhs) = match body.iter().position(|token| [">=", "<=", "="].contains(&token.as_str())) {
+        Some(idx) => (&body[..idx], Some(&body[idx]), &body[idx + 1..]),
+        None => (&body[..], None, &body[..0]),
+    };
+
+    let mut terms = Vec::new();
+    let mut tokens = lhs.iter().peekable();
+    while let Some(token) = tokens.next() {
+        let weight = token.parse::<i64>().map_err(|_| format!("Expected a weight, found '{}'", token))?;
+        let Some(name) = tokens.next() else {
+            return Err(format!("Expected a variable after the weight {}", token));
+        };
+        if tokens.peek().is_some_and(|next| next.parse::<i64>().is_err()) {
+            return Err(format!("Non-linear term with '{}' and '{}' is not supported", name, tokens.peek().unwrap()));
+        }
+
+        let (negated, name) = match name.strip_prefix('~') {
+            Some(name) => (true, name),
+            None => (false, name.as_str()),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("Invalid variable name '{}'", name));
+        }
+
+        let id = *ids.entry(name.to_string()).or_insert_with(|| {
+            names.push(name.to_string());
+            names.len()
+        }) as isize;
+        terms.push((weight, if negated { -id } else { id }));
+    }
+
+    if objective.is_some() {
+        return match comparison {
+            Some(op) => Err(format!("Unexpected '{}' in the objective", op)),
+            None => Ok(None),
+        };
+    }
+
+    let Some(comparison) = comparison else {
+        return Err("Expected '>=', '<=' or '=' in the constraint".to_string());
+    };
+    let bound = match rhs {
+        [bound] => bound.parse::<i64>().map_err(|_| format!("Expected an integer bound, found '{}'", bound))?,
+        _ => return Err(format!("Expected a single integer after '{}'", comparison)),
+    };
+    let comparison = comparison.parse::<Comparison>()?;
+
+    Ok(Some(PbConstraint { terms, comparison, bound }))
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+// Text formats a refutation can be written in
+// DRAT lists each derived clause, LRAT additionally lists the ids of the clauses it follows from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProofFormat {
+    Drat,
+    Lrat,
+}
+
+impl FromStr for ProofFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<ProofFormat, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "drat" => Ok(ProofFormat::Drat),
+            "lrat" => Ok(ProofFormat::Lrat),
+            _ => Err(format!("Unknown proof format '{}', expected 'drat' or 'lrat'", name)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProofStep {
+    // Clause with the given id follows by unit propagation on the hint clauses, in order
+    Add { id: usize, clause: Vec<isize>, hints: Vec<usize> },
+    // Clause with the given id is no longer used, so a checker can forget it
+    Delete { id: usize, clause: Vec<isize> },
+}
+
+// Clauses derived by the solver, ending with the empty clause when the input is UNSAT
+// Input clauses are implicitly numbered from 1 in the order they were given to the solver
+#[derive(Clone, Debug, Default)]
+pub struct Proof {
+    steps: Vec<ProofStep>,
+}
+
+impl Proof {
+    pub fn push(&mut self, step: ProofStep) {
+        self.steps.push(step);
+    }
+
+    pub fn steps(&self) -> &[ProofStep] {
+        &self.steps
+    }
+
+    // Checks if the proof derives the empty clause
+    pub fn is_refutation(&self) -> bool {
+        self.steps.iter().any(|step| matches!(step, ProofStep::Add { clause, .. } if clause.is_empty()))
+    }
+
+    pub fn write(&self, format: ProofFormat, writer: &mut impl Write) -> io::Result<()> {
+        // LRAT deletion lines start with the id of the last clause added
+        let mut last_id = 0;
+        for step in self.steps.iter() {
+            let (id, clause, hints) = match step {
+                ProofStep::Add { id, clause, hints } => (id, clause, hints),
+                ProofStep::Delete { id, clause } => {
+                    match format {
+                        ProofFormat::Drat => {
+                            write!(writer, "d ")?;
+                            for lit in clause {
+                                write!(writer, "{} ", lit)?;
+                            }
+                            writeln!(writer, "0")?;
+                        }
+                        ProofFormat::Lrat => writeln!(writer, "{} d {} 0", last_id, id)?,
+                    }
+                    continue;
+                }
+            };
+            last_id = *id;
+            if format == ProofFormat::Lrat {
+                write!(writer, "{} ", id)?;
+            }
+            for lit in clause {
+                write!(writer, "{} ", lit)?;
+            }
+            write!(writer, "0")?;
+            if format == ProofFormat::Lrat {
+                for hint in hints {
+                    write!(writer, " {}", hint)?;
+                }
+                write!(writer, " 0")?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_text(&self, format: ProofFormat) -> String {
+        let mut buffer = Vec::new();
+        self.write(format, &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+// Parses the numbers of an LRAT line up to the next 0
+fn read_until_zero<'a>(tokens: &mut impl Iterator<Item = &'a str>, line: usize) -> Result<Vec<isize>, String> {
+    let mut values = Vec::new();
+    loop {
+        let token = tokens.next().ok_or(format!("Line {}: missing terminating 0", line))?;
+        let value = token.parse::<isize>()
+            .map_err(|_| format!("Line {}: expected an integer, found '{}'", line, token))?;
+        if value == 0 {
+            return Ok(values);
+        }
+        values.push(value);
+    }
+}
+
+// Checks that clause follows from the hint clauses by unit propagation
+// Every hint must become unit under the negation of clause plus the earlier hints, and the last must be falsified
+fn check_rup(clause: &[isize], hints: &[isize], clauses: &HashMap<usize, Vec<isize>>, line: usize) -> Result<(), String> {
+    let mut assignment = HashMap::new();
+    for lit in clause {
+        assignment.insert(lit.unsigned_abs(), *lit < 0);
+    }
+
+    for hint in hints {
+        if *hint < 0 {
+            return Err(format!("Line {}: RAT hints are not supported", line));
+        }
+
+        let hint_clause = clauses.get(&(*hint as usize))
+            .ok_or(format!("Line {}: hint {} refers to an unknown or deleted clause", line, hint))?;
+
+        let mut unassigned = None;
+        for lit in hint_clause {
+            match assignment.get(&lit.unsigned_abs()) {
+                Some(value) if *value == (*lit > 0) => {
+                    return Err(format!("Line {}: hint {} is already satisfied", line, hint));
+                }
+                Some(_) => {}
+                None if unassigned.is_none_or(|unassigned| unassigned == *lit) => unassigned = Some(*lit),
+                None => return Err(format!("Line {}: hint {} is not unit", line, hint)),
+            }
+        }
+
+        match unassigned {
+            None => return Ok(()),
+            Some(lit) => {
+                assignment.insert(lit.unsigned_abs(), lit > 0);
+            }
+        }
+    }
+
+    Err(format!("Line {}: hints do not lead to a conflict", line))
+}
+
+// Verifies an LRAT refutation of the given clauses, which are numbered from 1
+// Succeeds only if every step is justified and the empty clause is derived
+pub fn check_lrat(clauses: &[Vec<isize>], proof: &str) -> Result<(), String> {
+    let mut known = clauses.iter()
+        .enumerate()
+        .map(|(idx, clause)| (idx + 1, clause.clone()))
+        .collect::<HashMap<_, _>>();
+    let mut last_id = clauses.len();
+
+    for (line_idx, text) in proof.lines().enumerate() {
+        let line = line_idx + 1;
+        let mut tokens = text.split_whitespace().peekable();
+
+        let id = match tokens.next() {
+            None => continue,
+            Some("c") => continue,
+            Some(token) => token.parse::<usize>()
+                .map_err(|_| format!("Line {}: expected a clause id, found '{}'", line, token))?,
+        };
+
+        if tokens.peek() == Some(&"d") {
+            tokens.next();
+            for deleted in read_until_zero(&mut tokens, line)? {
+                known.remove(&(deleted.unsigned_abs()));
+            }
+            continue;
+        }
+
+        if id <= last_id {
+            return Err(format!("Line {}: clause id {} is not increasing", line, id));
+        }
+        last_id = id;
+
+        let clause = read_until_zero(&mut tokens, line)?;
+        let hints = read_until_zero(&mut tokens, line)?;
+        check_rup(&clause, &hints, &known, line)?;
+
+        if clause.is_empty() {
+            return Ok(());
+        }
+        known.insert(id, clause);
+    }
+
+    Err(String::from("Proof does not derive the empty clause"))
+}
+
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use super::vm::{self, OpCode};
+
+// Largest number of variables all_assignments packs the results of, 2^32 bits is 512MB
+pub const MAX_BATCH_VARIABLES: usize = 32;
+
+// Evaluates a compiled program over 64 assignments at once, one per bit of a u64 lane
+// Keeps its program and its loads resolved to lanes, so it can be run any number of times
+pub struct BatchVM {
+    instructions: Vec<OpCode>,
+    // Index into variables of each Load, unused for other instructions
+    slots: Vec<usize>,
+    variables: Vec<String>,
+    values: Vec<u64>,
+    // Lanes of the shared subexpressions, see OpCode::Store
+    stored: Vec<u64>,
+}
+
+impl BatchVM {
+    pub fn new(instructions: Vec<OpCode>) -> BatchVM {
+        let mut indices = HashMap::new();
+        let mut variables = Vec::new();
+        let slots = instructions.iter()
+            .map(|instruction| match instruction {
+                OpCode::Load(name) => *indices.entry(name.clone()).or_insert_with(|| {
+                    variables.push(name.clone());
+                    variables.len() - 1
+                }),
+                _ => 0,
+            })
+            .collect();
+        BatchVM { instructions, slots, variables, values: Vec::new(), stored: Vec::new() }
+    }
+
+    // Variables the program loads, in the order of their first load
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    // lanes holds a lane for each of the variables, bit i of the result is the value under the assignment
+    // made of bit i of every lane
+    pub fn eval(&mut self, lanes: &[u64]) -> Result<u64, String> {
+        if lanes.len() != self.variables.len() {
+            return Err(format!("Expected {} lanes, one for each variable, found {}", self.variables.len(), lanes.len()));
+        }
+
+        // Pops the right-hand side, then the left-hand side
+        macro_rules! command {
+            (|$l:ident, $r:ident| $result:expr) => {
+                {
+                    let $r = self.values.pop().unwrap();
+                    let $l = self.values.pop().unwrap();
+                    self.values.push($result)
+                }
+            }
+        }
+
+        // A jump is only taken when it would be for every assignment, otherwise both operands are evaluated
+        // and merged at the target, with and for JumpIfFalse and or for JumpIfTrue
+        let mut merges: Vec<(usize, bool)> = Vec::new();
+        let mut cur = 0;
+        self.values.clear();
+        loop {
+            while let Some(&(_, is_and)) = merges.last().filter(|(target, _)| *target == cur) {
+                merges.pop();
+                command!(|l, r| if is_and { l & r } else { l | r });
+            }
+            if cur == self.instructions.len() {
+                break;
+            }
+
+            use OpCode::*;
+            match &self.instructions[cur] {
+                JumpIfFalse(target) | JumpIfTrue(target) => {
+                    let is_and = matches!(self.instructions[cur], JumpIfFalse(_));
+                    let decided = if is_and { 0 } else { !0 };
+                    if *self.values.last().unwrap() == decided {
+                        cur = *target;
+                        continue;
+                    }
+                    merges.push((*target, is_and));
+                }
+                Store(slot) => vm::store(&mut self.stored, *slot, *self.values.last().unwrap()),
+                Recall(slot) => self.values.push(self.stored[*slot]),
+                Load(_) => self.values.push(lanes[self.slots[cur]]),
+                T => self.values.push(!0),
+                F => self.values.push(0),
+                And => command!(|l, r| l & r),
+                Or => command!(|l, r| l | r),
+                Xor => command!(|l, r| l ^ r),
+                Iff => command!(|l, r| !(l ^ r)),
+                Implies => command!(|l, r| !l | r),
+                Nand => command!(|l, r| !(l & r)),
+                Nor => command!(|l, r| !(l | r)),
+                Not => {
+                    let a = self.values.pop().unwrap();
+                    self.values.push(!a);
+                }
+                // Counters differ from bit to bit, so they are evaluated one assignment at a time
+                Card(comparison, len, bound) => {
+                    let operands = self.values.split_off(self.values.len() - len);
+                    let result = (0..64)
+                        .filter(|bit| comparison.holds(operands.iter().filter(|lane| *lane >> bit & 1 == 1).count(), *bound))
+                        .fold(0, |result, bit| result | 1 << bit);
+                    self.values.push(result);
+                }
+                Pb(weights, comparison, bound) => {
+                    let operands = self.values.split_off(self.values.len() - weights.len());
+                    let sum = |bit: u64| operands.iter()
+                        .zip(weights)
+                        .filter(|(lane, _)| *lane >> bit & 1 == 1)
+                        .map(|(_, weight)| weight)
+                        .sum::<i64>();
+                    let result = (0..64)
+                        .filter(|bit| comparison.holds(sum(*bit), *bound))
+                        .fold(0, |result, bit| result | 1 << bit);
+                    self.values.push(result);
+                }
+            }
+            cur += 1;
+        }
+        Ok(self.values.pop().unwrap())
+    }
+
+    // Value under each assignment, each given as the values of the variables in order
+    pub fn eval_rows(&mut self, rows: &[Vec<bool>]) -> Result<Vec<bool>, String> {
+        let mut results = Vec::with_capacity(rows.len());
+        for chunk in rows.chunks(64) {
+            let mut lanes = vec![0; self.variables.len()];
+            for (bit, row) in chunk.iter().enumerate() {
+                if row.len() != self.variables.len() {
+                    return Err(format!("Expected {} values, one for each variable, found {}", self.variables.len(), row.len()));
+                }
+                for (lane, value) in lanes.iter_mut().zip(row) {
+                    *lane |= (*value as u64) << bit;
+                }
+            }
+            let result = self.eval(&lanes)?;
+            results.extend((0..chunk.len()).map(|bit| result >> bit & 1 == 1));
+        }
+        Ok(results)
+    }
+
+    // Value under every assignment of the given variables, which have to include all the loaded ones
+    // Bit i of word w is assignment 64w + i counted in binary, with the first variable the most significant
+    pub fn all_assignments(&mut self, variables: &[String]) -> Result<Vec<u64>, String> {
+        if variables.len() > MAX_BATCH_VARIABLES {
+            return Err(format!("Batch evaluation supports at most {} variables, found {}", MAX_BATCH_VARIABLES, variables.len()));
+        }
+        let positions = self.variables.iter()
+            .map(|name| variables.iter().position(|var| var == name).ok_or(format!("Undefined variable '{}'", name)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The last six variables cycle within a word, the others are the same across it
+        let n = variables.len();
+        let words = (1usize << n).div_ceil(64);
+        let mut results = Vec::with_capacity(words);
+        for word in 0..words {
+            let lanes = positions.iter()
+                .map(|position| match n - 1 - position {
+                    shift @ 0..6 => LOW_BIT_LANES[shift],
+                    shift => if (word * 64) >> shift & 1 == 1 { !0 } else { 0 },
+                })
+                .collect::<Vec<_>>();
+            results.push(self.eval(&lanes)?);
+        }
+        if n < 6 {
+            results[0] &= (1 << (1 << n)) - 1;
+        }
+        Ok(results)
+    }
+}
+
+// Lane of the variable at each of the six lowest binary digits of the assignment index
+const LOW_BIT_LANES: [u64; 6] = [
+    0xAAAA_AAAA_AAAA_AAAA,
+    0xCCCC_CCCC_CCCC_CCCC,
+    0xF0F0_F0F0_F0F0_F0F0,
+    0xFF00_FF00_FF00_FF00,
+    0xFFFF_0000_FFFF_0000,
+    0xFFFF_FFFF_0000_0000,
+];
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+#[derive(Default)]
 pub struct Env {
     identifier_to_value: HashMap<String, bool>,
 }
@@ -17,7 +18,7 @@ impl Env {
         self.identifier_to_value.remove(identifier);
     }
 
-    pub fn get(&mut self, identifier: &String) -> Option<bool> {
+    pub fn get(&self, identifier: &String) -> Option<bool> {
         self.identifier_to_value
             .get(identifier)
             .map(|v| *v)
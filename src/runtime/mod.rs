@@ -1,2 +1,7 @@
 pub mod vm;
+pub mod program;
 pub mod env;
+pub mod batch;
+pub mod peephole;
+pub mod partial;
+pub mod ternary;
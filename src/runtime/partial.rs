@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use crate::bdd::{self, Bdd};
+use crate::expr::{Backend, Expr};
+use crate::simplify::Simplifier;
+
+use super::env::Env;
+
+// Result of evaluating with only some of the variables defined
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Partial {
+    // The defined variables decide the expression
+    Value(bool),
+    // Simplified expression over the undefined variables
+    // Exactly the ones the value still depends on, unless the BDD of the residual was too big
+    Residual(Expr),
+}
+
+// Nodes the BDD of a residual may have before eval falls back to the solver
+pub const NODE_LIMIT: usize = 1 << 16;
+
+// Substitutes the defined variables and simplifies what is left
+// The BDD of the residual catches what the rules miss, so a decided expression always gives a value
+pub fn eval(expr: &Expr, env: &Env) -> Partial {
+    eval_within(expr, env, NODE_LIMIT)
+}
+
+// Like eval, with a limit on the BDD nodes
+// Past it the solver decides whether the residual is constant, and variables it doesn't depend on may stay
+pub fn eval_within(expr: &Expr, env: &Env, node_limit: usize) -> Partial {
+    let mut residual = Simplifier::new().simplify(&substitute(expr, env, &[]));
+    if let Expr::Literal(value) = residual {
+        return Partial::Value(value);
+    }
+
+    let mut manager = Bdd::new(Vec::new());
+    let Some(f) = manager.build_within(&residual, node_limit) else {
+        return if residual.is_tautology(Backend::Sat) {
+            Partial::Value(true)
+        } else if residual.is_contradiction(Backend::Sat) {
+            Partial::Value(false)
+        } else {
+            Partial::Residual(residual)
+        };
+    };
+    match f {
+        bdd::FALSE => return Partial::Value(false),
+        bdd::TRUE => return Partial::Value(true),
+        _ => {}
+    }
+
+    // Variables the value doesn't depend on can be fixed to anything, which the rules then remove
+    let support = manager.support(f);
+    let irrelevant = residual.get_variables().into_iter().filter(|name| !support.contains(name)).collect::<Vec<_>>();
+    if !irrelevant.is_empty() {
+        residual = Simplifier::new().simplify(&substitute(&residual, &Env::new(), &irrelevant));
+    }
+    Partial::Residual(residual)
+}
+
+// Replaces each defined variable by its value, and each of the fixed ones by false
+fn substitute(expr: &Expr, env: &Env, fixed: &[String]) -> Expr {
+    substitute_aux(expr, env, fixed, &mut HashMap::new())
+}
+
+// Substitutes into each shared node once, done holds the nodes substituted so far
+fn substitute_aux(expr: &Expr, env: &Env, fixed: &[String], done: &mut HashMap<*const Expr, Expr>) -> Expr {
+    if let Some(result) = done.get(&(expr as *const Expr)) {
+        return result.clone();
+    }
+    let result = match expr {
+        Expr::Var(name) if fixed.contains(name) => Expr::Literal(false),
+        Expr::Var(name) => env.get(name).map_or_else(|| expr.clone(), Expr::Literal),
+        _ => expr.node().map(|operand| substitute_aux(operand, env, fixed, done)).into_expr(),
+    };
+    done.insert(expr, result.clone());
+    result
+}
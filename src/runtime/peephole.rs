@@ -0,0 +1,79 @@
+use std::collections::HashSet;
+
+use super::env::Env;
+use super::vm::{OpCode, VM};
+
+// Number of values an operator pops, None for instructions that aren't operators
+fn arity(code: &OpCode) -> Option<usize> {
+    match code {
+        OpCode::Not => Some(1),
+        OpCode::And | OpCode::Or | OpCode::Xor | OpCode::Iff | OpCode::Implies | OpCode::Nand | OpCode::Nor => Some(2),
+        OpCode::Card(_, len, _) => Some(*len),
+        OpCode::Pb(weights, ..) => Some(weights.len()),
+        OpCode::Load(_) | OpCode::T | OpCode::F | OpCode::JumpIfFalse(_) | OpCode::JumpIfTrue(_)
+        | OpCode::Store(_) | OpCode::Recall(_) => None,
+    }
+}
+
+// Value of codes if it is an operator applied to constants only
+pub fn fold(codes: &[OpCode]) -> Option<bool> {
+    let (op, operands) = codes.split_last()?;
+    if arity(op)? != operands.len() || !operands.iter().all(|code| matches!(code, OpCode::T | OpCode::F)) {
+        return None;
+    }
+    VM::new(&Env::new(), codes.to_vec()).eval().ok()
+}
+
+// Removes pairs of negations and evaluates operators over constants, then points the jumps at the new indices
+// Nothing is merged into an instruction that a jump lands on, since the stack may differ when arriving there
+pub fn optimize(codes: Vec<OpCode>) -> Vec<OpCode> {
+    let targets = codes.iter()
+        .filter_map(|code| match code {
+            OpCode::JumpIfFalse(target) | OpCode::JumpIfTrue(target) => Some(*target),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    let mut out: Vec<OpCode> = Vec::with_capacity(codes.len());
+    // Whether each instruction of out is the first of something a jump lands on
+    let mut landed_on: Vec<bool> = Vec::with_capacity(codes.len());
+    let mut indices = Vec::with_capacity(codes.len() + 1);
+    // Set when a removed negation was landed on, so the jump now lands on whatever comes next
+    let mut carried = false;
+    for (idx, code) in codes.into_iter().enumerate() {
+        indices.push(out.len());
+        let target = targets.contains(&idx) || carried;
+        carried = false;
+        if !target {
+            if matches!(code, OpCode::Not) && matches!(out.last(), Some(OpCode::Not)) {
+                out.pop();
+                carried |= landed_on.pop().unwrap();
+                continue;
+            }
+
+            if let Some(len) = arity(&code).filter(|len| *len <= out.len()) {
+                let start = out.len() - len;
+                let mut window = out[start..].to_vec();
+                window.push(code.clone());
+                if let Some(value) = fold(&window).filter(|_| !landed_on[start..].iter().skip(1).any(|landed| *landed)) {
+                    let first_landed = landed_on.get(start).copied().unwrap_or(false);
+                    out.truncate(start);
+                    landed_on.truncate(start);
+                    out.push(if value { OpCode::T } else { OpCode::F });
+                    landed_on.push(first_landed);
+                    continue;
+                }
+            }
+        }
+        out.push(code);
+        landed_on.push(target);
+    }
+    indices.push(out.len());
+
+    for code in out.iter_mut() {
+        if let OpCode::JumpIfFalse(target) | OpCode::JumpIfTrue(target) = code {
+            *target = indices[*target];
+        }
+    }
+    out
+}
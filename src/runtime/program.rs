@@ -0,0 +1,116 @@
+use super::env::Env;
+use super::partial::{self, Partial};
+use super::ternary::{self, Logic, Truth};
+use super::vm::{OpCode, VM};
+use crate::card::CardEncoding;
+use crate::expr::Expr;
+use crate::pb::PbEncoding;
+
+// Compiled expression, which can be evaluated any number of times against different environments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    instructions: Vec<OpCode>,
+}
+
+impl Program {
+    pub fn new(instructions: Vec<OpCode>) -> Program {
+        Program { instructions }
+    }
+
+    pub fn instructions(&self) -> &[OpCode] {
+        &self.instructions
+    }
+
+    // An undefined variable is only an error if the result depends on it
+    // The jumps skip most of the ones it doesn't depend on, the Kleene evaluator settles most of the rest by
+    // treating them as unknown, and partial evaluation of the expression the program computes settles the
+    // ones it misses, like x or not x
+    pub fn eval(&self, env: &Env) -> Result<bool, String> {
+        match VM::from_program(env, self).eval() {
+            Err(msg) if self.loads_undefined(env) => ternary::eval(&self.instructions, env, Logic::Kleene).known()
+                .or_else(|| match partial::eval(&decompile(&self.instructions), env) {
+                    Partial::Value(value) => Some(value),
+                    Partial::Residual(_) => None,
+                })
+                .ok_or(msg),
+            result => result,
+        }
+    }
+
+    // Value in a three-valued logic, where undefined variables are unknown rather than an error
+    pub fn eval_ternary(&self, env: &Env, logic: Logic) -> Truth {
+        ternary::eval(&self.instructions, env, logic)
+    }
+
+    // Whether the program loads a variable env doesn't define, which is the only way the VM fails
+    fn loads_undefined(&self, env: &Env) -> bool {
+        self.instructions.iter().any(|code| matches!(code, OpCode::Load(name) if env.get(name).is_none()))
+    }
+}
+
+// Expression a program computes, with the variables it loads
+// Each jump is read as the connective it short-circuits, and for JumpIfFalse and or for JumpIfTrue
+fn decompile(instructions: &[OpCode]) -> Expr {
+    let mut values: Vec<Expr> = Vec::new();
+    let mut slots: Vec<Expr> = Vec::new();
+
+    // Pops the right-hand side, then the left-hand side
+    macro_rules! command {
+        (|$l:ident, $r:ident| $result:expr) => {
+            {
+                let $r = values.pop().unwrap();
+                let $l = values.pop().unwrap();
+                values.push($result)
+            }
+        }
+    }
+
+    // The left-hand side of a jump stays on the stack until the right-hand side is merged with it at the target
+    let mut merges: Vec<(usize, bool)> = Vec::new();
+    for cur in 0..=instructions.len() {
+        while let Some(&(_, is_and)) = merges.last().filter(|(target, _)| *target == cur) {
+            merges.pop();
+            command!(|l, r| if is_and { Expr::and(l, r) } else { Expr::or(l, r) });
+        }
+        let Some(instruction) = instructions.get(cur) else {
+            break;
+        };
+
+        use OpCode::*;
+        match instruction {
+            JumpIfFalse(target) => merges.push((*target, true)),
+            JumpIfTrue(target) => merges.push((*target, false)),
+            Load(name) => values.push(Expr::Var(name.clone())),
+            T => values.push(Expr::Literal(true)),
+            F => values.push(Expr::Literal(false)),
+            And => command!(|l, r| Expr::and(l, r)),
+            Or => command!(|l, r| Expr::or(l, r)),
+            Xor => command!(|l, r| Expr::xor(l, r)),
+            Iff => command!(|l, r| Expr::iff(l, r)),
+            Implies => command!(|l, r| Expr::eif(l, r)),
+            Nand => command!(|l, r| Expr::nand(l, r)),
+            Nor => command!(|l, r| Expr::nor(l, r)),
+            Not => {
+                let a = values.pop().unwrap();
+                values.push(Expr::not(a));
+            }
+            Card(comparison, len, bound) => {
+                let operands = values.split_off(values.len() - len);
+                values.push(Expr::cardinality(operands, *comparison, *bound, CardEncoding::default()));
+            }
+            Pb(weights, comparison, bound) => {
+                let operands = values.split_off(values.len() - weights.len());
+                let terms = weights.iter().copied().zip(operands).collect();
+                values.push(Expr::pseudo_boolean(terms, *comparison, *bound, PbEncoding::default()));
+            }
+            Store(slot) => {
+                if slots.len() <= *slot {
+                    slots.resize(slot + 1, Expr::Literal(false));
+                }
+                slots[*slot] = values.last().unwrap().clone();
+            }
+            Recall(slot) => values.push(slots[*slot].clone()),
+        }
+    }
+    values.pop().unwrap()
+}
@@ -0,0 +1,196 @@
+use std::ops::Not;
+use std::str::FromStr;
+
+use super::env::Env;
+use super::vm::{self, OpCode};
+use crate::card::Comparison;
+
+// Value in a three-valued logic, ordered so that and is min and or is max
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Truth {
+    False,
+    #[default]
+    Unknown,
+    True,
+}
+
+impl Truth {
+    // The bool, if the value is known
+    pub fn known(self) -> Option<bool> {
+        match self {
+            Truth::False => Some(false),
+            Truth::Unknown => None,
+            Truth::True => Some(true),
+        }
+    }
+
+    // Position on the scale false = 0, unknown = 1, true = 2
+    fn level(self) -> i8 {
+        self as i8
+    }
+
+    fn from_level(level: i8) -> Truth {
+        match level {
+            0 => Truth::False,
+            1 => Truth::Unknown,
+            _ => Truth::True,
+        }
+    }
+}
+
+impl From<bool> for Truth {
+    fn from(value: bool) -> Truth {
+        if value { Truth::True } else { Truth::False }
+    }
+}
+
+impl From<Option<bool>> for Truth {
+    fn from(value: Option<bool>) -> Truth {
+        value.map_or(Truth::Unknown, Truth::from)
+    }
+}
+
+impl Not for Truth {
+    type Output = Truth;
+
+    fn not(self) -> Truth {
+        Truth::from_level(2 - self.level())
+    }
+}
+
+// Three-valued logics, which agree with classical logic when nothing is unknown
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Logic {
+    // Strong Kleene, where a connective is unknown unless the known operands decide it
+    #[default]
+    Kleene,
+    // Kleene's and, or and not, with implication min(1, 1 - a + b), so unknown -> unknown is true
+    // Iff is implication both ways and xor its negation
+    Lukasiewicz,
+}
+
+impl FromStr for Logic {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Logic, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "kleene" => Ok(Logic::Kleene),
+            "lukasiewicz" | "łukasiewicz" => Ok(Logic::Lukasiewicz),
+            _ => Err(format!("Unknown logic '{}', expected 'kleene' or 'lukasiewicz'", name)),
+        }
+    }
+}
+
+impl Logic {
+    fn implies(self, l: Truth, r: Truth) -> Truth {
+        match self {
+            Logic::Kleene => (!l).max(r),
+            Logic::Lukasiewicz => Truth::from_level((2 - l.level() + r.level()).min(2)),
+        }
+    }
+
+    fn iff(self, l: Truth, r: Truth) -> Truth {
+        self.implies(l, r).min(self.implies(r, l))
+    }
+}
+
+// Value of a compiled program where undefined variables are unknown
+// Programs with short-circuit jumps give Kleene's implication, so Lukasiewicz logic needs Expr::compile_eager
+pub fn eval(instructions: &[OpCode], env: &Env, logic: Logic) -> Truth {
+    let mut values: Vec<Truth> = Vec::new();
+    let mut slots: Vec<Truth> = Vec::new();
+
+    // Pops the right-hand side, then the left-hand side
+    macro_rules! command {
+        (|$l:ident, $r:ident| $result:expr) => {
+            {
+                let $r = values.pop().unwrap();
+                let $l = values.pop().unwrap();
+                values.push($result)
+            }
+        }
+    }
+
+    // An unknown left-hand side doesn't decide a jump, so the right-hand side is evaluated and merged with it
+    // at the target, with and for JumpIfFalse and or for JumpIfTrue
+    let mut merges: Vec<(usize, bool)> = Vec::new();
+    let mut cur = 0;
+    loop {
+        while let Some(&(_, is_and)) = merges.last().filter(|(target, _)| *target == cur) {
+            merges.pop();
+            command!(|l, r| if is_and { l.min(r) } else { l.max(r) });
+        }
+        if cur == instructions.len() {
+            break;
+        }
+
+        use OpCode::*;
+        match &instructions[cur] {
+            JumpIfFalse(target) | JumpIfTrue(target) => {
+                let is_and = matches!(instructions[cur], JumpIfFalse(_));
+                match (values.last().unwrap().known(), is_and) {
+                    (Some(false), true) | (Some(true), false) => {
+                        cur = *target;
+                        continue;
+                    }
+                    (Some(_), _) => {
+                        values.pop();
+                    }
+                    (None, _) => merges.push((*target, is_and)),
+                }
+            }
+            Store(slot) => vm::store(&mut slots, *slot, *values.last().unwrap()),
+            Recall(slot) => values.push(slots[*slot]),
+            Load(name) => values.push(env.get(name).into()),
+            T => values.push(Truth::True),
+            F => values.push(Truth::False),
+            And => command!(|l, r| l.min(r)),
+            Or => command!(|l, r| l.max(r)),
+            Xor => command!(|l, r| !logic.iff(l, r)),
+            Iff => command!(|l, r| logic.iff(l, r)),
+            Implies => command!(|l, r| logic.implies(l, r)),
+            Nand => command!(|l, r| !l.min(r)),
+            Nor => command!(|l, r| !l.max(r)),
+            Not => {
+                let a = values.pop().unwrap();
+                values.push(!a);
+            }
+            // Known when the comparison holds for every value the unknown operands could give, or for none
+            Card(comparison, len, bound) => {
+                let operands = values.split_off(values.len() - len);
+                let count = |value| operands.iter().filter(|operand| **operand == value).count();
+                let trues = count(Truth::True);
+                values.push(within(*comparison, trues, trues + count(Truth::Unknown), *bound));
+            }
+            Pb(weights, comparison, bound) => {
+                let operands = values.split_off(values.len() - weights.len());
+                let sum = |value: Truth, keep: fn(&i64) -> bool| operands.iter()
+                    .zip(weights)
+                    .filter(|(operand, weight)| **operand == value && keep(weight))
+                    .map(|(_, weight)| weight)
+                    .sum::<i64>();
+                let trues = sum(Truth::True, |_| true);
+                let (low, high) = (trues + sum(Truth::Unknown, |weight| *weight < 0), trues + sum(Truth::Unknown, |weight| *weight > 0));
+                values.push(within(*comparison, low, high, *bound));
+            }
+        }
+        cur += 1;
+    }
+    values.pop().unwrap()
+}
+
+// Whether the comparison holds for a value known to be between low and high
+fn within<T: Ord>(comparison: Comparison, low: T, high: T, bound: T) -> Truth {
+    let (always, never) = match comparison {
+        Comparison::AtMost => (high <= bound, low > bound),
+        Comparison::AtLeast => (low >= bound, high < bound),
+        Comparison::Exactly => (low == high && low == bound, bound < low || bound > high),
+    };
+    if always {
+        Truth::True
+    } else if never {
+        Truth::False
+    } else {
+        Truth::Unknown
+    }
+}
@@ -1,74 +1,133 @@
+use std::borrow::Cow;
+
 use super::env::Env;
+use super::program::Program;
+use crate::card::Comparison;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum OpCode {
     Load(String),
     T,
     F,
     And,
     Or,
+    Xor,
+    Iff,
+    Implies,
+    Nand,
+    Nor,
     Not,
-    Used,
-}
-
-impl Default for OpCode {
-    fn default() -> Self {
-        OpCode::Used
-    }
+    // Pops the given number of values and compares how many are true with the bound
+    Card(Comparison, usize, usize),
+    // Pops a value for each weight and compares the weighted sum of the true ones with the bound
+    Pb(Vec<i64>, Comparison, i64),
+    // Jump to the instruction at the index, keeping the value as the result, if it is false or true
+    // Otherwise the value is popped and execution goes on with the next instruction
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+    // Copies the value of a shared subexpression into the slot where it is computed, and pushes it back
+    // wherever it occurs again
+    Store(usize),
+    Recall(usize),
 }
 
 pub struct VM<'a> {
-    env: &'a mut Env,
+    env: &'a Env,
     values: Vec<bool>,
-    instructions: Vec<OpCode>,
+    slots: Vec<bool>,
+    instructions: Cow<'a, [OpCode]>,
     cur: usize,
 }
 
 impl<'a> VM<'a> {
-    pub fn new(env: &'a mut Env, instructions: Vec<OpCode>) -> VM<'a> {
-        VM { env, values: Vec::new(), instructions, cur: 0 }
+    pub fn new(env: &'a Env, instructions: Vec<OpCode>) -> VM<'a> {
+        VM { env, values: Vec::new(), slots: Vec::new(), instructions: Cow::Owned(instructions), cur: 0 }
     }
 
-    fn load(&mut self, name: &String) -> Result<(), String> {
-        let val = self.env.get(name).ok_or(
-            format!("Undefined variable '{}'", name)
-        )?;
-        self.values.push(val);
-        Ok(())
+    // Runs a program without copying its instructions
+    pub fn from_program(env: &'a Env, program: &'a Program) -> VM<'a> {
+        VM { env, values: Vec::new(), slots: Vec::new(), instructions: Cow::Borrowed(program.instructions()), cur: 0 }
     }
 
-    fn execute_next(&mut self, cur: usize) -> Result<(), String> {
+    // Runs the instruction at cur, returning the index of the next one
+    fn execute_next(&mut self, cur: usize) -> Result<usize, String> {
+        // Pops the right-hand side, then the left-hand side
         macro_rules! command {
-            ($op:tt) => {
+            (|$l:ident, $r:ident| $result:expr) => {
                 {
-                    let a = self.values.pop().unwrap();
-                    let b = self.values.pop().unwrap();
-                    self.values.push(a $op b)
+                    let $r = self.values.pop().unwrap();
+                    let $l = self.values.pop().unwrap();
+                    self.values.push($result)
                 }
             }
         }
 
         use OpCode::*;
-        match std::mem::take(&mut self.instructions[cur]) {
-            Load(name) => self.load(&name)?,
+        match &self.instructions[cur] {
+            Load(name) => {
+                let val = self.env.get(name).ok_or(
+                    format!("Undefined variable '{}'", name)
+                )?;
+                self.values.push(val);
+            }
             T => self.values.push(true),
             F => self.values.push(false),
-            And => command!(&&),
-            Or => command!(||),
+            And => command!(|l, r| l && r),
+            Or => command!(|l, r| l || r),
+            Xor => command!(|l, r| l != r),
+            Iff => command!(|l, r| l == r),
+            Implies => command!(|l, r| !l || r),
+            Nand => command!(|l, r| !(l && r)),
+            Nor => command!(|l, r| !(l || r)),
             Not => {
                 let a = self.values.pop().unwrap();
                 self.values.push(!a);
             },
-            Used => unreachable!()
+            Card(comparison, len, bound) => {
+                let count = self.values.split_off(self.values.len() - len)
+                    .into_iter()
+                    .filter(|value| *value)
+                    .count();
+                self.values.push(comparison.holds(count, *bound));
+            }
+            Pb(weights, comparison, bound) => {
+                let sum = self.values.split_off(self.values.len() - weights.len())
+                    .into_iter()
+                    .zip(weights)
+                    .filter(|(value, _)| *value)
+                    .map(|(_, weight)| weight)
+                    .sum::<i64>();
+                self.values.push(comparison.holds(sum, *bound));
+            }
+            JumpIfFalse(target) | JumpIfTrue(target) => {
+                let value = *self.values.last().unwrap();
+                if value == matches!(self.instructions[cur], JumpIfTrue(_)) {
+                    return Ok(*target);
+                }
+                self.values.pop();
+            }
+            Store(slot) => store(&mut self.slots, *slot, *self.values.last().unwrap()),
+            Recall(slot) => self.values.push(self.slots[*slot]),
         }
-        Ok(())
+        Ok(cur + 1)
     }
 
+    // Runs the instructions from the start, so a VM can be evaluated more than once
     pub fn eval(&mut self) -> Result<bool, String> {
+        self.cur = 0;
+        self.values.clear();
+        self.slots.clear();
         while self.cur < self.instructions.len() {
-            self.execute_next(self.cur)?;
-            self.cur += 1;
+            self.cur = self.execute_next(self.cur)?;
         }
         Ok(self.values.pop().unwrap())
     }
 }
+
+// Puts value in the slot, making room for it if this is the first store to it
+pub fn store<T: Copy + Default>(slots: &mut Vec<T>, slot: usize, value: T) {
+    if slots.len() <= slot {
+        slots.resize(slot + 1, T::default());
+    }
+    slots[slot] = value;
+}
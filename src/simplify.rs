@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::card::{CardEncoding, Comparison};
+use crate::dag::Node;
+use crate::expr::Expr;
+use crate::pb::PbEncoding;
+
+// A rewrite rule gets an expression whose operands are already simplified
+// It returns the expression to replace it with, or None if it doesn't apply
+pub type Rule = Arc<dyn Fn(&Expr) -> Option<Expr> + Send + Sync>;
+
+// Rewrites expressions bottom up, replacing each one by the first rule that applies until none does
+// Rules have to make progress, by shrinking the expression or pushing negations inward, or this won't terminate
+#[derive(Clone)]
+pub struct Simplifier {
+    rules: Vec<(String, Rule)>,
+}
+
+impl Default for Simplifier {
+    fn default() -> Simplifier {
+        Simplifier::new()
+    }
+}
+
+impl Simplifier {
+    // Simplifier with the built-in rules, in the order they are tried
+    pub fn new() -> Simplifier {
+        let mut simplifier = Simplifier::empty();
+        simplifier.add_rule("constants", constants);
+        simplifier.add_rule("double-negation", double_negation);
+        simplifier.add_rule("complement", complement);
+        simplifier.add_rule("idempotence", idempotence);
+        simplifier.add_rule("absorption", absorption);
+        simplifier.add_rule("de-morgan", de_morgan);
+        simplifier
+    }
+
+    pub fn empty() -> Simplifier {
+        Simplifier { rules: Vec::new() }
+    }
+
+    pub fn add_rule(&mut self, name: &str, rule: impl Fn(&Expr) -> Option<Expr> + Send + Sync + 'static) {
+        self.rules.push((name.to_string(), Arc::new(rule)));
+    }
+
+    // Adds a rule replacing every match of pattern by replacement
+    // Variables in the pattern match any subexpression, and each occurrence of one has to match the same subexpression
+    pub fn add_pattern(&mut self, pattern: Expr, replacement: Expr) -> Result<(), String> {
+        let bound = pattern.get_variables();
+        if let Some(name) = replacement.get_variables().into_iter().find(|name| !bound.contains(name)) {
+            return Err(format!("Variable '{}' of the replacement doesn't occur in the pattern", name));
+        }
+
+        let name = format!("{} => {}", pattern, replacement);
+        self.add_rule(&name, move |expr| {
+            let mut bindings = HashMap::new();
+            matches(&pattern, expr, &mut bindings).then(|| substitute(&replacement, &bindings))
+        });
+        Ok(())
+    }
+
+    pub fn remove_rule(&mut self, name: &str) -> Result<(), String> {
+        let idx = self.rules.iter()
+            .position(|(rule, _)| rule == name)
+            .ok_or(format!("Unknown rule '{}'", name))?;
+        self.rules.remove(idx);
+        Ok(())
+    }
+
+    pub fn rule_names(&self) -> Vec<String> {
+        self.rules.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    pub fn simplify(&self, expr: &Expr) -> Expr {
+        self.simplify_aux(expr, &mut HashMap::new())
+    }
+
+    // Simplifies each shared node once, done maps the nodes simplified so far to the result
+    fn simplify_aux(&self, expr: &Expr, done: &mut HashMap<Expr, Expr>) -> Expr {
+        if let Some(simplified) = done.get(expr) {
+            return simplified.clone();
+        }
+        let operands = expr.node().map(|operand| self.simplify_aux(operand, done)).into_expr();
+
+        // A rewritten expression may have new operands that aren't simplified yet
+        let simplified = match self.rules.iter().find_map(|(_, rule)| rule(&operands).filter(|rewritten| *rewritten != operands)) {
+            Some(rewritten) => self.simplify_aux(&rewritten, done),
+            None => operands,
+        };
+        done.insert(expr.clone(), simplified.clone());
+        simplified
+    }
+}
+
+// Binds the variables of pattern to the subexpressions of expr they line up with
+fn matches<'a>(pattern: &Expr, expr: &'a Expr, bindings: &mut HashMap<String, &'a Expr>) -> bool {
+    if let Expr::Var(name) = pattern {
+        return match bindings.get(name) {
+            Some(bound) => *bound == expr,
+            None => {
+                bindings.insert(name.clone(), expr);
+                true
+            }
+        };
+    }
+
+    // Same connective with the same bound, or the same literal, and matching operands
+    let (pattern, expr) = (pattern.node(), expr.node());
+    pattern.map(|_| ()) == expr.map(|_| ())
+        && pattern.operands().into_iter()
+            .zip(expr.operands())
+            .all(|(pattern, expr)| matches(pattern, expr, bindings))
+}
+
+fn substitute(expr: &Expr, bindings: &HashMap<String, &Expr>) -> Expr {
+    match expr {
+        Expr::Var(name) => (*bindings[name]).clone(),
+        _ => expr.node().map(|operand| substitute(operand, bindings)).into_expr(),
+    }
+}
+
+// Operands of a chain of conjunctions, or disjunctions, with the kind of chain, true for conjunctions
+fn junction(expr: &Expr) -> Option<(bool, Vec<Expr>)> {
+    fn collect(expr: &Expr, is_and: bool, operands: &mut Vec<Expr>) {
+        match expr.node() {
+            Node::And(l, r) if is_and => {
+                collect(l, is_and, operands);
+                collect(r, is_and, operands);
+            }
+            Node::Or(l, r) if !is_and => {
+                collect(l, is_and, operands);
+                collect(r, is_and, operands);
+            }
+            _ => operands.push(expr.clone()),
+        }
+    }
+
+    let is_and = match expr {
+        Expr::And(_) => true,
+        Expr::Or(_) => false,
+        _ => return None,
+    };
+    let mut operands = Vec::new();
+    collect(expr, is_and, &mut operands);
+    Some((is_and, operands))
+}
+
+// Chain of the operands associated to the left, the chain of no operands is its identity
+fn rebuild(is_and: bool, operands: Vec<Expr>) -> Expr {
+    operands.into_iter()
+        .reduce(|l, r| if is_and { Expr::and(l, r) } else { Expr::or(l, r) })
+        .unwrap_or(Expr::Literal(is_and))
+}
+
+fn is_negation(l: &Expr, r: &Expr) -> bool {
+    matches!(l.node(), Node::Not(inner) if inner == r) || matches!(r.node(), Node::Not(inner) if inner == l)
+}
+
+// Propagates literals through connectives and constraints
+fn constants(expr: &Expr) -> Option<Expr> {
+    let node = expr.node();
+    let (value, other, on_left) = match node {
+        Node::Not(Expr::Literal(value)) => return Some(Expr::Literal(!value)),
+        Node::Card(exprs, comparison, bound, encoding) => return card_constants(exprs, comparison, bound, encoding),
+        Node::Pb(terms, comparison, bound, encoding) => return pb_constants(terms, comparison, bound, encoding),
+        _ => match node.operands()[..] {
+            [Expr::Literal(value), other] => (*value, (*other).clone(), true),
+            [other, Expr::Literal(value)] => (*value, (*other).clone(), false),
+            _ => return None,
+        },
+    };
+
+    Some(match (node, value) {
+        (Node::And(..), true) | (Node::Or(..), false) | (Node::Xor(..), false) | (Node::Iff(..), true) => other,
+        (Node::And(..), false) | (Node::Nor(..), true) => Expr::Literal(false),
+        (Node::Or(..), true) | (Node::Nand(..), false) => Expr::Literal(true),
+        (Node::Xor(..), true) | (Node::Iff(..), false) | (Node::Nand(..), true) | (Node::Nor(..), false) => Expr::not(other),
+        (Node::Implies(..), value) => match (on_left, value) {
+            (true, false) | (false, true) => Expr::Literal(true),
+            (true, true) => other,
+            (false, false) => Expr::not(other),
+        },
+        _ => unreachable!(),
+    })
+}
+
+// Counts the true operands towards the bound, and decides the constraint if every possible count agrees
+fn card_constants(
+    exprs: Vec<&Expr>,
+    comparison: Comparison,
+    bound: usize,
+    encoding: CardEncoding,
+) -> Option<Expr> {
+    let known = exprs.iter().filter(|expr| ***expr == Expr::Literal(true)).count();
+    let rest = exprs.iter()
+        .filter(|expr| !matches!(expr, Expr::Literal(_)))
+        .map(|expr| (*expr).clone())
+        .collect::<Vec<_>>();
+
+    let holds = (known..=known + rest.len()).map(|count| comparison.holds(count, bound)).collect::<Vec<_>>();
+    if holds.iter().all(|holds| *holds) {
+        Some(Expr::Literal(true))
+    } else if !holds.iter().any(|holds| *holds) {
+        Some(Expr::Literal(false))
+    } else if rest.len() < exprs.len() {
+        // Some count holds, so the bound is at least the number of true operands
+        Some(Expr::cardinality(rest, comparison, bound - known, encoding))
+    } else {
+        None
+    }
+}
+
+// Moves the weights of true operands into the bound, and decides the constraint if the reachable sums agree
+fn pb_constants(
+    terms: Vec<(i64, &Expr)>,
+    comparison: Comparison,
+    bound: i64,
+    encoding: PbEncoding,
+) -> Option<Expr> {
+    let known = terms.iter()
+        .filter(|(_, expr)| **expr == Expr::Literal(true))
+        .map(|(weight, _)| weight)
+        .sum::<i64>();
+    let rest = terms.iter()
+        .filter(|(weight, expr)| *weight != 0 && !matches!(expr, Expr::Literal(_)))
+        .map(|(weight, expr)| (*weight, (*expr).clone()))
+        .collect::<Vec<_>>();
+
+    let min = known + rest.iter().map(|(weight, _)| (*weight).min(0)).sum::<i64>();
+    let max = known + rest.iter().map(|(weight, _)| (*weight).max(0)).sum::<i64>();
+    let decided = match comparison {
+        Comparison::AtMost if max <= bound => Some(true),
+        Comparison::AtMost if min > bound => Some(false),
+        Comparison::AtLeast if min >= bound => Some(true),
+        Comparison::AtLeast if max < bound => Some(false),
+        Comparison::Exactly if min == max => Some(min == bound),
+        Comparison::Exactly if bound < min || bound > max => Some(false),
+        _ => None,
+    };
+
+    match decided {
+        Some(value) => Some(Expr::Literal(value)),
+        None if rest.len() < terms.len() => Some(Expr::pseudo_boolean(rest, comparison, bound - known, encoding)),
+        None => None,
+    }
+}
+
+fn double_negation(expr: &Expr) -> Option<Expr> {
+    let Node::Not(inner) = expr.node() else {
+        return None;
+    };
+    match inner.node() {
+        Node::Not(inner) => Some(inner.clone()),
+        _ => None,
+    }
+}
+
+// An operand together with its negation, e.g. a and not a
+fn complement(expr: &Expr) -> Option<Expr> {
+    if let Some((is_and, operands)) = junction(expr) {
+        let complementary = operands.iter()
+            .enumerate()
+            .any(|(idx, l)| operands[idx + 1..].iter().any(|r| is_negation(l, r)));
+        return complementary.then_some(Expr::Literal(!is_and));
+    }
+
+    match expr.node() {
+        Node::Xor(l, r) | Node::Nand(l, r) if is_negation(l, r) => Some(Expr::Literal(true)),
+        Node::Iff(l, r) | Node::Nor(l, r) if is_negation(l, r) => Some(Expr::Literal(false)),
+        Node::Implies(l, r) if is_negation(l, r) => Some(r.clone()),
+        _ => None,
+    }
+}
+
+// An operand repeated, e.g. a or a
+fn idempotence(expr: &Expr) -> Option<Expr> {
+    if let Some((is_and, operands)) = junction(expr) {
+        let mut unique = Vec::new();
+        for operand in operands.iter() {
+            if !unique.contains(operand) {
+                unique.push(operand.clone());
+            }
+        }
+        return (unique.len() < operands.len()).then(|| rebuild(is_and, unique));
+    }
+
+    match expr.node() {
+        Node::Xor(l, r) if l == r => Some(Expr::Literal(false)),
+        Node::Iff(l, r) | Node::Implies(l, r) if l == r => Some(Expr::Literal(true)),
+        Node::Nand(l, r) | Node::Nor(l, r) if l == r => Some(Expr::not(l.clone())),
+        _ => None,
+    }
+}
+
+// An operand that is implied by another operand of a conjunction, e.g. a and (a or b), or the dual for disjunctions
+fn absorption(expr: &Expr) -> Option<Expr> {
+    let (is_and, operands) = junction(expr)?;
+    let inner = operands.iter()
+        .map(|operand| match junction(operand) {
+            Some((kind, inner)) if kind != is_and => inner,
+            _ => vec![operand.clone()],
+        })
+        .collect::<Vec<_>>();
+
+    // Operand i goes when the inner operands of another one are among its own, keeping the first of equal ones
+    let absorbed = |i: usize| (0..operands.len()).any(|j| {
+        j != i
+            && inner[j].iter().all(|operand| inner[i].contains(operand))
+            && (inner[j].len() < inner[i].len() || j < i)
+    });
+    let kept = operands.iter()
+        .enumerate()
+        .filter(|(idx, _)| !absorbed(*idx))
+        .map(|(_, operand)| operand.clone())
+        .collect::<Vec<_>>();
+    (kept.len() < operands.len()).then(|| rebuild(is_and, kept))
+}
+
+// Pushes negations into connectives and cardinality constraints, towards the variables
+fn de_morgan(expr: &Expr) -> Option<Expr> {
+    let Node::Not(inner) = expr.node() else {
+        return None;
+    };
+    let negated = |expr: &Expr| Expr::not(expr.clone());
+
+    Some(match inner.node() {
+        Node::And(l, r) => Expr::or(negated(l), negated(r)),
+        Node::Or(l, r) => Expr::and(negated(l), negated(r)),
+        Node::Nand(l, r) => Expr::and(l.clone(), r.clone()),
+        Node::Nor(l, r) => Expr::or(l.clone(), r.clone()),
+        Node::Implies(l, r) => Expr::and(l.clone(), negated(r)),
+        Node::Xor(l, r) => Expr::iff(l.clone(), r.clone()),
+        Node::Iff(l, r) => Expr::xor(l.clone(), r.clone()),
+        Node::Card(exprs, Comparison::AtMost, bound, encoding) => {
+            Expr::cardinality(exprs.into_iter().cloned().collect(), Comparison::AtLeast, bound + 1, encoding)
+        }
+        Node::Card(exprs, Comparison::AtLeast, bound, encoding) if bound > 0 => {
+            Expr::cardinality(exprs.into_iter().cloned().collect(), Comparison::AtMost, bound - 1, encoding)
+        }
+        _ => return None,
+    })
+}
@@ -0,0 +1,986 @@
+use std::collections::HashMap;
+
+use crate::cnf::CNF;
+use crate::proof::{Proof, ProofStep};
+
+// Reference to a clause, its offset in the clause arena
+pub type ClauseRef = usize;
+
+// Every clause stored back to back in one buffer
+// Each clause is a header holding its length and its id, followed by its literals
+#[derive(Clone, Debug, Default)]
+pub struct ClauseArena {
+    data: Vec<isize>,
+}
+
+const HEADER_LEN: usize = 2;
+
+impl ClauseArena {
+    pub fn alloc(&mut self, clause: &[isize], id: usize) -> ClauseRef {
+        let cref = self.data.len();
+        self.data.push(clause.len() as isize);
+        self.data.push(id as isize);
+        self.data.extend_from_slice(clause);
+        cref
+    }
+
+    pub fn len(&self, cref: ClauseRef) -> usize {
+        self.data[cref] as usize
+    }
+
+    // Id of the clause as used in proofs, input clauses are numbered from 1 in the order they were added
+    pub fn id(&self, cref: ClauseRef) -> usize {
+        self.data[cref + 1] as usize
+    }
+
+    pub fn lits(&self, cref: ClauseRef) -> &[isize] {
+        &self.data[cref + HEADER_LEN..cref + HEADER_LEN + self.len(cref)]
+    }
+
+    pub fn lits_mut(&mut self, cref: ClauseRef) -> &mut [isize] {
+        let len = self.len(cref);
+        &mut self.data[cref + HEADER_LEN..cref + HEADER_LEN + len]
+    }
+}
+
+// Variables not known to be assigned, in a binary max-heap ordered by activity
+// Assigned variables are only removed lazily when they reach the top
+#[derive(Clone, Debug, Default)]
+struct VarHeap {
+    heap: Vec<usize>,
+    // Index of each variable in heap, None if it isn't in it
+    position: Vec<Option<usize>>,
+}
+
+impl VarHeap {
+    fn contains(&self, idx: usize) -> bool {
+        self.position.get(idx).is_some_and(|position| position.is_some())
+    }
+
+    fn insert(&mut self, idx: usize, activity: &[f64]) {
+        if self.position.len() <= idx {
+            self.position.resize(idx + 1, None);
+        }
+        if self.contains(idx) {
+            return;
+        }
+        self.position[idx] = Some(self.heap.len());
+        self.heap.push(idx);
+        self.sift_up(self.heap.len() - 1, activity);
+    }
+
+    fn pop(&mut self, activity: &[f64]) -> Option<usize> {
+        let top = *self.heap.first()?;
+        let last = self.heap.pop().unwrap();
+        self.position[top] = None;
+        if !self.heap.is_empty() {
+            self.heap[0] = last;
+            self.position[last] = Some(0);
+            self.sift_down(0, activity);
+        }
+        Some(top)
+    }
+
+    // Restores the order after the variable's activity went up
+    fn increased(&mut self, idx: usize, activity: &[f64]) {
+        if let Some(Some(position)) = self.position.get(idx) {
+            self.sift_up(*position, activity);
+        }
+    }
+
+    fn sift_up(&mut self, mut position: usize, activity: &[f64]) {
+        let idx = self.heap[position];
+        while position > 0 {
+            let parent = (position - 1) / 2;
+            if activity[self.heap[parent]] >= activity[idx] {
+                break;
+            }
+            self.heap[position] = self.heap[parent];
+            self.position[self.heap[position]] = Some(position);
+            position = parent;
+        }
+        self.heap[position] = idx;
+        self.position[idx] = Some(position);
+    }
+
+    fn sift_down(&mut self, mut position: usize, activity: &[f64]) {
+        let idx = self.heap[position];
+        loop {
+            let left = 2 * position + 1;
+            if left >= self.heap.len() {
+                break;
+            }
+            let right = left + 1;
+            let child = if right < self.heap.len() && activity[self.heap[right]] > activity[self.heap[left]] { right } else { left };
+            if activity[self.heap[child]] <= activity[idx] {
+                break;
+            }
+            self.heap[position] = self.heap[child];
+            self.position[self.heap[position]] = Some(position);
+            position = child;
+        }
+        self.heap[position] = idx;
+        self.position[idx] = Some(position);
+    }
+}
+
+// Clause watching a literal, along with another of its literals
+// If the blocker is true the clause is satisfied, and is skipped without reading it from the arena
+#[derive(Clone, Copy, Debug)]
+struct Watch {
+    cref: ClauseRef,
+    blocker: isize,
+}
+
+// Learned clause that can be deleted, with the number of distinct decision levels of its literals when
+// it was learned, clauses over few levels are the most useful to keep
+#[derive(Clone, Copy, Debug)]
+struct Learnt {
+    cref: ClauseRef,
+    lbd: usize,
+}
+
+// Exponential moving average with the bias correction of Adam, as in CaDiCaL
+// Without it the average starts near zero and takes many more values than its window to catch up
+#[derive(Clone, Copy, Debug)]
+struct Ema {
+    biased: f64,
+    alpha: f64,
+
+    // Weight the zero the average started from still has, (1 - alpha)^n after n values
+    start_weight: f64,
+}
+
+impl Ema {
+    fn new(window: f64) -> Ema {
+        Ema { biased: 0.0, alpha: 1.0 / window, start_weight: 1.0 }
+    }
+
+    fn update(&mut self, value: f64) {
+        self.biased += (value - self.biased) * self.alpha;
+        self.start_weight *= 1.0 - self.alpha;
+    }
+
+    fn value(&self) -> f64 {
+        if self.start_weight == 1.0 { 0.0 } else { self.biased / (1.0 - self.start_weight) }
+    }
+}
+
+// Conflict-driven clause learning SAT solver
+// Literals use the same convention as CNF: variable ids start at 1 and -id represents negation
+// The solver is incremental: clauses can be appended between calls to solve, and learned
+// clauses are kept since they follow from the clauses alone, not from any assumptions
+#[derive(Clone, Debug)]
+pub struct Solver {
+    arena: ClauseArena,
+
+    // Input clauses and learned units, which are never deleted
+    clauses: Vec<ClauseRef>,
+
+    // Learned clauses of at least two literals, half of which are deleted every so often
+    learnts: Vec<Learnt>,
+
+    // watches[lit_index(lit)] lists the clauses whose first or second literal is lit
+    // The first two literals of a clause are watched, and a clause only needs to be
+    // looked at when one of them becomes false
+    watches: Vec<Vec<Watch>>,
+
+    // assignment[id - 1] is the current truth value of variable with id
+    assignment: Vec<Option<bool>>,
+
+    // Decision level at which each variable was assigned
+    level: Vec<usize>,
+
+    // Clause that implied each variable, None for decisions
+    reason: Vec<Option<ClauseRef>>,
+
+    // Assigned literals in the order they were assigned
+    trail: Vec<isize>,
+
+    // trail_lim[d] is the length of the trail before decision level d + 1 was entered
+    trail_lim: Vec<usize>,
+
+    // Index of the next trail literal whose consequences haven't been propagated
+    queue_head: usize,
+
+    // Appended clauses that were already unit when added, their first literal is assigned on the next solve
+    pending_units: Vec<ClauseRef>,
+
+    // Variable activity for the decision heuristic (VSIDS)
+    activity: Vec<f64>,
+    activity_inc: f64,
+    heap: VarHeap,
+
+    // Moving averages of the LBD of recent and of all learned clauses, restarts come when recent clauses
+    // get clearly worse than usual, as in Glucose
+    conflicts_since_restart: usize,
+    lbd_fast: Ema,
+    lbd_slow: Ema,
+
+    // Conflicts until the next deletion of learned clauses, which comes less often each time
+    conflicts_until_reduce: usize,
+    reductions: usize,
+
+    // Last value each variable was assigned, reused when deciding on it again
+    saved_phase: Vec<bool>,
+
+    // Set once an empty clause is found, the formula can never become SAT again
+    unsat: bool,
+
+    // Assumptions that together made the last solve UNSAT, empty if the clauses are UNSAT on their own
+    failed: Vec<isize>,
+
+    // Hints for deriving the empty clause, held until solve so input clause ids stay contiguous
+    refutation_hints: Option<Vec<usize>>,
+
+    // Id given to the next input or learned clause
+    next_id: usize,
+
+    // Id of a unit clause asserting each variable assigned at level 0, used as proof hints
+    unit_id: Vec<Option<usize>>,
+
+    // Steps of the refutation, only recorded once enabled
+    proof: Option<Proof>,
+}
+
+// Result of analyzing a conflict: the learned clause (asserting literal first),
+// the level to backjump to, and the ids of the clauses it was derived from
+struct Analysis {
+    learned: Vec<isize>,
+    backjump_level: usize,
+    hints: Vec<usize>,
+}
+
+const ACTIVITY_DECAY: f64 = 0.95;
+const ACTIVITY_LIMIT: f64 = 1e100;
+
+// Number of conflicts the recent and overall LBD averages roughly cover
+const LBD_FAST_WINDOW: f64 = 32.0;
+const LBD_SLOW_WINDOW: f64 = 4096.0;
+
+// Restart once the recent average is this much above the overall one, but not within this many conflicts
+const RESTART_MARGIN: f64 = 1.25;
+const RESTART_MIN_CONFLICTS: usize = 50;
+
+// Conflicts before the first reduction, each later one waits REDUCE_INC more conflicts than the last
+const REDUCE_FIRST: usize = 2000;
+const REDUCE_INC: usize = 300;
+
+// Learned clauses over at most this many levels are never deleted
+const GLUE_LBD: usize = 2;
+
+impl Solver {
+    pub fn new(num_vars: usize) -> Solver {
+        let mut solver = Solver {
+            arena: ClauseArena::default(),
+            clauses: Vec::new(),
+            learnts: Vec::new(),
+            watches: vec![Vec::new(); 2 * num_vars],
+            assignment: vec![None; num_vars],
+            level: vec![0; num_vars],
+            reason: vec![None; num_vars],
+            trail: Vec::new(),
+            trail_lim: Vec::new(),
+            queue_head: 0,
+            pending_units: Vec::new(),
+            activity: vec![0.0; num_vars],
+            activity_inc: 1.0,
+            heap: VarHeap::default(),
+            conflicts_since_restart: 0,
+            lbd_fast: Ema::new(LBD_FAST_WINDOW),
+            lbd_slow: Ema::new(LBD_SLOW_WINDOW),
+            conflicts_until_reduce: REDUCE_FIRST,
+            reductions: 0,
+            saved_phase: vec![true; num_vars],
+            unsat: false,
+            failed: Vec::new(),
+            refutation_hints: None,
+            next_id: 1,
+            unit_id: vec![None; num_vars],
+            proof: None,
+        };
+        for idx in 0..num_vars {
+            solver.heap.insert(idx, &solver.activity);
+        }
+        solver
+    }
+
+    pub fn from_cnf(cnf: &CNF) -> Solver {
+        let mut solver = Solver::new(cnf.num_vars());
+        for clause in cnf.get_clauses() {
+            solver.append_clause(clause.clone());
+        }
+        solver
+    }
+
+    pub fn num_vars(&self) -> usize {
+        self.assignment.len()
+    }
+
+    // Grows the solver so variables with ids up to num_vars exist
+    pub fn reserve_vars(&mut self, num_vars: usize) {
+        if num_vars <= self.num_vars() {
+            return;
+        }
+
+        self.watches.resize(2 * num_vars, Vec::new());
+        self.assignment.resize(num_vars, None);
+        self.level.resize(num_vars, 0);
+        self.reason.resize(num_vars, None);
+        self.activity.resize(num_vars, 0.0);
+        self.saved_phase.resize(num_vars, true);
+        self.unit_id.resize(num_vars, None);
+        for idx in self.heap.position.len()..num_vars {
+            self.heap.insert(idx, &self.activity);
+        }
+    }
+
+    // Start recording a refutation, must be called before any clause is added
+    // Input clause ids are only contiguous if every clause is appended before the first solve
+    pub fn enable_proof(&mut self) {
+        assert_eq!(self.next_id, 1, "proofs must be enabled before adding clauses");
+        self.proof = Some(Proof::default());
+    }
+
+    // Steps recorded since enable_proof, once solve returns None they derive the empty clause
+    pub fn proof(&self) -> Option<&Proof> {
+        self.proof.as_ref()
+    }
+
+    // Adds a clause, creating any variables it mentions that don't exist yet
+    pub fn append_clause(&mut self, mut clause: Vec<isize>) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        clause.sort_unstable();
+        clause.dedup();
+
+        // Clauses containing both x and -x are always satisfied
+        if clause.iter().any(|lit| clause.contains(&-lit)) {
+            return;
+        }
+
+        self.reserve_vars(clause.iter().map(|lit| lit.unsigned_abs()).max().unwrap_or(0));
+
+        // Only level 0 assignments exist between solves, so literals that are already false stay false
+        // Move them to the back so the watched literals are ones that can still change
+        clause.sort_by_key(|lit| self.value(*lit) == Some(false));
+
+        if clause.is_empty() {
+            self.refute(vec![id]);
+            return;
+        }
+
+        let cref = if clause.len() == 1 {
+            self.arena.alloc(&clause, id)
+        } else {
+            self.attach(&clause, id)
+        };
+        self.clauses.push(cref);
+
+        match self.value(clause[0]) {
+            Some(false) => {
+                let mut hints = self.unit_hints(cref, None);
+                hints.push(id);
+                self.refute(hints);
+            }
+            Some(true) => {}
+            None if clause.len() == 1 => self.assign(clause[0], Some(cref)),
+            None if self.value(clause[1]) == Some(false) => self.pending_units.push(cref),
+            None => {}
+        }
+    }
+
+    // Runs the CDCL loop
+    // Returns the satisfying truth assignment, where assignment[i] is the value of variable i + 1,
+    // or None if the clauses are UNSAT
+    pub fn solve(&mut self) -> Option<Vec<bool>> {
+        self.solve_with_assumptions(&[])
+    }
+
+    // Solves with the given literals temporarily forced true, without changing the clauses
+    // Returns None if the clauses are UNSAT under the assumptions
+    pub fn solve_with_assumptions(&mut self, assumptions: &[isize]) -> Option<Vec<bool>> {
+        self.reserve_vars(assumptions.iter().map(|lit| lit.unsigned_abs()).max().unwrap_or(0));
+        self.failed.clear();
+
+        for cref in std::mem::take(&mut self.pending_units) {
+            let lit = self.arena.lits(cref)[0];
+            match self.value(lit) {
+                None => self.assign(lit, Some(cref)),
+                Some(false) => {
+                    let mut hints = self.unit_hints(cref, None);
+                    hints.push(self.arena.id(cref));
+                    self.refute(hints);
+                }
+                Some(true) => {}
+            }
+        }
+
+        if self.unsat {
+            self.derive_empty_clause();
+            return None;
+        }
+
+        loop {
+            if let Some(conflict) = self.propagate() {
+                if self.decision_level() == 0 {
+                    let mut hints = self.unit_hints(conflict, None);
+                    hints.push(self.arena.id(conflict));
+                    self.refute(hints);
+                    self.derive_empty_clause();
+                    return None;
+                }
+
+                let Analysis { learned, backjump_level, hints } = self.analyze(conflict);
+                let lbd = self.lbd(&learned);
+                self.backjump(backjump_level);
+                self.conflicts_since_restart += 1;
+                self.lbd_fast.update(lbd as f64);
+                self.lbd_slow.update(lbd as f64);
+                self.conflicts_until_reduce = self.conflicts_until_reduce.saturating_sub(1);
+
+                let id = self.next_id;
+                self.next_id += 1;
+                if let Some(proof) = self.proof.as_mut() {
+                    proof.push(ProofStep::Add { id, clause: learned.clone(), hints });
+                }
+
+                let asserting = learned[0];
+                let cref = if learned.len() == 1 {
+                    let cref = self.arena.alloc(&learned, id);
+                    self.clauses.push(cref);
+                    cref
+                } else {
+                    let cref = self.attach(&learned, id);
+                    self.learnts.push(Learnt { cref, lbd });
+                    cref
+                };
+                self.assign(asserting, Some(cref));
+                self.decay_activity();
+            } else {
+                // Restarting keeps the learned clauses and activities, so the search resumes
+                // from a better starting point
+                if self.conflicts_since_restart >= RESTART_MIN_CONFLICTS && self.lbd_fast.value() > RESTART_MARGIN * self.lbd_slow.value() {
+                    self.conflicts_since_restart = 0;
+                    self.backjump(0);
+                }
+                if self.conflicts_until_reduce == 0 {
+                    self.reductions += 1;
+                    self.conflicts_until_reduce = REDUCE_FIRST + REDUCE_INC * self.reductions;
+                    self.reduce_learnts();
+                }
+
+                // Each assumption gets its own decision level before any free decision is made
+                let lit = if self.decision_level() < assumptions.len() {
+                    let assumption = assumptions[self.decision_level()];
+                    match self.value(assumption) {
+                        Some(true) => {
+                            self.trail_lim.push(self.trail.len());
+                            continue;
+                        }
+                        Some(false) => {
+                            self.analyze_final(assumption);
+                            self.backjump(0);
+                            return None;
+                        }
+                        None => assumption,
+                    }
+                } else {
+                    match self.pick_branch_literal() {
+                        Some(lit) => lit,
+                        None => {
+                            let model = self.assignment.iter().map(|value| value.unwrap()).collect();
+                            self.backjump(0);
+                            return Some(model);
+                        }
+                    }
+                };
+
+                self.trail_lim.push(self.trail.len());
+                self.assign(lit, None);
+            }
+        }
+    }
+
+    // Subset of the assumptions of the last solve that was enough to make it UNSAT
+    pub fn failed_assumptions(&self) -> &[isize] {
+        &self.failed
+    }
+
+    // Finds a subset of the assumptions under which the clauses are UNSAT, or None if they are SAT
+    // The core is minimal: dropping any one of its literals makes the clauses SAT again
+    pub fn unsat_core(&mut self, assumptions: &[isize]) -> Option<Vec<isize>> {
+        if self.solve_with_assumptions(assumptions).is_some() {
+            return None;
+        }
+
+        // Every literal before i is known to be needed, since dropping it from a larger set was SAT
+        let mut core = self.failed.clone();
+        let mut i = 0;
+        while i < core.len() {
+            let mut without = core.clone();
+            without.remove(i);
+
+            if self.solve_with_assumptions(&without).is_none() {
+                core = without.into_iter().filter(|lit| self.failed.contains(lit)).collect();
+            } else {
+                i += 1;
+            }
+        }
+
+        Some(core)
+    }
+
+    // Stores a clause of at least two literals and watches its first two
+    fn attach(&mut self, clause: &[isize], id: usize) -> ClauseRef {
+        let cref = self.arena.alloc(clause, id);
+        self.watch(cref);
+        cref
+    }
+
+    // Watches the first two literals of a stored clause, each blocked by the other
+    fn watch(&mut self, cref: ClauseRef) {
+        let lits = self.arena.lits(cref);
+        let (first, second) = (lits[0], lits[1]);
+        self.watches[Self::lit_index(first)].push(Watch { cref, blocker: second });
+        self.watches[Self::lit_index(second)].push(Watch { cref, blocker: first });
+    }
+
+    fn decision_level(&self) -> usize {
+        self.trail_lim.len()
+    }
+
+    fn var_index(lit: isize) -> usize {
+        lit.unsigned_abs() - 1
+    }
+
+    // Index of a literal in the watch lists, x and -x are stored next to each other
+    fn lit_index(lit: isize) -> usize {
+        2 * Self::var_index(lit) + (lit < 0) as usize
+    }
+
+    fn value(&self, lit: isize) -> Option<bool> {
+        self.assignment[Self::var_index(lit)].map(|value| value == (lit > 0))
+    }
+
+    fn assign(&mut self, lit: isize, reason: Option<ClauseRef>) {
+        let idx = Self::var_index(lit);
+        self.assignment[idx] = Some(lit > 0);
+        self.level[idx] = self.decision_level();
+        self.reason[idx] = reason;
+        self.trail.push(lit);
+
+        if self.decision_level() == 0 && self.proof.is_some() {
+            self.derive_unit(lit, reason.unwrap());
+        }
+    }
+
+    // Records a unit clause for a literal implied at level 0 so later steps can cite it
+    fn derive_unit(&mut self, lit: isize, reason: ClauseRef) {
+        let idx = Self::var_index(lit);
+        if self.arena.len(reason) == 1 {
+            self.unit_id[idx] = Some(self.arena.id(reason));
+            return;
+        }
+
+        let mut hints = self.unit_hints(reason, Some(lit));
+        hints.push(self.arena.id(reason));
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.unit_id[idx] = Some(id);
+        if let Some(proof) = self.proof.as_mut() {
+            proof.push(ProofStep::Add { id, clause: vec![lit], hints });
+        }
+    }
+
+    // Ids of the unit clauses falsifying every level 0 literal of a clause except skip
+    fn unit_hints(&self, cref: ClauseRef, skip: Option<isize>) -> Vec<usize> {
+        self.arena.lits(cref).iter()
+            .filter(|&&lit| Some(lit) != skip && self.level[Self::var_index(lit)] == 0)
+            .filter_map(|&lit| self.unit_id[Self::var_index(lit)])
+            .collect()
+    }
+
+    // Marks the clauses as UNSAT, the first reason found is the one the proof uses
+    fn refute(&mut self, hints: Vec<usize>) {
+        if !self.unsat {
+            self.unsat = true;
+            self.refutation_hints = Some(hints);
+        }
+    }
+
+    fn derive_empty_clause(&mut self) {
+        if let Some(hints) = self.refutation_hints.take() {
+            let id = self.next_id;
+            self.next_id += 1;
+            if let Some(proof) = self.proof.as_mut() {
+                proof.push(ProofStep::Add { id, clause: Vec::new(), hints });
+            }
+        }
+    }
+
+    // Undo every assignment made above the given decision level
+    fn backjump(&mut self, level: usize) {
+        if self.decision_level() <= level {
+            return;
+        }
+
+        let trail_len = self.trail_lim[level];
+        for lit in self.trail.drain(trail_len..) {
+            let idx = Self::var_index(lit);
+            self.saved_phase[idx] = lit > 0;
+            self.assignment[idx] = None;
+            self.reason[idx] = None;
+            self.heap.insert(idx, &self.activity);
+        }
+        self.trail_lim.truncate(level);
+        self.queue_head = trail_len;
+    }
+
+    // Assigns every implied literal until a fixpoint is reached
+    // Only the clauses watching a literal that just became false are visited
+    // Returns a falsified clause if there is a conflict
+    fn propagate(&mut self) -> Option<ClauseRef> {
+        while self.queue_head < self.trail.len() {
+            let false_lit = -self.trail[self.queue_head];
+            self.queue_head += 1;
+
+            let mut watchers = std::mem::take(&mut self.watches[Self::lit_index(false_lit)]);
+            let mut kept = 0;
+            let mut conflict = None;
+
+            let mut i = 0;
+            while i < watchers.len() {
+                let watch = watchers[i];
+                i += 1;
+
+                if self.value(watch.blocker) == Some(true) {
+                    watchers[kept] = watch;
+                    kept += 1;
+                    continue;
+                }
+
+                // Keep the falsified watch in the second position
+                let cref = watch.cref;
+                let lits = self.arena.lits_mut(cref);
+                if lits[0] == false_lit {
+                    lits.swap(0, 1);
+                }
+                let first = lits[0];
+
+                // The other watched literal makes a better blocker when it is true
+                if first != watch.blocker && self.value(first) == Some(true) {
+                    watchers[kept] = Watch { cref, blocker: first };
+                    kept += 1;
+                    continue;
+                }
+
+                // Look for a literal that isn't false to watch instead
+                let replacement = (2..self.arena.len(cref))
+                    .find(|&k| self.value(self.arena.lits(cref)[k]) != Some(false));
+
+                if let Some(k) = replacement {
+                    let lits = self.arena.lits_mut(cref);
+                    lits.swap(1, k);
+                    let new_watch = lits[1];
+                    self.watches[Self::lit_index(new_watch)].push(Watch { cref, blocker: first });
+                    continue;
+                }
+
+                watchers[kept] = Watch { cref, blocker: first };
+                kept += 1;
+
+                if self.value(first) == Some(false) {
+                    conflict = Some(cref);
+                    break;
+                }
+                self.assign(first, Some(cref));
+            }
+
+            // On conflict the unvisited watchers are kept as they are
+            while i < watchers.len() {
+                watchers[kept] = watchers[i];
+                kept += 1;
+                i += 1;
+            }
+            watchers.truncate(kept);
+            self.watches[Self::lit_index(false_lit)] = watchers;
+
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+        None
+    }
+
+    // Derives a learned clause from the conflict using the first unique implication point
+    fn analyze(&mut self, conflict: ClauseRef) -> Analysis {
+        let mut seen = vec![false; self.num_vars()];
+        let mut learned = vec![0];
+        let mut unit_hints = Vec::new();     // Units removing level 0 literals
+        let mut chain = Vec::new();          // Clauses resolved, starting from the conflict
+        let mut pending = 0;                // Literals of the current level still to be resolved
+        let mut trail_idx = self.trail.len();
+        let mut cref = conflict;
+        let mut resolved_lit = None;
+
+        loop {
+            chain.push(self.arena.id(cref));
+
+            for k in 0..self.arena.len(cref) {
+                let lit = self.arena.lits(cref)[k];
+                if Some(lit) == resolved_lit {
+                    continue;
+                }
+
+                let idx = Self::var_index(lit);
+                if seen[idx] {
+                    continue;
+                }
+
+                if self.level[idx] == 0 {
+                    seen[idx] = true;
+                    if let Some(id) = self.unit_id[idx] {
+                        unit_hints.push(id);
+                    }
+                    continue;
+                }
+
+                seen[idx] = true;
+                self.bump_activity(idx);
+
+                if self.level[idx] == self.decision_level() {
+                    pending += 1;
+                } else {
+                    learned.push(lit);
+                }
+            }
+
+            // Walk back to the most recent literal involved in the conflict
+            let lit = loop {
+                trail_idx -= 1;
+                let lit = self.trail[trail_idx];
+                if seen[Self::var_index(lit)] {
+                    break lit;
+                }
+            };
+
+            pending -= 1;
+            if pending == 0 {
+                learned[0] = -lit;
+                break;
+            }
+
+            resolved_lit = Some(lit);
+            cref = self.reason[Self::var_index(lit)].unwrap();
+        }
+
+        // Drop the literals whose negation follows from the rest of the clause by their reasons
+        let mut in_clause = vec![false; self.num_vars()];
+        for lit in learned.iter() {
+            in_clause[Self::var_index(*lit)] = true;
+        }
+        let mut implied = vec![None; self.num_vars()];
+        let mut k = 1;
+        while k < learned.len() {
+            if self.is_implied(Self::var_index(learned[k]), &in_clause, &mut implied) {
+                learned.swap_remove(k);
+            } else {
+                k += 1;
+            }
+        }
+
+        // The reasons of the dropped literals, and of the literals they depend on, come first in the proof
+        // Replayed in trail order each one is unit, its other literals being in the clause or implied before it
+        let mut implied_hints = Vec::new();
+        for lit in self.trail.iter() {
+            let idx = Self::var_index(*lit);
+            if implied[idx] != Some(true) {
+                continue;
+            }
+            let reason = self.reason[idx].unwrap();
+            for other in self.arena.lits(reason) {
+                let other = Self::var_index(*other);
+                if self.level[other] == 0 && !seen[other] {
+                    seen[other] = true;
+                    unit_hints.extend(self.unit_id[other]);
+                }
+            }
+            implied_hints.push(self.arena.id(reason));
+        }
+
+        // Backjump to the second highest level in the clause, keeping that literal second
+        // so the learned clause watches the asserting literal and the last one to be unassigned
+        let mut backjump_level = 0;
+        for i in 1..learned.len() {
+            let level = self.level[Self::var_index(learned[i])];
+            if level > backjump_level {
+                backjump_level = level;
+                learned.swap(1, i);
+            }
+        }
+
+        // Replaying the resolved clauses in trail order derives a conflict from the negated clause
+        let mut hints = unit_hints;
+        hints.extend(implied_hints);
+        hints.extend(chain.into_iter().rev());
+
+        Analysis { learned, backjump_level, hints }
+    }
+
+    // Whether the variable's value follows by its reason from the variables in the clause and at level 0,
+    // either directly or through other variables for which this holds, results are memoized in implied
+    fn is_implied(&self, root: usize, in_clause: &[bool], implied: &mut [Option<bool>]) -> bool {
+        // Each variable being checked with the position of the next literal of its reason to look at
+        let mut stack = vec![(root, 0)];
+        while let Some((idx, k)) = stack.last_mut() {
+            let idx = *idx;
+            let Some(cref) = self.reason[idx] else {
+                break;
+            };
+            let lits = self.arena.lits(cref);
+            if *k == lits.len() {
+                implied[idx] = Some(true);
+                stack.pop();
+                continue;
+            }
+
+            let other = Self::var_index(lits[*k]);
+            *k += 1;
+            if other == idx || in_clause[other] || self.level[other] == 0 {
+                continue;
+            }
+            match implied[other] {
+                Some(true) => {}
+                Some(false) => break,
+                None => stack.push((other, 0)),
+            }
+        }
+
+        // A variable that isn't implied makes every one depending on it not implied either
+        for (idx, _) in stack {
+            implied[idx] = Some(false);
+        }
+        implied[root] == Some(true)
+    }
+
+    // Collects the assumptions that imply the negation of the failed assumption
+    // While assumptions are being decided every decision is an assumption, so they are
+    // the trail literals without a reason that the failed literal depends on
+    fn analyze_final(&mut self, failed: isize) {
+        self.failed = vec![failed];
+        if self.decision_level() == 0 {
+            return;
+        }
+
+        let mut seen = vec![false; self.num_vars()];
+        seen[Self::var_index(failed)] = true;
+
+        for i in (self.trail_lim[0]..self.trail.len()).rev() {
+            let lit = self.trail[i];
+            let idx = Self::var_index(lit);
+            if !seen[idx] {
+                continue;
+            }
+
+            match self.reason[idx] {
+                None => self.failed.push(lit),
+                Some(cref) => {
+                    for &other in self.arena.lits(cref) {
+                        if self.level[Self::var_index(other)] > 0 {
+                            seen[Self::var_index(other)] = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn bump_activity(&mut self, idx: usize) {
+        self.activity[idx] += self.activity_inc;
+        if self.activity[idx] > ACTIVITY_LIMIT {
+            // Scaling every activity by the same amount keeps the heap ordered
+            for activity in self.activity.iter_mut() {
+                *activity /= ACTIVITY_LIMIT;
+            }
+            self.activity_inc /= ACTIVITY_LIMIT;
+        }
+        self.heap.increased(idx, &self.activity);
+    }
+
+    fn decay_activity(&mut self) {
+        self.activity_inc /= ACTIVITY_DECAY;
+    }
+
+    // Picks the unassigned variable with the highest activity, using its saved phase
+    fn pick_branch_literal(&mut self) -> Option<isize> {
+        while let Some(idx) = self.heap.pop(&self.activity) {
+            if self.assignment[idx].is_none() {
+                let lit = (idx + 1) as isize;
+                return Some(if self.saved_phase[idx] { lit } else { -lit });
+            }
+        }
+        None
+    }
+
+    // Number of distinct decision levels among the literals of a clause
+    fn lbd(&self, clause: &[isize]) -> usize {
+        let mut levels = clause.iter().map(|lit| self.level[Self::var_index(*lit)]).collect::<Vec<_>>();
+        levels.sort_unstable();
+        levels.dedup();
+        levels.len()
+    }
+
+    // A clause can't be deleted while it is the reason for one of its literals
+    fn is_locked(&self, cref: ClauseRef) -> bool {
+        let idx = Self::var_index(self.arena.lits(cref)[0]);
+        self.assignment[idx].is_some() && self.reason[idx] == Some(cref)
+    }
+
+    // Deletes the half of the learned clauses over the most decision levels, except locked and glue clauses,
+    // then moves the remaining clauses into a new arena so the space of the deleted ones is reclaimed
+    fn reduce_learnts(&mut self) {
+        let mut learnts = std::mem::take(&mut self.learnts);
+        learnts.sort_by_key(|learnt| learnt.lbd);
+        let half = learnts.len() / 2;
+        let (kept, deleted): (Vec<_>, Vec<_>) = learnts.into_iter()
+            .enumerate()
+            .partition(|(rank, learnt)| *rank < half || learnt.lbd <= GLUE_LBD || self.is_locked(learnt.cref));
+
+        if let Some(proof) = self.proof.as_mut() {
+            for (_, learnt) in deleted.iter() {
+                proof.push(ProofStep::Delete { id: self.arena.id(learnt.cref), clause: self.arena.lits(learnt.cref).to_vec() });
+            }
+        }
+
+        // Copying keeps each clause's literals in place, so its first two are still the watched ones
+        let mut arena = ClauseArena::default();
+        let mut moved = HashMap::new();
+        let mut relocate = |cref: ClauseRef| {
+            let new = arena.alloc(self.arena.lits(cref), self.arena.id(cref));
+            moved.insert(cref, new);
+            new
+        };
+        self.clauses = self.clauses.iter().map(|cref| relocate(*cref)).collect();
+        self.learnts = kept.into_iter().map(|(_, learnt)| Learnt { cref: relocate(learnt.cref), lbd: learnt.lbd }).collect();
+        self.arena = arena;
+
+        for watchers in self.watches.iter_mut() {
+            watchers.clear();
+        }
+        let crefs = self.clauses.iter().chain(self.learnts.iter().map(|learnt| &learnt.cref)).copied().collect::<Vec<_>>();
+        for cref in crefs {
+            if self.arena.len(cref) > 1 {
+                self.watch(cref);
+            }
+        }
+        for reason in self.reason.iter_mut() {
+            *reason = reason.map(|cref| moved[&cref]);
+        }
+        for cref in self.pending_units.iter_mut() {
+            *cref = moved[cref];
+        }
+    }
+}
@@ -227,3 +227,1376 @@ fn implied_assignment() {
     assert_eq!(CNF::implied_assignment(&vec![-1, 2, 3], &vec![None, Some(true), Some(false)]), None);
     assert_eq!(CNF::implied_assignment(&vec![1], &vec![None, Some(true), Some(false)]), Some((0, true)));
 }
+
+// Clauses stating that n + 1 pigeons fit in n holes, which is UNSAT
+fn pigeonhole(holes: usize) -> CNF {
+    let var = |pigeon: usize, hole: usize| (pigeon * holes + hole + 1) as isize;
+    let mut clauses = Vec::new();
+
+    for pigeon in 0..=holes {
+        clauses.push((0..holes).map(|hole| var(pigeon, hole)).collect());
+    }
+    for hole in 0..holes {
+        for p1 in 0..=holes {
+            for p2 in p1 + 1..=holes {
+                clauses.push(vec![-var(p1, hole), -var(p2, hole)]);
+            }
+        }
+    }
+
+    let symbol_table = (0..(holes + 1) * holes).map(|i| format!("p{i}")).collect();
+    CNF::new(symbol_table, clauses)
+}
+
+// Deterministic pseudo-random 3-SAT instance
+fn random_3sat(num_vars: usize, num_clauses: usize, seed: u64) -> CNF {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 33) as usize
+    };
+
+    let clauses = (0..num_clauses)
+        .map(|_| (0..3).map(|_| {
+            let id = (next() % num_vars + 1) as isize;
+            if next() % 2 == 0 { id } else { -id }
+        }).collect())
+        .collect();
+
+    let symbol_table = (0..num_vars).map(|i| format!("x{i}")).collect();
+    CNF::new(symbol_table, clauses)
+}
+
+fn satisfies(clauses: &[Vec<isize>], model: &[bool]) -> bool {
+    clauses.iter().all(|clause| clause.iter().any(|lit| model[lit.unsigned_abs() - 1] == (*lit > 0)))
+}
+
+#[test]
+fn cdcl() {
+    assert!(pigeonhole(4).find_evidence().is_none());
+    assert!(pigeonhole(5).find_evidence().is_none());
+
+    for seed in 0..5 {
+        let cnf = random_3sat(200, 600, seed);
+        let model = cnf.find_evidence().unwrap();
+        assert!(satisfies(cnf.get_clauses(), &model));
+    }
+
+    // Near the phase transition, compare against brute force
+    for seed in 0..20 {
+        let cnf = random_3sat(10, 45, seed);
+        let brute_force = (0..1u32 << 10).any(|bits| {
+            let model = (0..10).map(|i| bits >> i & 1 == 1).collect::<Vec<_>>();
+            satisfies(cnf.get_clauses(), &model)
+        });
+        assert_eq!(cnf.find_evidence().is_some(), brute_force);
+    }
+}
+
+#[test]
+fn clause_arena() {
+    use crate::solver::ClauseArena;
+
+    let mut arena = ClauseArena::default();
+    let a = arena.alloc(&[1, -2, 3], 1);
+    let b = arena.alloc(&[-4], 2);
+    let c = arena.alloc(&[2, 5], 7);
+
+    assert_eq!(arena.lits(a), &[1, -2, 3]);
+    assert_eq!(arena.lits(b), &[-4]);
+    assert_eq!(arena.len(c), 2);
+    assert_eq!(arena.id(b), 2);
+    assert_eq!(arena.id(c), 7);
+
+    arena.lits_mut(a).swap(0, 2);
+    assert_eq!(arena.lits(a), &[3, -2, 1]);
+    assert_eq!(arena.lits(c), &[2, 5]);
+}
+
+#[test]
+fn lrat_proofs() {
+    use crate::proof::{check_lrat, ProofFormat, ProofStep};
+
+    for holes in 2..6 {
+        let cnf = pigeonhole(holes);
+        let proof = cnf.find_refutation().unwrap();
+        assert!(proof.is_refutation());
+        assert_eq!(cnf.check_lrat(&proof.to_text(ProofFormat::Lrat)), Ok(()));
+    }
+
+    for seed in 0..20 {
+        let cnf = random_3sat(10, 60, seed);
+        match cnf.find_refutation() {
+            Some(proof) => assert!(cnf.check_lrat(&proof.to_text(ProofFormat::Lrat)).is_ok()),
+            None => assert!(cnf.find_evidence().is_some()),
+        }
+    }
+
+    // Long enough searches delete learned clauses, which the proof records in both formats
+    let cnf = pigeonhole(7);
+    let proof = cnf.find_refutation().unwrap();
+    assert!(proof.steps().iter().any(|step| matches!(step, ProofStep::Delete { .. })));
+    assert!(proof.to_text(ProofFormat::Drat).lines().any(|line| line.starts_with("d ")));
+    assert_eq!(cnf.check_lrat(&proof.to_text(ProofFormat::Lrat)), Ok(()));
+
+    // Conflicting unit clauses and empty clauses are refuted before any search
+    let cnf = CNF::new(vec![String::from("x")], vec![vec![1], vec![-1], vec![1]]);
+    assert_eq!(cnf.find_refutation().unwrap().to_text(ProofFormat::Lrat), "4 0 1 2 0\n");
+    let cnf = CNF::new(vec![String::from("x")], vec![vec![1], vec![]]);
+    assert_eq!(cnf.find_refutation().unwrap().to_text(ProofFormat::Drat), "0\n");
+
+    let expr = Expr::parse("(a -> b) and a and not b".as_bytes()).unwrap();
+    let cnf = expr.tseitin(false);
+    let proof = cnf.find_refutation().unwrap().to_text(ProofFormat::Lrat);
+    assert!(cnf.check_lrat(&proof).is_ok());
+    assert!(Expr::parse("a or b".as_bytes()).unwrap().tseitin(false).find_refutation().is_none());
+
+    // Proofs with missing or wrong hints are rejected
+    let clauses = vec![vec![1, 2], vec![-1, 2], vec![1, -2], vec![-1, -2]];
+    assert!(check_lrat(&clauses, "5 2 0 1 2 0\n6 0 5 3 4 0\n").is_ok());
+    assert!(check_lrat(&clauses, "5 2 0 1 0\n6 0 5 3 4 0\n").is_err());
+    assert!(check_lrat(&clauses, "5 2 0 1 2 0\n6 0 5 3 0\n").is_err());
+    assert!(check_lrat(&clauses, "5 2 0 1 2 0\n").is_err());
+    assert!(check_lrat(&clauses, "4 2 0 1 2 0\n6 0 5 3 4 0\n").is_err());
+    assert!(check_lrat(&clauses, "5 2 0 1 2 0\n5 d 1 0\n6 0 5 3 4 0\n").is_ok());
+    assert!(check_lrat(&clauses, "5 2 0 1 2 0\n5 d 3 0\n6 0 5 3 4 0\n").is_err());
+}
+
+#[test]
+fn incremental() {
+    use crate::solver::Solver;
+
+    // Argument x -> y, x, therefore y, checked by assuming the conclusion is false
+    let mut solver = Solver::new(2);
+    solver.append_clause(vec![-1, 2]);
+    assert!(solver.solve_with_assumptions(&[1, -2]).is_none());
+    assert!(solver.solve_with_assumptions(&[1]).unwrap()[1]);
+    assert!(solver.solve_with_assumptions(&[-2]).is_some());
+
+    // Clauses appended later can mention new variables and make the formula UNSAT for good
+    solver.append_clause(vec![3, 1]);
+    solver.append_clause(vec![-3]);
+    assert_eq!(solver.num_vars(), 3);
+    assert!(solver.solve_with_assumptions(&[-2]).is_none());
+    assert!(solver.solve().unwrap()[1]);
+    solver.append_clause(vec![-2]);
+    assert!(solver.solve().is_none());
+    assert!(solver.solve_with_assumptions(&[1]).is_none());
+
+    // Answers under assumptions agree with solving from scratch with the assumptions as units
+    for seed in 0..10 {
+        let cnf = random_3sat(12, 40, seed);
+        let mut solver = Solver::from_cnf(&cnf);
+        for round in 0..10 {
+            let assumptions = (0..3)
+                .map(|i| {
+                    let id = ((seed as usize * 7 + round * 5 + i * 3) % 12 + 1) as isize;
+                    if (round + i) % 2 == 0 { id } else { -id }
+                })
+                .collect::<Vec<_>>();
+
+            let mut fresh = cnf.clone();
+            for lit in assumptions.iter() {
+                fresh.append_clause(vec![*lit]);
+            }
+
+            match solver.solve_with_assumptions(&assumptions) {
+                Some(model) => {
+                    assert!(satisfies(cnf.get_clauses(), &model));
+                    assert!(assumptions.iter().all(|lit| model[lit.unsigned_abs() - 1] == (*lit > 0)));
+                }
+                None => assert!(fresh.find_evidence().is_none()),
+            }
+        }
+    }
+
+    // Expressions added to one CNF share their variables
+    let mut cnf = CNF::new(Vec::new(), Vec::new());
+    let kb = Expr::parse("(a -> b) and (b -> c)".as_bytes()).unwrap().tseitin_into(&mut cnf, false);
+    cnf.enforce(kb, true);
+    let query = Expr::parse("a and not c".as_bytes()).unwrap().tseitin_into(&mut cnf, false);
+    let a = Expr::Var(String::from("a")).tseitin_into(&mut cnf, false);
+
+    let mut solver = Solver::from_cnf(&cnf);
+    assert!(solver.solve_with_assumptions(&[query]).is_none());
+    assert!(solver.solve_with_assumptions(&[-query, a]).is_some());
+    assert_eq!(cnf.get_symbol_table().iter().filter(|name| *name == "a").count(), 1);
+}
+
+#[test]
+fn unsat_cores() {
+    use crate::solver::Solver;
+
+    let symbol_table = vec![String::from("a"), String::from("b"), String::from("c"), String::from("d")];
+    let cnf = CNF::new(symbol_table.clone(), vec![vec![1], vec![2], vec![-1, 3], vec![4], vec![-3, -1], vec![-2, 4]]);
+    assert_eq!(cnf.unsat_core(), Some(vec![0, 2, 4]));
+    assert_eq!(CNF::new(symbol_table, vec![vec![1, 2], vec![-1]]).unsat_core(), None);
+
+    // Every clause of a core is needed
+    for seed in 0..10 {
+        let cnf = random_3sat(8, 50, seed);
+        if let Some(core) = cnf.unsat_core() {
+            let clauses = cnf.get_clauses_clone();
+            let core_clauses = core.iter().map(|idx| clauses[*idx].clone()).collect::<Vec<_>>();
+            assert!(cnf.from_self(core_clauses.clone()).find_evidence().is_none());
+            for skip in 0..core_clauses.len() {
+                let mut smaller = core_clauses.clone();
+                smaller.remove(skip);
+                assert!(cnf.from_self(smaller).find_evidence().is_some());
+            }
+        }
+    }
+
+    let mut solver = Solver::new(3);
+    solver.append_clause(vec![-1, 2]);
+    solver.append_clause(vec![-2, -3]);
+    assert!(solver.solve_with_assumptions(&[1, 3]).is_none());
+    let mut failed = solver.failed_assumptions().to_vec();
+    failed.sort();
+    assert_eq!(failed, vec![1, 3]);
+    assert_eq!(solver.unsat_core(&[2, 1, -2, 3]), Some(vec![-2, 2]));
+    assert_eq!(solver.unsat_core(&[1]), None);
+
+    let premises = [
+        "a -> (b and c)",
+        "b -> (d or e)",
+        "c -> f",
+        "d -> g",
+        "e -> g",
+        "f -> h",
+        "(g and h) -> i",
+        "a",
+        "j",
+    ].iter().map(|src| Expr::parse(src.as_bytes()).unwrap()).collect::<Vec<_>>();
+
+    let conclusion = Expr::Var(String::from("i"));
+    assert_eq!(Expr::premise_core(&premises, Some(&conclusion)), Some(vec![0, 1, 2, 3, 4, 5, 6, 7]));
+    assert_eq!(Expr::premise_core(&premises, Some(&Expr::Var(String::from("j")))), Some(vec![8]));
+    assert_eq!(Expr::premise_core(&premises, Some(&Expr::Var(String::from("k")))), None);
+    assert_eq!(Expr::premise_core(&premises, None), None);
+
+    let inconsistent = ["a -> b", "c", "a", "not b"].iter()
+        .map(|src| Expr::parse(src.as_bytes()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(Expr::premise_core(&inconsistent, None), Some(vec![0, 2, 3]));
+
+    // Equal premises share a Tseitin variable, but only one of them is needed
+    let parse = |srcs: &[&str]| srcs.iter().map(|src| Expr::parse(src.as_bytes()).unwrap()).collect::<Vec<_>>();
+    let core = Expr::premise_core(&parse(&["a", "a", "not a", "c"]), None).unwrap();
+    assert!(core == vec![0, 2] || core == vec![1, 2]);
+    let core = Expr::premise_core(&parse(&["a -> b", "a", "a -> b"]), Some(&Expr::Var(String::from("b")))).unwrap();
+    assert!(core == vec![0, 1] || core == vec![1, 2]);
+}
+
+#[test]
+fn model_enumeration() {
+    use std::collections::HashSet;
+
+    let expr = Expr::parse("a or b".as_bytes()).unwrap();
+    let mut models = expr.models();
+    assert_eq!(models.names(), &[String::from("a"), String::from("b")]);
+    let found = models.by_ref().collect::<HashSet<_>>();
+    assert_eq!(found, HashSet::from([vec![true, true], vec![true, false], vec![false, true]]));
+
+    // Auxiliary variables don't lead to duplicate models
+    let expr = Expr::parse("(a xor b) <-> (c nand (a or d))".as_bytes()).unwrap();
+    let models = expr.models().collect::<Vec<_>>();
+    let unique = models.iter().cloned().collect::<HashSet<_>>();
+    assert_eq!(models.len(), unique.len());
+    assert_eq!(models.len(), 8);
+
+    let cnf = Expr::parse("(a and b) or c".as_bytes()).unwrap().tseitin(false);
+    assert_eq!(cnf.models_over(&[String::from("a")]).unwrap().count(), 2);
+    assert_eq!(cnf.models_over(&[String::from("c"), String::from("a")]).unwrap().count(), 3);
+    assert!(cnf.models_over(&[String::from("z")]).is_err());
+
+    assert_eq!(Expr::parse("T or F".as_bytes()).unwrap().models().collect::<Vec<_>>(), vec![Vec::<bool>::new()]);
+    assert_eq!(Expr::parse("a and not a".as_bytes()).unwrap().models().count(), 0);
+}
+
+#[test]
+fn bignum() {
+    use crate::bignum::BigUint;
+
+    assert_eq!(BigUint::zero().to_string(), "0");
+    assert_eq!(BigUint::from(1234567890123).to_string(), "1234567890123");
+    assert_eq!(BigUint::pow2(100).to_string(), "1267650600228229401496703205376");
+    assert_eq!((&BigUint::pow2(64) + &BigUint::from(u64::MAX)).to_string(), "36893488147419103231");
+    assert_eq!((&BigUint::from(u64::MAX) * &BigUint::from(u64::MAX)).to_string(), "340282366920938463426481119284349108225");
+    assert_eq!(&BigUint::from(1_000_000_000) * &BigUint::zero(), BigUint::zero());
+}
+
+#[test]
+fn model_counting() {
+    for prop in [
+        "a or b",
+        "(a xor b) <-> (c nand (a or d))",
+        "not (((a or (b and c) <-> d) xor a or (b and c) <-> d) nand e) or not (not f)",
+        "(e nand f) and not g <-> (h or i) xor (not j nor k)",
+        "a and not a",
+        "T",
+    ] {
+        let expr = Expr::parse(prop.as_bytes()).unwrap();
+        assert_eq!(expr.count_models().to_string(), expr.models().count().to_string(), "{prop}");
+    }
+
+    // Independent components multiply, 50 copies of (a or b) have 3^50 models
+    let prop = (0..50).map(|i| format!("(a{i} or b{i})")).collect::<Vec<_>>().join(" and ");
+    let expr = Expr::parse(prop.as_bytes()).unwrap();
+    assert_eq!(expr.count_models().to_string(), "717897987691852588770249");
+
+    // Auxiliary variables that aren't defined by the named ones are still projected away
+    let symbol_table = vec![String::from("a"), String::from("b"), String::from("$0")];
+    let cnf = CNF::new(symbol_table, vec![vec![1, 3], vec![2, -3]]);
+    assert_eq!(cnf.count_models().to_string(), "3");
+    assert_eq!(cnf.count_models_over(&[String::from("a")]).unwrap().to_string(), "2");
+
+    for seed in 0..10 {
+        let cnf = random_3sat(10, 30, seed);
+        let brute_force = (0..1u32 << 10).filter(|bits| {
+            let model = (0..10).map(|i| bits >> i & 1 == 1).collect::<Vec<_>>();
+            satisfies(cnf.get_clauses(), &model)
+        }).count();
+        assert_eq!(cnf.count_models().to_string(), brute_force.to_string());
+    }
+}
+
+#[test]
+fn maxsat() {
+    use crate::maxsat::MaxSat;
+
+    // Compare optimal costs with brute force over hard 3-SAT clauses and weighted soft clauses
+    for seed in 0..15 {
+        let hard = random_3sat(8, 12, seed);
+        let soft = random_3sat(8, 14, seed + 100);
+        let mut maxsat = MaxSat::new(hard.clone());
+        for (idx, clause) in soft.get_clauses().iter().enumerate() {
+            maxsat.add_soft(clause[..1 + idx % 2].to_vec(), (idx as u64 * 7 + seed) % 5 + 1);
+        }
+
+        let brute_force = (0..1u32 << 8)
+            .map(|bits| (0..8).map(|i| bits >> i & 1 == 1).collect::<Vec<_>>())
+            .filter(|model| satisfies(hard.get_clauses(), model))
+            .map(|model| maxsat.cost(&model))
+            .min();
+
+        let solution = maxsat.solve();
+        assert_eq!(solution.as_ref().map(|solution| solution.cost), brute_force);
+        if let Some(solution) = solution {
+            assert!(satisfies(hard.get_clauses(), &solution.model));
+        }
+    }
+
+    // Scheduling style preferences over expressions
+    let mut maxsat = MaxSat::new(CNF::new(Vec::new(), Vec::new()));
+    let parse = |src: &str| Expr::parse(src.as_bytes()).unwrap();
+    maxsat.add_hard_expr(&parse("not (morning and evening)"));
+    maxsat.add_hard_expr(&parse("morning or evening"));
+    maxsat.add_soft_expr(&parse("morning"), 3);
+    maxsat.add_soft_expr(&parse("evening"), 5);
+    maxsat.add_soft_expr(&parse("evening -> late"), 1);
+    maxsat.add_soft_expr(&parse("not late"), 2);
+
+    let solution = maxsat.solve().unwrap();
+    assert_eq!(solution.cost, 4);
+    let cnf = maxsat.get_cnf();
+    let value = |name: &str| solution.model[cnf.get_id(name).unwrap() - 1];
+    assert!(value("evening") && !value("morning") && !value("late"));
+
+    maxsat.add_hard_expr(&parse("F"));
+    assert!(maxsat.solve().is_none());
+}
+
+#[test]
+fn cardinality() {
+    use crate::card::{CardEncoding, Comparison};
+
+    fn choose(n: usize, k: usize) -> u64 {
+        (0..k).fold(1, |acc, i| acc * (n - i) as u64 / (i as u64 + 1))
+    }
+
+    // Every encoding must count the right models for the constraint and for its negation
+    for encoding in [CardEncoding::SequentialCounter, CardEncoding::Totalizer, CardEncoding::CardinalityNetwork] {
+        for comparison in [Comparison::AtMost, Comparison::AtLeast, Comparison::Exactly] {
+            for n in 1..=6 {
+                for bound in 0..=n + 1 {
+                    let vars = (0..n).map(|i| Expr::Var(format!("x{}", i))).collect::<Vec<_>>();
+                    let expr = Expr::cardinality(vars, comparison, bound, encoding);
+
+                    let expected = (0..=n).filter(|count| comparison.holds(*count, bound)).map(|count| choose(n, count)).sum::<u64>();
+                    assert_eq!(expr.count_models().to_string(), expected.to_string(), "{:?} {:?} {} of {}", encoding, comparison, bound, n);
+                    assert_eq!(Expr::not(expr).count_models().to_string(), ((1 << n) - expected).to_string());
+                }
+            }
+        }
+    }
+
+    // Cardinality constraints mix with ordinary formulas, and agree with the VM
+    let vars = ["a", "b", "c", "d"].map(|name| Expr::Var(name.to_string()));
+    let expr = Expr::and(
+        Expr::exactly(vars.to_vec(), 2),
+        Expr::or(Expr::at_most(vec![vars[0].clone(), vars[1].clone()], 0), Expr::parse(b"c -> d").unwrap()),
+    );
+    let cnf = expr.tseitin(false);
+    let evidence = cnf.find_evidence().unwrap();
+    assert!(expr.is_valid_sat_proof(&evidence, &cnf.get_symbol_table()));
+    assert_eq!(expr.count_models().to_string(), "4");
+}
+
+#[test]
+fn pseudo_boolean() {
+    use crate::card::Comparison;
+    use crate::pb::PbEncoding;
+
+    // Random weights, including negative and repeated ones, against brute force for both encodings
+    let mut seed = 7u64;
+    let mut next = |modulus: u64| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (seed >> 33) % modulus
+    };
+    let depth = 70;
+    for _ in 0..depth {
+        let n = 1 + next(6) as usize;
+        let weights = (0..n).map(|_| next(13) as i64 - 6).collect::<Vec<_>>();
+        let bound = next(15) as i64 - 5;
+        let comparison = [Comparison::AtMost, Comparison::AtLeast, Comparison::Exactly][next(3) as usize];
+
+        let expected = (0..1u32 << n)
+            .filter(|bits| {
+                let sum = (0..n).filter(|i| bits >> i & 1 == 1).map(|i| weights[i]).sum::<i64>();
+                comparison.holds(sum, bound)
+            })
+            .count();
+
+        for encoding in [PbEncoding::Bdd, PbEncoding::Adder] {
+            let terms = weights.iter().enumerate().map(|(i, weight)| (*weight, Expr::Var(format!("x{}", i)))).collect();
+            let expr = Expr::pseudo_boolean(terms, comparison, bound, encoding);
+            assert_eq!(expr.count_models().to_string(), expected.to_string(), "{:?} {:?} {} {}", encoding, weights, comparison.operator(), bound);
+            assert_eq!(Expr::not(expr.clone()).count_models().to_string(), ((1 << n) - expected).to_string());
+
+            let mut manager = crate::bdd::Bdd::new((0..n).map(|i| format!("x{}", i)).collect());
+            let f = manager.build(&expr);
+            assert_eq!(manager.count_models(f).to_string(), expected.to_string(), "{:?} {} {}", weights, comparison.operator(), bound);
+        }
+    }
+
+    // OPB files load straight into a CNF
+    let src = "* #variable= 3 #constraint= 3\nmin: +1 x1 +1 x2 ;\n+3 x1 +2 x2\n+5 x3 <= 7 ;\n+1 x1 +1 ~x3 >= 1;\n+1 x2 +1 x3 = 1 ;\n";
+    for encoding in [PbEncoding::Bdd, PbEncoding::Adder] {
+        let cnf = CNF::from_opb(src, encoding).unwrap();
+        assert_eq!(&cnf.get_symbol_table()[..3], ["x1", "x2", "x3"]);
+        assert_eq!(cnf.count_models().to_string(), "2");
+    }
+
+    let opb = crate::pb::parse_opb(src).unwrap();
+    assert_eq!(opb.constraints[1].terms, vec![(1, 1), (1, -3)]);
+
+    assert_eq!(CNF::from_opb("+1 x1 +1 x2 >= 1\n", PbEncoding::Bdd).unwrap_err(), "Line 1: Expected ';' at the end of the constraint");
+    assert_eq!(CNF::from_opb("+1 x1 >= 1 ;\n+2 x1 x2 >= 1 ;", PbEncoding::Bdd).unwrap_err(), "Line 2: Non-linear term with 'x1' and 'x2' is not supported");
+    assert!(CNF::from_opb("+1 x1 > 1 ;", PbEncoding::Bdd).is_err());
+}
+
+#[test]
+fn dimacs() {
+    // Clauses may span lines, and SATLIB files end with '%'
+    let src = "c example\nc var 2 rain\nc 100 clauses\np cnf 3 3\n1 -2 0\n2\n3 0 -1 0\n%\n0\n";
+    let cnf = CNF::from_dimacs(src).unwrap();
+    assert_eq!(cnf.get_symbol_table(), ["1", "rain", "3"]);
+    assert_eq!(cnf.get_clauses(), &vec![vec![1, -2], vec![2, 3], vec![-1]]);
+    assert!(cnf.find_evidence().is_some());
+
+    // Names survive a round trip, so auxiliary variables are still projected away
+    let expr = Expr::parse(b"(a -> b) and (b xor c)").unwrap();
+    let cnf = expr.tseitin(false);
+    let text = cnf.to_dimacs();
+    assert!(text.contains(&format!("p cnf {} {}\n", cnf.num_vars(), cnf.get_clauses().len())));
+
+    let read = CNF::read_dimacs(std::io::BufReader::new(text.as_bytes())).unwrap();
+    assert_eq!(read.get_symbol_table(), cnf.get_symbol_table());
+    assert_eq!(read.get_clauses(), cnf.get_clauses());
+    assert_eq!(read.count_models().to_string(), expr.count_models().to_string());
+
+    // Other comments are left alone, and names may swap the default ones around
+    let cnf = CNF::from_dimacs("c 1 2\nc var 1 2\nc var 2 1\np cnf 2 1\n1 2 0\n").unwrap();
+    assert_eq!(cnf.get_symbol_table(), ["2", "1"]);
+
+    for (src, err) in [
+        ("1 2 0\n", "Line 1: clause before the 'p cnf' problem line"),
+        ("p cnf 2\n", "Line 1: expected 'p cnf <variables> <clauses>', found 'p cnf 2'"),
+        ("p sat 2 1\n", "Line 1: unsupported format 'sat', expected 'cnf'"),
+        ("p cnf 2 1\np cnf 2 1\n", "Line 2: duplicate problem line"),
+        ("p cnf 2 1\n1 -3 0\n", "Line 2: literal -3 is out of range for 2 variables"),
+        ("p cnf 2 1\n1 x 0\n", "Line 2: expected a literal, found 'x'"),
+        ("p cnf 2 1\n1 2\n", "Line 2: last clause is missing its terminating 0"),
+        ("p cnf 2 2\n1 2 0\n", "Problem line declares 2 clauses but 1 were found"),
+        ("c var 3 a\np cnf 2 0\n", "Line 1: named variable 3 is out of range for 2 variables"),
+        ("c var 1 a\nc var 2 a\np cnf 2 0\n", "Line 2: name 'a' is used by variables 1 and 2"),
+        ("c var 1 2\np cnf 2 0\n", "Line 1: name '2' is used by variables 1 and 2"),
+        ("p cnf 2 0\nc var 2 1\n", "Line 2: name '1' is used by variables 1 and 2"),
+        ("", "Missing 'p cnf' problem line"),
+    ] {
+        assert_eq!(CNF::from_dimacs(src).unwrap_err(), err);
+    }
+}
+
+#[test]
+fn native_connectives() {
+    let (a, b) = (Expr::Var(String::from("a")), Expr::Var(String::from("b")));
+
+    // The VM and the Tseitin clauses agree with each connective's truth table
+    type Connective = fn(Expr, Expr) -> Expr;
+    let connectives: [(Connective, [bool; 4]); 7] = [
+        (Expr::and, [false, false, false, true]),
+        (Expr::or, [false, true, true, true]),
+        (Expr::xor, [false, true, true, false]),
+        (Expr::iff, [true, false, false, true]),
+        (Expr::eif, [true, true, false, true]),
+        (Expr::nand, [true, true, true, false]),
+        (Expr::nor, [true, false, false, false]),
+    ];
+    for (connective, table) in connectives {
+        let expr = connective(a.clone(), b.clone());
+        for (idx, expected) in table.iter().enumerate() {
+            let (a_value, b_value) = (idx >> 1 == 1, idx & 1 == 1);
+            let mut env = Env::new();
+            env.define(String::from("a"), a_value);
+            env.define(String::from("b"), b_value);
+            assert_eq!(VM::new(&env, expr.compile()).eval().unwrap(), *expected, "{expr}");
+
+            let mut cnf = expr.tseitin(false);
+            cnf.append_clause(vec![if a_value { 1 } else { -1 } * cnf.get_id("a").unwrap() as isize]);
+            cnf.append_clause(vec![if b_value { 1 } else { -1 } * cnf.get_id("b").unwrap() as isize]);
+            assert_eq!(cnf.find_evidence().is_some(), *expected, "{expr}");
+        }
+    }
+
+    // Printing gives back the same tree when parsed, with only the needed parentheses
+    for (prop, printed) in [
+        ("a nand (a nor not b and b or c) xor c -> b <-> c", "a nand (a nor not b and b or c) xor c -> b <-> c"),
+        ("a -> (b -> c)", "a -> (b -> c)"),
+        ("((a -> b) -> c)", "a -> b -> c"),
+        ("not (not a) and (b or T)", "not (not a) and (b or T)"),
+        ("(a xor b) and c nor F", "(a xor b) and c nor F"),
+    ] {
+        let expr = Expr::parse(prop.as_bytes()).unwrap();
+        assert_eq!(expr.to_string(), printed);
+        assert_eq!(Expr::parse(printed.as_bytes()).unwrap(), expr);
+    }
+
+    // Chains grow linearly, so their Tseitin encodings stay small
+    let chain = (0..200).map(|i| format!("x{i}")).collect::<Vec<_>>().join(" <-> ");
+    let expr = Expr::parse(chain.as_bytes()).unwrap();
+    assert_eq!(expr.tseitin(false).get_clauses().len(), 4 * 199 + 1);
+
+    let chain = (0..12).map(|i| format!("x{i}")).collect::<Vec<_>>().join(" xor ");
+    assert_eq!(Expr::parse(chain.as_bytes()).unwrap().count_models().to_string(), (1u64 << 11).to_string());
+}
+
+// Random expression over the variables a to e using every connective
+fn random_expr(depth: usize, seed: &mut u64) -> Expr {
+    *seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    let choice = (*seed >> 33) % if depth == 0 { 6 } else { 16 };
+    let mut sub = || random_expr(depth.saturating_sub(1), seed);
+    match choice {
+        0..=4 => Expr::Var(["a", "b", "c", "d", "e"][choice as usize].to_string()),
+        5 => Expr::Literal(*seed >> 40 & 1 == 1),
+        6 => Expr::not(sub()),
+        7 => Expr::and(sub(), sub()),
+        8 => Expr::or(sub(), sub()),
+        9 => Expr::xor(sub(), sub()),
+        10 => Expr::iff(sub(), sub()),
+        11 => Expr::eif(sub(), sub()),
+        12 => Expr::nand(sub(), sub()),
+        13 => Expr::nor(sub(), sub()),
+        14 => Expr::at_most(vec![sub(), sub(), sub()], 1),
+        _ => Expr::not(Expr::not(sub())),
+    }
+}
+
+#[test]
+fn plaisted_greenbaum() {
+    use crate::expr::CnfEncoding;
+
+    // Both encodings have the same models once auxiliary variables are projected away
+    let mut seed = 3;
+    for _ in 0..300 {
+        let expr = random_expr(4, &mut seed);
+        let tseitin = expr.encode(CnfEncoding::Tseitin);
+        let pg = expr.encode(CnfEncoding::PlaistedGreenbaum);
+
+        let names = expr.get_variables();
+        assert_eq!(
+            pg.count_models_over(&names).unwrap().to_string(),
+            tseitin.count_models_over(&names).unwrap().to_string(),
+            "{expr}"
+        );
+        assert!(pg.get_clauses().len() <= tseitin.get_clauses().len(), "{expr}");
+    }
+
+    // Negations become negative literals instead of variables, and chains of the same connective share a gate
+    let pg = Expr::parse(b"not (a and not b)").unwrap().encode(CnfEncoding::PlaistedGreenbaum);
+    assert_eq!(pg.get_clauses(), &vec![vec![-1, 2]]);
+
+    let pg = Expr::parse(b"c xor (a nand (b -> not d) or e)").unwrap().encode(CnfEncoding::PlaistedGreenbaum);
+    assert_eq!(pg.num_vars(), 8);
+    assert_eq!(pg.get_clauses().len(), 2 + 4 + 3 + 1);
+}
+
+#[test]
+fn structural_sharing() {
+    use crate::runtime::partial::Partial;
+
+    // Repeated subformulas are parsed into one shared node, which still reads as the whole tree
+    let expr = Expr::parse(b"((a and b) or c) xor ((a and b) or c) and (a and b)").unwrap();
+    assert_eq!((expr.size(), expr.dag_size()), (15, 7));
+    assert_eq!(expr.to_string(), "(a and b or c) xor (a and b or c) and (a and b)");
+    assert_eq!(Expr::parse(b"a and a").unwrap().dag_size(), 2);
+
+    // Copies become one shared node, and so do equal operands built apart
+    let ab = Expr::and(Expr::Var("a".to_string()), Expr::Var("b".to_string()));
+    let both = Expr::or(ab.clone(), ab.clone());
+    assert_eq!((both.size(), both.dag_size()), (7, 4));
+    let a = Expr::not(Expr::Var("a".to_string()));
+    let b = Expr::not(Expr::Var("a".to_string()));
+    assert_eq!(Expr::and(a.clone(), b).dag_size(), 3);
+    assert_eq!(Expr::and(a.clone(), Expr::Var("b".to_string())), Expr::and(a, Expr::Var("b".to_string())));
+
+    // Each shared subformula gets a single Tseitin variable
+    let cnf = expr.tseitin(false);
+    assert_eq!(cnf.num_vars(), 3 + 4);
+    assert_eq!(cnf.count_models().to_string(), "3");
+
+    // Gates are also shared between expressions added to the same CNF
+    let mut cnf = CNF::new(Vec::new(), Vec::new());
+    let first = Expr::parse(b"(a or b) -> c").unwrap().tseitin_into(&mut cnf, false);
+    let num_vars = cnf.num_vars();
+    let second = Expr::parse(b"not ((a or b) -> c)").unwrap().tseitin_into(&mut cnf, false);
+    assert_eq!(cnf.num_vars(), num_vars + 1);
+    assert_ne!(first, second);
+
+    // A rule set built from many copies of a few rules only grows by its chain of 400 conjunctions
+    let rules = (0..200)
+        .map(|i| format!("(x{} -> (a and b or c)) and (a and b or c <-> d)", i % 4))
+        .collect::<Vec<_>>()
+        .join(" and ");
+    let expr = Expr::parse(rules.as_bytes()).unwrap();
+    assert!(expr.dag_size() < 450);
+    let shared = expr.tseitin(false);
+    let pg = expr.encode(CnfEncoding::PlaistedGreenbaum);
+    assert!(shared.num_vars() < 450 && pg.num_vars() < 450);
+    assert_eq!(shared.count_models().to_string(), pg.count_models().to_string());
+
+    // Every consumer visits a shared node once, so a chain whose tree has over 2^70 nodes is quick to handle
+    let v = Expr::Var("v".to_string());
+    let mut chain = Expr::Var("a".to_string());
+    let depth = 70;
+    for _ in 0..depth {
+        chain = Expr::and(chain.clone(), Expr::or(chain.clone(), v.clone()));
+    }
+    assert_eq!(chain.dag_size(), 2 + 2 * depth);
+    assert_eq!(chain.size(), usize::MAX);
+    assert_eq!(chain.get_variables(), vec!["a", "v"]);
+    assert!(chain.tseitin(false).get_clauses().len() <= 6 * depth + 1);
+    assert!(chain.encode(CnfEncoding::PlaistedGreenbaum).get_clauses().len() < depth * depth);
+    assert!(chain.is_equivalent(&Expr::Var("a".to_string()), Backend::Bdd));
+    assert!(chain.compile().len() < 10 * depth);
+    let mut env = Env::new();
+    env.define("a".to_string(), true);
+    env.define("v".to_string(), false);
+    assert_eq!(chain.program().eval(&env), Ok(true));
+    assert_eq!(chain.simplify(), Expr::Var("a".to_string()));
+    assert_eq!(chain.partial_eval(&Env::new()), Partial::Residual(Expr::Var("a".to_string())));
+
+    // Where the short-circuited occurrences of a shared node would each compute it, the program stays linear
+    let mut mux = Expr::Var("s".to_string());
+    for k in 0..depth {
+        let (x, y) = (Expr::Var(format!("x{k}")), Expr::Var(format!("y{k}")));
+        mux = Expr::or(Expr::and(x, mux.clone()), Expr::and(y, mux));
+    }
+    assert!(mux.compile().len() < 10 * depth);
+    let mut env = Env::new();
+    for k in 0..depth {
+        env.define(format!("x{k}"), k % 2 == 0);
+        env.define(format!("y{k}"), k % 2 == 1);
+    }
+    env.define("s".to_string(), true);
+    assert_eq!(mux.program().eval(&env), Ok(true));
+    env.define("s".to_string(), false);
+    assert_eq!(mux.program().eval(&env), Ok(false));
+}
+
+#[test]
+fn simplify() {
+    use crate::simplify::Simplifier;
+
+    let simplified = |src: &str| Expr::parse(src.as_bytes()).unwrap().simplify().to_string();
+    assert_eq!(simplified("a and T or F"), "a");
+    assert_eq!(simplified("not (not a) -> F"), "not a");
+    assert_eq!(simplified("b and a and b"), "b and a");
+    assert_eq!(simplified("a and (b or a) and c"), "a and c");
+    assert_eq!(simplified("(a or b) and (c or b or a)"), "a or b");
+    assert_eq!(simplified("a and b and not a"), "F");
+    assert_eq!(simplified("not (a and not b)"), "not a or b");
+    assert_eq!(simplified("not (a xor b) nor a"), "(a <-> b) nor a");
+    assert_eq!(Expr::at_most(vec![Expr::Literal(true), Expr::Var(String::from("a")), Expr::Literal(false)], 1).simplify().to_string(), "at most 0 of (a)");
+    assert_eq!(Expr::not(Expr::at_least(vec![Expr::Var(String::from("a")), Expr::Var(String::from("b"))], 1)).simplify().to_string(), "at most 0 of (a, b)");
+
+    // Simplified expressions are equivalent, have no literals left inside and are a fixpoint
+    fn literals(expr: &Expr) -> usize {
+        usize::from(matches!(expr, Expr::Literal(_))) + expr.node().operands().into_iter().map(|operand| literals(operand)).sum::<usize>()
+    }
+    let mut seed = 11;
+    for _ in 0..300 {
+        let expr = random_expr(4, &mut seed);
+        let simple = expr.simplify();
+        assert!(Expr::not(Expr::iff(expr.clone(), simple.clone())).tseitin(false).find_evidence().is_none(), "{expr} => {simple}");
+        assert!(matches!(simple, Expr::Literal(_)) || literals(&simple) == 0, "{expr} => {simple}");
+        assert_eq!(simple.simplify(), simple);
+    }
+
+    // Rules can be added as patterns or functions, and removed by name
+    let mut simplifier = Simplifier::empty();
+    let parse = |src: &str| Expr::parse(src.as_bytes()).unwrap();
+    simplifier.add_pattern(parse("x and (y or z)"), parse("x and y or x and z")).unwrap();
+    assert_eq!(simplifier.simplify(&parse("a and (b or (c and (d or e)))")).to_string(), "a and b or (a and (c and d) or a and (c and e))");
+    assert_eq!(simplifier.add_pattern(parse("x or y"), parse("x or z")).unwrap_err(), "Variable 'z' of the replacement doesn't occur in the pattern");
+
+    simplifier.add_rule("swap", |expr| match expr.node() {
+        crate::dag::Node::Xor(l, r) if l.to_string() > r.to_string() => Some(Expr::xor(r.clone(), l.clone())),
+        _ => None,
+    });
+    assert_eq!(simplifier.simplify(&parse("c xor b")).to_string(), "b xor c");
+    assert_eq!(simplifier.rule_names(), ["x and (y or z) => x and y or x and z", "swap"]);
+    simplifier.remove_rule("swap").unwrap();
+    assert_eq!(simplifier.remove_rule("swap").unwrap_err(), "Unknown rule 'swap'");
+}
+
+#[test]
+fn normal_forms() {
+    let parse = |src: &str| Expr::parse(src.as_bytes()).unwrap();
+    let equivalent = |l: &Expr, r: &Expr| Expr::not(Expr::iff(l.clone(), r.clone())).tseitin(false).find_evidence().is_none();
+
+    assert_eq!(parse("not (a -> b nand c)").to_nnf(100).unwrap().to_string(), "a and (b and c)");
+    assert_eq!(parse("a xor b").to_cnf(100).unwrap().to_string(), "(a or b) and (not b or not a)");
+    assert_eq!(parse("(a or b) and (c or a)").to_dnf(100).unwrap().to_string(), "a or b and c");
+    assert_eq!(parse("a and not a").to_dnf(100).unwrap().to_string(), "F");
+    assert_eq!(parse("a or not a or F").to_cnf(100).unwrap().to_string(), "T");
+    assert_eq!(Expr::not(Expr::exactly(vec![parse("a"), parse("b")], 1)).to_nnf(100).unwrap().to_string(), "(at most 0 of (a, b)) or (at least 2 of (a, b))");
+
+    // Every form is equivalent, and each only puts its connectives where it should
+    fn shape(expr: &Expr, depth: usize, order: [fn(&Expr) -> bool; 2]) -> bool {
+        let is_literal = match expr {
+            Expr::Var(_) | Expr::Card(_) | Expr::Pb(_) | Expr::Literal(_) => true,
+            Expr::Not(_) => matches!(expr.node().operands()[0], Expr::Var(_)),
+            _ => false,
+        };
+        // A level may be skipped, e.g. a CNF of a single clause
+        is_literal || (depth..2).find(|depth| (order[*depth])(expr)).is_some_and(|depth| {
+            expr.node().operands().into_iter().all(|operand| shape(operand, depth, order))
+        })
+    }
+    let is_and = |expr: &Expr| matches!(expr, Expr::And(_));
+    let is_or = |expr: &Expr| matches!(expr, Expr::Or(_));
+    let mut seed = 17;
+    for _ in 0..200 {
+        let expr = random_expr(3, &mut seed);
+        let nnf = expr.to_nnf(10_000).unwrap();
+        let cnf = expr.to_cnf(10_000).unwrap();
+        let dnf = expr.to_dnf(10_000).unwrap();
+        for form in [&nnf, &cnf, &dnf] {
+            assert!(equivalent(&expr, form), "{expr} => {form}");
+        }
+        assert!(shape(&cnf, 0, [is_and, is_or]), "{cnf}");
+        assert!(shape(&dnf, 0, [is_or, is_and]), "{dnf}");
+    }
+
+    // Distributing a long disjunction of conjunctions blows up, so the size guard stops it
+    let src = (0..8).map(|i| format!("(x{i} and y{i})")).collect::<Vec<_>>().join(" or ");
+    assert_eq!(parse(&src).to_cnf(100_000).unwrap().size(), 256 * (8 + 7) + 255);
+    assert_eq!(parse(&src).to_cnf(1_000).unwrap_err(), "Normal form has more than 1000 nodes");
+    assert!(parse(&src).to_dnf(100).is_ok());
+    assert_eq!(parse(&src).to_nnf(10).unwrap_err(), "Normal form has more than 10 nodes");
+}
+
+#[test]
+fn minimize() {
+    use crate::minimize::MinimizeMethod;
+
+    let parse = |src: &str| Expr::parse(src.as_bytes()).unwrap();
+    let sop = |src: &str, dont_care: Option<&str>, method| {
+        parse(src).minimize_sop(dont_care.map(parse).as_ref(), method).unwrap().to_string()
+    };
+    for method in [MinimizeMethod::Exact, MinimizeMethod::Espresso] {
+        assert_eq!(sop("a and b or a and not b", None, method), "a");
+        assert_eq!(sop("a and b or not a and c or b and c", None, method), "a and b or not a and c");
+        assert_eq!(sop("a and not b", Some("a and b"), method), "a");
+        assert_eq!(sop("a and not a", None, method), "F");
+        assert_eq!(sop("a or not a", None, method), "T");
+        assert_eq!(parse("a or b and c").minimize_pos(None, method).unwrap().to_string(), "(a or b) and (a or c)");
+    }
+
+    // Every cover is equivalent outside the don't-cares, and the exact one is never larger
+    let cost = |expr: &Expr| {
+        let terms = crate::normal::dnf_terms(expr, 100_000).unwrap();
+        (terms.len(), terms.iter().map(|term| term.len()).sum::<usize>())
+    };
+    let equivalent_outside = |l: &Expr, r: &Expr, dont_care: &Expr| {
+        Expr::and(Expr::not(dont_care.clone()), Expr::xor(l.clone(), r.clone())).tseitin(false).find_evidence().is_none()
+    };
+    let mut seed = 23;
+    for _ in 0..100 {
+        let expr = random_expr(3, &mut seed);
+        let dont_care = random_expr(2, &mut seed);
+        let exact = expr.minimize_sop(Some(&dont_care), MinimizeMethod::Exact).unwrap();
+        assert!(equivalent_outside(&expr, &exact, &dont_care), "{expr} => {exact}");
+        let pos = expr.minimize_pos(Some(&dont_care), MinimizeMethod::Exact).unwrap();
+        assert!(equivalent_outside(&expr, &pos, &dont_care), "{expr} => {pos}");
+
+        match expr.minimize_sop(Some(&dont_care), MinimizeMethod::Espresso) {
+            Ok(heuristic) => {
+                assert!(equivalent_outside(&expr, &heuristic, &dont_care), "{expr} => {heuristic}");
+                assert!(cost(&exact) <= cost(&heuristic), "{expr} => {exact} against {heuristic}");
+            }
+            Err(err) => assert_eq!(err, "Minimisation doesn't support cardinality or pseudo-Boolean constraints"),
+        }
+    }
+
+    // Past the exact limit the heuristic works from cube covers rather than the truth table
+    let src = (0..12).map(|i| format!("x{i} and y{i} or x{i} and not y{i}")).collect::<Vec<_>>().join(" or ");
+    let expected = (0..12).map(|i| format!("x{i}")).collect::<Vec<_>>();
+    let mut expected = expected;
+    expected.sort();
+    assert_eq!(sop(&src, None, MinimizeMethod::Auto), expected.join(" or "));
+    assert_eq!(parse(&src).minimize_sop(None, MinimizeMethod::Exact).unwrap_err(), "Exact minimisation supports at most 16 variables, found 24");
+    assert!("quine".parse::<MinimizeMethod>().is_err());
+
+    // Not all equal has many covers of the same size, past the Petrick limit Auto settles for a greedy one
+    // while Exact keeps searching, and gives up with an error when even the search is too long
+    let not_all_equal = |n: usize| {
+        let vars = (0..n).map(|i| format!("x{i}")).collect::<Vec<_>>();
+        let negated = vars.iter().map(|var| format!("not {var}")).collect::<Vec<_>>();
+        parse(&format!("({}) and ({})", vars.join(" or "), negated.join(" or ")))
+    };
+    let expr = not_all_equal(6);
+    let exact = expr.minimize_sop(None, MinimizeMethod::Exact).unwrap();
+    let greedy = expr.minimize_sop(None, MinimizeMethod::Auto).unwrap();
+    assert_eq!(cost(&exact), (6, 12));
+    assert!(cost(&greedy) > cost(&exact));
+    assert!(exact.is_equivalent(&expr, Backend::Sat) && greedy.is_equivalent(&expr, Backend::Sat));
+    assert_eq!(
+        not_all_equal(7).minimize_sop(None, MinimizeMethod::Exact).unwrap_err(),
+        "Exact cover search exceeded 100000 branches, use method 'espresso' or 'auto' for a heuristic cover"
+    );
+}
+
+#[test]
+fn bdd() {
+    use crate::bdd::{self, Bdd, BddOp};
+    use crate::card::Comparison;
+    use crate::expr::Backend;
+    use crate::pb::PbEncoding;
+
+    let parse = |src: &str| Expr::parse(src.as_bytes()).unwrap();
+
+    // Equivalent expressions get the same handle
+    let mut manager = Bdd::new(Vec::new());
+    let f = manager.build(&parse("a and (b or c)"));
+    assert_eq!(f, manager.build(&parse("(c or b) and a")));
+    assert_eq!(f, manager.build(&parse("not (not a or not b and not c)")));
+    assert_ne!(f, manager.build(&parse("a or b and c")));
+    assert_eq!(manager.count_models(f).to_string(), "3");
+
+    let (a, b, c) = (manager.var("a"), manager.var("b"), manager.var("c"));
+    let b_or_c = manager.apply(BddOp::Or, b, c);
+    assert_eq!(manager.ite(a, b_or_c, bdd::FALSE), f);
+    assert_eq!(manager.restrict(f, "a", false), bdd::FALSE);
+    assert_eq!(manager.restrict(f, "a", true), b_or_c);
+    assert_eq!(manager.exists(f, &["a".to_string()]), b_or_c);
+    assert_eq!(manager.forall(b_or_c, &["b".to_string()]), c);
+
+    // Both backends agree, and so do the model counts
+    let mut seed = 29;
+    for _ in 0..300 {
+        let l = random_expr(3, &mut seed);
+        let r = random_expr(3, &mut seed);
+        assert_eq!(l.is_equivalent(&r, Backend::Bdd), l.is_equivalent(&r, Backend::Sat), "{l} and {r}");
+        assert_eq!(l.is_tautology(Backend::Bdd), l.is_tautology(Backend::Sat), "{l}");
+        assert_eq!(l.is_contradiction(Backend::Bdd), l.is_contradiction(Backend::Sat), "{l}");
+
+        let mut manager = Bdd::new(Vec::new());
+        let f = manager.build(&l);
+        assert_eq!(manager.count_models(f).to_string(), l.count_models().to_string(), "{l}");
+    }
+    let vars = ["a", "b", "c"].map(|name| Expr::Var(name.to_string())).to_vec();
+    assert!(Expr::at_least(vars, 2).is_equivalent(&parse("a and b or a and c or b and c"), Backend::Bdd));
+
+    // Distinct weights don't make a node per reachable sum
+    let terms = (0..40).map(|i| (1 << i, Expr::Var(format!("x{}", i)))).collect();
+    let expr = Expr::pseudo_boolean(terms, Comparison::AtMost, (1 << 39) + 3, PbEncoding::Bdd);
+    let mut manager = Bdd::new(Vec::new());
+    let f = manager.build(&expr);
+    assert_eq!(manager.size(&[f]), 38);
+    assert_eq!(manager.count_models(f).to_string(), ((1u64 << 39) + 4).to_string());
+
+    // Grouping the pairs together takes the BDD from exponential to linear size
+    let pairs = parse("x1 and y1 or x2 and y2 or x3 and y3 or x4 and y4");
+    let order = ["x1", "x2", "x3", "x4", "y1", "y2", "y3", "y4"].map(String::from).to_vec();
+    let mut manager = Bdd::new(order);
+    let f = manager.build(&pairs);
+    assert_eq!(manager.size(&[f]), 30);
+    let count = manager.count_models(f);
+    let f = manager.sift(&[f])[0];
+    assert_eq!(manager.size(&[f]), 8);
+    assert_eq!(manager.count_models(f), count);
+    assert_eq!(f, manager.build(&pairs));
+    assert!(manager.reorder(vec!["x1".to_string()], &[f]).is_err());
+
+    // Sifting by swapping levels in place keeps every function, and collecting frees what no root needs
+    let mut seed = 31;
+    for _ in 0..100 {
+        let l = random_expr(4, &mut seed);
+        let r = random_expr(4, &mut seed);
+        let mut manager = Bdd::new(Vec::new());
+        let roots = [manager.build(&l), manager.build(&r)];
+        let counts = roots.map(|f| manager.count_models(f));
+        let roots = manager.sift(&roots);
+        assert_eq!(roots.iter().map(|f| manager.count_models(*f)).collect::<Vec<_>>(), counts, "{l} and {r}");
+        assert_eq!(manager.node_count(), manager.size(&roots) + 2, "{l} and {r}");
+        assert_eq!(roots, [manager.build(&l), manager.build(&r)], "{l} and {r}");
+    }
+    let mut manager = Bdd::new(Vec::new());
+    let f = manager.build(&pairs);
+    manager.build(&parse("a xor b xor c xor d"));
+    let size = manager.size(&[f]);
+    let f = manager.collect(&[f])[0];
+    assert_eq!(manager.node_count(), size + 2);
+    assert_eq!(f, manager.build(&pairs));
+}
+
+#[test]
+fn truth_table() {
+    use crate::truth_table::{Row, TableFormat, TruthTable};
+
+    let parse = |src: &str| Expr::parse(src.as_bytes()).unwrap();
+    let table = parse("a and not a").truth_table(true, &VariableOrder::default()).unwrap();
+    assert_eq!(table.columns(), ["a", "not a", "a and not a"]);
+    assert_eq!(table.render(TableFormat::Text), "a | not a | a and not a\n--+-------+------------\nF | T     | F\nT | F     | F\n");
+    assert_eq!(table.render(TableFormat::Markdown), "| a | not a | a and not a |\n| --- | --- | --- |\n| F | T | F |\n| T | F | F |\n");
+    assert_eq!(table.render(TableFormat::Csv), "a,not a,a and not a\nF,T,F\nT,F,F\n");
+    assert_eq!(parse("a -> a").truth_table(false, &VariableOrder::default()).unwrap().render(TableFormat::Html),
+        "<table>\n<thead>\n<tr><th>a</th><th>a -&gt; a</th></tr>\n</thead>\n<tbody>\n<tr><td>F</td><td>T</td></tr>\n<tr><td>T</td><td>T</td></tr>\n</tbody>\n</table>\n");
+
+    // Repeated subformulas get one column, listed after their operands
+    let table = parse("(a and b) or not (a and b)").truth_table(true, &VariableOrder::default()).unwrap();
+    assert_eq!(table.subformulas, [parse("a and b"), parse("not (a and b)")]);
+    assert!(table.rows().all(|row| row.result && row.subformulas[0] != row.subformulas[1]));
+
+    // Rows count up in binary over the variables, and the true ones are the models
+    let mut seed = 31;
+    for _ in 0..100 {
+        let expr = random_expr(3, &mut seed);
+        let table = expr.truth_table(true, &VariableOrder::default()).unwrap();
+        assert_eq!(table.num_rows(), 1 << table.variables.len());
+        for (idx, row) in table.rows().enumerate() {
+            let bits = row.assignment.iter().fold(0, |bits, value| bits * 2 + *value as usize);
+            assert_eq!(bits, idx);
+            assert_eq!(row.result, expr.is_valid_sat_proof(&row.assignment, &table.variables), "{expr}");
+            assert_eq!(row.subformulas.len(), table.subformulas.len());
+        }
+        let count = table.rows().filter(|row| row.result).count();
+        assert_eq!(count, table.count_true());
+        assert_eq!(count.to_string(), expr.count_models().to_string(), "{expr}");
+    }
+
+    // Only the column bits are stored, so wide tables fit, up to a limit the caller can change
+    let wide = (0..22).map(|idx| Expr::Var(format!("x{idx}"))).reduce(Expr::or).unwrap();
+    let table = wide.truth_table(false, &VariableOrder::default()).unwrap();
+    assert_eq!((table.num_rows(), table.count_true()), (1 << 22, (1 << 22) - 1));
+    assert_eq!(table.row(5), Row { assignment: (0..22).map(|idx| idx == 19 || idx == 21).collect(), subformulas: Vec::new(), result: true });
+    assert!(!table.result(0));
+    assert_eq!(TruthTable::new(&wide, false, &VariableOrder::default(), 21).unwrap_err(), "Truth table supports at most 21 variables, found 22");
+    let wider = (0..25).map(|idx| Expr::Var(format!("x{idx}"))).reduce(Expr::and).unwrap();
+    assert_eq!(wider.truth_table(false, &VariableOrder::default()).unwrap_err(), "Truth table supports at most 24 variables, found 25");
+}
+
+#[test]
+fn variable_order() {
+    use crate::expr::CnfEncoding;
+
+    let expr = Expr::parse(b"(c or a) and not (b -> a) xor d").unwrap();
+    let given = |names: &[&str]| VariableOrder::Given(names.iter().map(|name| name.to_string()).collect());
+    assert_eq!(expr.get_variables(), ["c", "a", "b", "d"]);
+    assert_eq!(expr.variables(&VariableOrder::Alphabetical).unwrap(), ["a", "b", "c", "d"]);
+    assert_eq!(expr.variables(&given(&["d", "e", "c", "b", "a"])).unwrap(), ["d", "e", "c", "b", "a"]);
+    assert_eq!(expr.variables(&given(&["a", "b", "c"])).unwrap_err(), "Variable 'd' is missing from the order");
+    assert_eq!(expr.variables(&given(&["a", "b", "c", "d", "a"])).unwrap_err(), "Variable 'a' occurs more than once in the order");
+    assert_eq!("alphabetical".parse::<VariableOrder>(), Ok(VariableOrder::Alphabetical));
+
+    // Truth tables, symbol tables and models all follow the order
+    for order in [VariableOrder::FirstOccurrence, VariableOrder::Alphabetical, given(&["b", "d", "a", "c"])] {
+        let vars = expr.variables(&order).unwrap();
+        assert_eq!(expr.truth_table(false, &order).unwrap().variables, vars);
+        for encoding in [CnfEncoding::Tseitin, CnfEncoding::PlaistedGreenbaum] {
+            let cnf = expr.encode_ordered(encoding, &order).unwrap();
+            assert_eq!(cnf.get_symbol_table()[..vars.len()], vars);
+            assert_eq!(cnf.models().names(), vars);
+
+            // Evidence lists the variables first, so it can be read against the order directly
+            let evidence = cnf.find_evidence().unwrap();
+            assert!(expr.is_valid_sat_proof(&evidence[..vars.len()].to_vec(), &vars));
+        }
+    }
+    assert_eq!(expr.tseitin(false).get_symbol_table()[..4], ["c", "a", "b", "d"]);
+}
+
+#[test]
+fn batch_eval() {
+    use crate::runtime::batch::BatchVM;
+
+    // Every bit agrees with the one-assignment VM, and the program survives being rerun
+    let mut seed = 37;
+    for _ in 0..200 {
+        let expr = random_expr(4, &mut seed);
+        let mut batch = BatchVM::new(expr.compile());
+        let vars = batch.variables().to_vec();
+        let rows = (0..100u64)
+            .map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (0..vars.len()).map(|var| seed >> (33 + var) & 1 == 1).collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let results = batch.eval_rows(&rows).unwrap();
+        assert_eq!(results.len(), rows.len());
+        for (row, result) in rows.iter().zip(results) {
+            let mut env = Env::new();
+            for (name, value) in vars.iter().zip(row) {
+                env.define(name.clone(), *value);
+            }
+            assert_eq!(VM::new(&env, expr.compile()).eval().unwrap(), result, "{expr}");
+        }
+    }
+
+    // Assignments are packed in binary order, and bits past the last assignment are clear
+    let expr = Expr::parse(b"a and not b or c").unwrap();
+    let mut batch = BatchVM::new(expr.compile());
+    let names = ["a", "b", "c"].map(String::from);
+    assert_eq!(batch.all_assignments(&names).unwrap(), [0b1011_1010]);
+    assert_eq!(batch.all_assignments(&names).unwrap(), [0b1011_1010]);
+    assert_eq!(batch.all_assignments(&names[..2]).unwrap_err(), "Undefined variable 'c'");
+    assert_eq!(batch.eval(&[0]).unwrap_err(), "Expected 3 lanes, one for each variable, found 1");
+
+    let wide = (0..22).map(|idx| Expr::Var(format!("x{idx}"))).reduce(Expr::xor).unwrap();
+    let names = wide.get_variables();
+    let words = BatchVM::new(wide.compile()).all_assignments(&names).unwrap();
+    assert_eq!(words.len(), 1 << 16);
+    assert_eq!(words.iter().map(|word| word.count_ones() as usize).sum::<usize>(), 1 << 21);
+    assert_eq!(words[0], 0x6996_9669_9669_6996);
+}
+
+#[test]
+fn compiled_program() {
+    let expr = Expr::parse(b"(a xor b) -> not c").unwrap();
+    let program = expr.program();
+    assert_eq!(program.instructions(), expr.compile());
+
+    // One program, every environment, and the instructions are still there afterwards
+    let mut env = Env::new();
+    for idx in 0..8 {
+        for (var, name) in ["a", "b", "c"].iter().enumerate() {
+            env.define(name.to_string(), idx >> var & 1 == 1);
+        }
+        let expected = VM::new(&env, expr.compile()).eval().unwrap();
+        assert_eq!(program.eval(&env), Ok(expected));
+        assert_eq!(program.eval(&env), Ok(expected));
+    }
+
+    // A VM can be rerun as well
+    let mut vm = VM::from_program(&env, &program);
+    assert_eq!(vm.eval(), vm.eval());
+
+    env.define("b".to_string(), false);
+    env.undefine(&"c".to_string());
+    assert_eq!(program.eval(&env), Err("Undefined variable 'c'".to_string()));
+}
+
+// Value of an expression straight from its definition, as a reference for the compiled programs
+fn reference_eval(expr: &Expr, values: &std::collections::HashMap<String, bool>) -> bool {
+    use crate::dag::Node;
+
+    let node = expr.node().map(|operand| reference_eval(operand, values));
+    match node {
+        Node::And(l, r) => l && r,
+        Node::Or(l, r) => l || r,
+        Node::Xor(l, r) => l != r,
+        Node::Iff(l, r) => l == r,
+        Node::Implies(l, r) => !l || r,
+        Node::Nand(l, r) => !(l && r),
+        Node::Nor(l, r) => !(l || r),
+        Node::Not(value) => !value,
+        Node::Card(values, comparison, bound, _) => comparison.holds(values.iter().filter(|value| **value).count(), bound),
+        Node::Pb(terms, comparison, bound, _) => {
+            comparison.holds(terms.iter().filter(|(_, value)| *value).map(|(weight, _)| weight).sum(), bound)
+        }
+        Node::Literal(value) => value,
+        Node::Var(name) => values[&name],
+    }
+}
+
+#[test]
+fn short_circuit() {
+    use std::collections::HashMap;
+    use crate::runtime::batch::BatchVM;
+
+    let parse = |src: &str| Expr::parse(src.as_bytes()).unwrap();
+    let eval = |src: &str, values: &[(&str, bool)]| {
+        let mut env = Env::new();
+        for (name, value) in values {
+            env.define(name.to_string(), *value);
+        }
+        parse(src).program().eval(&env)
+    };
+    assert_eq!(eval("a and b", &[("a", false)]), Ok(false));
+    assert_eq!(eval("a and b", &[("a", true)]), Err("Undefined variable 'b'".to_string()));
+    assert_eq!(eval("a or b", &[("a", true)]), Ok(true));
+    assert_eq!(eval("a -> b", &[("a", false)]), Ok(true));
+    assert_eq!(eval("a nand b", &[("a", false)]), Ok(true));
+    assert_eq!(eval("a nor b", &[("a", true)]), Ok(false));
+    assert_eq!(eval("F and a", &[]), Ok(false));
+    assert_eq!(eval("a and F", &[]), Ok(false));
+    assert_eq!(eval("a -> T", &[]), Ok(true));
+    assert_eq!(eval("x and y", &[("y", false)]), Ok(false));
+    assert_eq!(eval("x or t", &[("t", true)]), Ok(true));
+    assert_eq!(eval("x -> t", &[("t", true)]), Ok(true));
+    assert_eq!(eval("(x and y) or (z and y)", &[("y", false)]), Ok(false));
+    assert_eq!(eval("(x and y) or (z and y)", &[("y", true)]), Err("Undefined variable 'x'".to_string()));
+    assert_eq!(eval("x xor y", &[("y", true)]), Err("Undefined variable 'x'".to_string()));
+    assert_eq!(eval("x or not x", &[("b", true)]), Ok(true));
+    assert_eq!(eval("(x and b) or (not x and b)", &[("b", true)]), Ok(true));
+    assert_eq!(eval("(x and b) or (not x and b)", &[("b", false)]), Ok(false));
+
+    // The jumps skip shared subexpressions too, which are only recalled where their computation has run
+    // Only the program falls back on the other evaluators, the VM just runs the instructions
+    let vm = |src: &str, values: &[(&str, bool)]| {
+        let mut env = Env::new();
+        for (name, value) in values {
+            env.define(name.to_string(), *value);
+        }
+        VM::new(&env, parse(src).compile()).eval()
+    };
+    let shared = "(a and (b or c)) or (d and (b or c))";
+    assert_eq!(vm(shared, &[("a", false), ("d", false)]), Ok(false));
+    assert_eq!(vm(shared, &[("a", false), ("d", true), ("b", true)]), Ok(true));
+    assert_eq!(vm(shared, &[("a", true), ("b", false), ("c", false), ("d", true)]), Ok(false));
+    assert_eq!(vm("x or not x", &[]), Err("Undefined variable 'x'".to_string()));
+
+    // Constants fold away, and so do double negations
+    let compile = |src: &str| parse(src).compile();
+    let load = |name: &str| OpCode::Load(name.to_string());
+    assert_eq!(compile("not (not a)"), [load("a")]);
+    assert_eq!(compile("a and T"), [load("a")]);
+    assert_eq!(compile("T xor a"), [load("a"), OpCode::Not]);
+    assert_eq!(compile("not (T and F) or a"), [OpCode::T]);
+    assert_eq!(compile("(not a) -> b"), [load("a"), OpCode::JumpIfTrue(3), load("b")]);
+    assert_eq!(Expr::at_most(vec![Expr::Literal(true), Expr::Literal(false), Expr::Literal(true)], 1).compile(), [OpCode::F]);
+    assert_eq!(compile("(b or c) and (a or (b or c))"), [
+        load("b"), OpCode::JumpIfTrue(3), load("c"), OpCode::Store(0),
+        OpCode::JumpIfFalse(8), load("a"), OpCode::JumpIfTrue(8), OpCode::Recall(0),
+    ]);
+
+    // Whatever a partial environment decides agrees with every completion of it
+    let mut seed = 41;
+    for _ in 0..300 {
+        let expr = random_expr(4, &mut seed);
+        let program = expr.program();
+        let vars = expr.get_variables();
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let defined = (seed >> 33) as usize;
+
+        let mut env = Env::new();
+        for (var, name) in vars.iter().enumerate().filter(|(var, _)| defined >> (2 * var) & 1 == 1) {
+            env.define(name.clone(), defined >> (2 * var + 1) & 1 == 1);
+        }
+        let partial = program.eval(&env);
+        let mut batch = BatchVM::new(expr.compile());
+        for idx in 0..1usize << vars.len() {
+            let values = vars.iter().enumerate()
+                .map(|(var, name)| (name.clone(), env.get(name).unwrap_or(idx >> var & 1 == 1)))
+                .collect::<HashMap<_, _>>();
+            let expected = reference_eval(&expr, &values);
+            if let Ok(value) = partial {
+                assert_eq!(value, expected, "{expr}");
+            }
+
+            let lanes = batch.variables().iter().map(|name| if values[name] { !0 } else { 0 }).collect::<Vec<_>>();
+            assert_eq!(batch.eval(&lanes).unwrap(), if expected { !0 } else { 0 }, "{expr}");
+        }
+    }
+}
+
+#[test]
+fn partial_eval() {
+    use std::collections::HashMap;
+    use crate::bdd::Bdd;
+    use crate::runtime::partial::{self, Partial};
+
+    let parse = |src: &str| Expr::parse(src.as_bytes()).unwrap();
+    let env = |values: &[(&str, bool)]| {
+        let mut env = Env::new();
+        for (name, value) in values {
+            env.define(name.to_string(), *value);
+        }
+        env
+    };
+    let rule = parse("(a and b) or (c and not a)");
+    assert_eq!(rule.partial_eval(&env(&[("a", true)])), Partial::Residual(parse("b")));
+    assert_eq!(rule.partial_eval(&env(&[("a", false)])), Partial::Residual(parse("c")));
+    assert_eq!(rule.partial_eval(&env(&[("a", true), ("b", false)])), Partial::Value(false));
+    assert_eq!(rule.partial_eval(&env(&[])), Partial::Residual(rule.clone()));
+
+    // Decided even when the rules alone don't see it, and irrelevant variables are dropped
+    assert_eq!(parse("(a and b) or (a and not b) or c").partial_eval(&env(&[("c", false)])), Partial::Residual(parse("a")));
+    assert_eq!(parse("(a xor b) xor b").partial_eval(&env(&[("a", true)])), Partial::Value(true));
+
+    // Past the node limit the solver still decides constant residuals, the rest stay as simplified
+    let eval_within = |src: &str, values: &[(&str, bool)]| partial::eval_within(&parse(src), &env(values), 4);
+    assert_eq!(eval_within("(a xor b) xor b", &[("a", true)]), Partial::Value(true));
+    assert_eq!(eval_within("(a xor b) xor (b xor a)", &[]), Partial::Value(false));
+    let Partial::Residual(residual) = eval_within("(a and b) or (a and not b) or c", &[("c", false)]) else { panic!() };
+    assert!(residual.is_equivalent(&parse("a"), Backend::Sat));
+
+    // A manager that hit the limit is left usable
+    let mut manager = Bdd::new(Vec::new());
+    let hard = parse("(a and b) or (c and d) or (e and f)");
+    assert_eq!(manager.build_within(&hard, 4), None);
+    assert!(manager.order().is_empty());
+    assert_eq!(manager.node_count(), 2);
+    let f = manager.build_within(&hard, partial::NODE_LIMIT).unwrap();
+    assert_eq!(f, manager.build(&hard));
+    assert_eq!(manager.count_models(f).to_string(), "37");
+
+    // The limit counts the nodes a call adds, exactly, whatever the table already holds
+    let added = manager.node_count() - 2;
+    let mut manager = Bdd::new(Vec::new());
+    assert_eq!(manager.build_within(&hard, added - 1), None);
+    assert!(manager.build_within(&hard, added).is_some());
+    let other = parse("(a and b) xor g");
+    let (nodes, order) = (manager.node_count(), manager.order().to_vec());
+    assert_eq!(manager.build_within(&other, 1), None);
+    assert_eq!((manager.node_count(), manager.order()), (nodes, &order[..]));
+    let g = manager.build_within(&other, added).unwrap();
+    assert_eq!(manager.count_models(g).to_string(), "64");
+
+    // Residuals whose BDD grows exponentially with the first occurrence order stay bounded
+    let xs = (0..20).map(|i| Expr::Var(format!("x{i}"))).collect::<Vec<_>>();
+    let ys = (0..20).map(|i| Expr::Var(format!("y{i}"))).collect::<Vec<_>>();
+    let any = xs.iter().chain(&ys).cloned().reduce(Expr::or).unwrap();
+    let pairs = xs.iter().zip(&ys).map(|(x, y)| Expr::and(x.clone(), y.clone())).reduce(Expr::or).unwrap();
+    assert!(matches!(Expr::and(any, pairs).partial_eval(&env(&[])), Partial::Residual(_)));
+
+    // The residual agrees with the expression under every completion of the environment
+    let mut seed = 59;
+    for _ in 0..300 {
+        let expr = random_expr(4, &mut seed);
+        let vars = expr.get_variables();
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let defined = (seed >> 33) as usize;
+
+        let mut env = Env::new();
+        for (var, name) in vars.iter().enumerate().filter(|(var, _)| defined >> (2 * var) & 1 == 1) {
+            env.define(name.clone(), defined >> (2 * var + 1) & 1 == 1);
+        }
+        let partial = expr.partial_eval(&env);
+        if let Partial::Residual(residual) = &partial {
+            assert!(residual.get_variables().iter().all(|name| env.get(name).is_none()), "{expr}");
+        }
+        for idx in 0..1usize << vars.len() {
+            let values = vars.iter().enumerate()
+                .map(|(var, name)| (name.clone(), env.get(name).unwrap_or(idx >> var & 1 == 1)))
+                .collect::<HashMap<_, _>>();
+            let expected = reference_eval(&expr, &values);
+            match &partial {
+                Partial::Value(value) => assert_eq!(*value, expected, "{expr}"),
+                Partial::Residual(residual) => assert_eq!(reference_eval(residual, &values), expected, "{expr}"),
+            }
+        }
+    }
+}
+
+#[test]
+fn three_valued() {
+    use std::collections::HashMap;
+    use crate::runtime::ternary::{Logic, Truth};
+
+    let parse = |src: &str| Expr::parse(src.as_bytes()).unwrap();
+    let eval = |src: &str, values: &[(&str, bool)], logic: Logic| {
+        let mut env = Env::new();
+        for (name, value) in values {
+            env.define(name.to_string(), *value);
+        }
+        let program = if logic == Logic::Lukasiewicz { parse(src).program_eager() } else { parse(src).program() };
+        program.eval_ternary(&env, logic)
+    };
+    let kleene = |src: &str, values: &[(&str, bool)]| eval(src, values, Logic::Kleene);
+    assert_eq!(kleene("a and b", &[("a", false)]), Truth::False);
+    assert_eq!(kleene("a and b", &[("a", true)]), Truth::Unknown);
+    assert_eq!(kleene("b and a", &[("a", false)]), Truth::False);
+    assert_eq!(kleene("a or b", &[("b", true)]), Truth::True);
+    assert_eq!(kleene("a -> b", &[]), Truth::Unknown);
+    assert_eq!(kleene("a -> a", &[]), Truth::Unknown);
+    assert_eq!(kleene("a xor b", &[("a", true)]), Truth::Unknown);
+    assert_eq!(kleene("not (a nand b)", &[("b", false)]), Truth::False);
+
+    // Lukasiewicz only differs where both sides of an implication are unknown
+    let lukasiewicz = |src: &str, values: &[(&str, bool)]| eval(src, values, Logic::Lukasiewicz);
+    assert_eq!(lukasiewicz("a -> a", &[]), Truth::True);
+    assert_eq!(lukasiewicz("a <-> b", &[]), Truth::True);
+    assert_eq!(lukasiewicz("a <-> not a", &[]), Truth::True);
+    assert_eq!(lukasiewicz("a xor b", &[]), Truth::False);
+    assert_eq!(lukasiewicz("a -> b", &[("b", false)]), Truth::Unknown);
+    assert_eq!(lukasiewicz("a or not a", &[]), Truth::Unknown);
+
+    // Counters are known when every value of the unknown operands agrees
+    let mut env = Env::new();
+    env.define("a".to_string(), true);
+    let vars = |names: &[&str]| names.iter().map(|name| Expr::Var(name.to_string())).collect::<Vec<_>>();
+    assert_eq!(Expr::at_least(vars(&["a", "b"]), 1).program().eval_ternary(&env, Logic::Kleene), Truth::True);
+    assert_eq!(Expr::at_most(vars(&["a", "b"]), 1).program().eval_ternary(&env, Logic::Kleene), Truth::Unknown);
+    assert_eq!(Expr::at_most(vars(&["a", "b", "c"]), 0).program().eval_ternary(&env, Logic::Kleene), Truth::False);
+    assert_eq!("lukasiewicz".parse::<Logic>(), Ok(Logic::Lukasiewicz));
+    assert!("fuzzy".parse::<Logic>().is_err());
+
+    // A known Kleene value is the value under every completion of the environment,
+    // and both logics are classical logic when every variable is defined
+    let mut seed = 73;
+    for _ in 0..300 {
+        let expr = random_expr(4, &mut seed);
+        let vars = expr.get_variables();
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let defined = (seed >> 33) as usize;
+
+        let mut env = Env::new();
+        for (var, name) in vars.iter().enumerate().filter(|(var, _)| defined >> (2 * var) & 1 == 1) {
+            env.define(name.clone(), defined >> (2 * var + 1) & 1 == 1);
+        }
+        let kleene = expr.program().eval_ternary(&env, Logic::Kleene);
+        let lukasiewicz = expr.program_eager().eval_ternary(&env, Logic::Lukasiewicz);
+        let results = (0..1usize << vars.len())
+            .map(|idx| {
+                let values = vars.iter().enumerate()
+                    .map(|(var, name)| (name.clone(), env.get(name).unwrap_or(idx >> var & 1 == 1)))
+                    .collect::<HashMap<_, _>>();
+                reference_eval(&expr, &values)
+            })
+            .collect::<Vec<_>>();
+        if let Some(value) = kleene.known() {
+            assert!(results.iter().all(|result| *result == value), "{expr}");
+        }
+        if vars.iter().all(|name| env.get(name).is_some()) {
+            assert_eq!(kleene, Truth::from(results[0]), "{expr}");
+            assert_eq!(lukasiewicz, Truth::from(results[0]), "{expr}");
+        }
+    }
+}
+
+
+
@@ -0,0 +1,215 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::expr::{Expr, VariableOrder};
+use crate::runtime::batch::{BatchVM, MAX_BATCH_VARIABLES};
+
+// A table has a row for each of the 2^n assignments, and a bit for each row in each column,
+// so tables are limited to this many variables unless the caller asks for more
+pub const DEFAULT_MAX_VARIABLES: usize = 24;
+
+// Ways of rendering a truth table as text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TableFormat {
+    // Columns padded to line up, for terminals
+    #[default]
+    Text,
+    Markdown,
+    Csv,
+    Html,
+}
+
+impl FromStr for TableFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<TableFormat, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "text" => Ok(TableFormat::Text),
+            "markdown" | "md" => Ok(TableFormat::Markdown),
+            "csv" => Ok(TableFormat::Csv),
+            "html" => Ok(TableFormat::Html),
+            _ => Err(format!("Unknown table format '{}', expected 'text', 'markdown', 'csv' or 'html'", name)),
+        }
+    }
+}
+
+// Values of every column under one assignment
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Row {
+    // Value of each variable, in the table's variable order
+    pub assignment: Vec<bool>,
+    // Value of each of the table's subformulas
+    pub subformulas: Vec<bool>,
+    pub result: bool,
+}
+
+impl Row {
+    // Every value in column order, the variables then the subformulas then the result
+    pub fn values(&self) -> Vec<bool> {
+        let mut values = self.assignment.clone();
+        values.extend(&self.subformulas);
+        values.push(self.result);
+        values
+    }
+}
+
+// Value of an expression under every assignment of its variables
+// Rows count up in binary, with the first variable the most significant and false before true
+// Only the bits of the subformula and result columns are stored, rows are put together when asked for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TruthTable {
+    pub expr: Expr,
+    pub variables: Vec<String>,
+    // Intermediate columns, each operand before the subformulas using it
+    pub subformulas: Vec<Expr>,
+    // Bit i of word w of each column is its value in row 64w + i, the last column being the result
+    words: Vec<Vec<u64>>,
+}
+
+impl TruthTable {
+    // Table over the expression's variables in the given order, with a column for each distinct proper subformula if asked for
+    // Fails if there are more than max_variables variables, which can be at most MAX_BATCH_VARIABLES
+    pub fn new(expr: &Expr, subformulas: bool, order: &VariableOrder, max_variables: usize) -> Result<TruthTable, String> {
+        let variables = expr.variables(order)?;
+        let max_variables = max_variables.min(MAX_BATCH_VARIABLES);
+        if variables.len() > max_variables {
+            return Err(format!("Truth table supports at most {} variables, found {}", max_variables, variables.len()));
+        }
+
+        let mut columns = Vec::new();
+        if subformulas {
+            for operand in expr.node().operands() {
+                collect_subformulas(operand, &mut columns);
+            }
+        }
+
+        // Every column is evaluated 64 rows at a time
+        let words = columns.iter().chain([expr])
+            .map(|column| BatchVM::new(column.compile()).all_assignments(&variables))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(TruthTable { expr: expr.clone(), variables, subformulas: columns, words })
+    }
+
+    pub fn num_rows(&self) -> usize {
+        1 << self.variables.len()
+    }
+
+    // Value of the expression in row idx
+    pub fn result(&self, idx: usize) -> bool {
+        self.bit(self.words.len() - 1, idx)
+    }
+
+    fn bit(&self, column: usize, idx: usize) -> bool {
+        self.words[column][idx / 64] >> (idx % 64) & 1 == 1
+    }
+
+    // Row idx, with the variables read off the binary digits of idx
+    pub fn row(&self, idx: usize) -> Row {
+        let n = self.variables.len();
+        Row {
+            assignment: (0..n).map(|var| idx >> (n - 1 - var) & 1 == 1).collect(),
+            subformulas: (0..self.subformulas.len()).map(|column| self.bit(column, idx)).collect(),
+            result: self.result(idx),
+        }
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = Row> + '_ {
+        (0..self.num_rows()).map(|idx| self.row(idx))
+    }
+
+    // Number of rows where the expression is true
+    pub fn count_true(&self) -> usize {
+        self.words.last().unwrap().iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    // Header of each column, the variables then the subformulas then the expression
+    pub fn columns(&self) -> Vec<String> {
+        self.variables.iter().cloned()
+            .chain(self.subformulas.iter().map(Expr::to_string))
+            .chain([self.expr.to_string()])
+            .collect()
+    }
+
+    pub fn render(&self, format: TableFormat) -> String {
+        let columns = self.columns();
+        let rows = self.rows()
+            .map(|row| row.values().into_iter().map(|value| if value { "T" } else { "F" }).collect::<Vec<_>>());
+
+        let mut out = String::new();
+        match format {
+            TableFormat::Text => {
+                let widths = columns.iter().map(|column| column.chars().count()).collect::<Vec<_>>();
+                let line = |cells: Vec<String>| cells.join(" | ").trim_end().to_string() + "\n";
+                out += &line(columns.clone());
+                out += &widths.iter().map(|width| "-".repeat(*width)).collect::<Vec<_>>().join("-+-");
+                out += "\n";
+                for row in rows {
+                    out += &line(row.iter().zip(&widths).map(|(value, width)| format!("{:width$}", value, width = width)).collect());
+                }
+            }
+            TableFormat::Markdown => {
+                out += &format!("| {} |\n", columns.join(" | "));
+                out += &format!("|{}|\n", vec![" --- "; columns.len()].join("|"));
+                for row in rows {
+                    out += &format!("| {} |\n", row.join(" | "));
+                }
+            }
+            TableFormat::Csv => {
+                out += &columns.iter().map(|column| csv_field(column)).collect::<Vec<_>>().join(",");
+                out += "\n";
+                for row in rows {
+                    out += &row.join(",");
+                    out += "\n";
+                }
+            }
+            TableFormat::Html => {
+                let cells = |tag: &str, cells: Vec<String>| {
+                    let cells = cells.iter().map(|cell| format!("<{tag}>{cell}</{tag}>")).collect::<String>();
+                    format!("<tr>{}</tr>\n", cells)
+                };
+                out += "<table>\n<thead>\n";
+                out += &cells("th", columns.iter().map(|column| html_escape(column)).collect());
+                out += "</thead>\n<tbody>\n";
+                for row in rows {
+                    out += &cells("td", row.into_iter().map(String::from).collect());
+                }
+                out += "</tbody>\n</table>\n";
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for TruthTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(TableFormat::Text))
+    }
+}
+
+// Adds the subformulas of expr that aren't variables, constants or already there, operands first
+fn collect_subformulas(expr: &Expr, columns: &mut Vec<Expr>) {
+    let node = expr.node();
+    if node.operands().is_empty() {
+        return;
+    }
+    for operand in node.operands() {
+        collect_subformulas(operand, columns);
+    }
+    if !columns.contains(expr) {
+        columns.push(expr.clone());
+    }
+}
+
+// Quotes a field holding a comma, quote or line break, doubling its quotes
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}